@@ -0,0 +1,176 @@
+//! Rendering of a test suite as Allure results, the files of an `allure-results` directory that the
+//! Allure report and its dashboards are generated from.
+
+use crate::model::{epoch_millis as millis, Status, TestCase, TestSuite};
+use serde_json::{json, Value};
+
+/// The `n`th id of a run, shaped like a UUID. The ids of a run share the `seed`, which keeps them
+/// apart from the ones of the other runs written to the same directory.
+fn uuid(seed: u128, n: usize) -> String {
+    let id = format!("{:032x}", seed.wrapping_shl(32).wrapping_add(n as u128));
+    format!(
+        "{}-{}-{}-{}-{}",
+        &id[..8],
+        &id[8..12],
+        &id[12..16],
+        &id[16..20],
+        &id[20..]
+    )
+}
+
+fn status(testcase: &TestCase) -> &'static str {
+    match testcase.status() {
+        Status::Passed => "passed",
+        Status::Failed => "failed",
+        Status::Errored => "broken",
+        Status::Skipped => "skipped",
+    }
+}
+
+/// The files of the results, each with its name and contents, to be written in one directory.
+#[derive(Default)]
+struct Results {
+    files: Vec<(String, String)>,
+}
+
+impl Results {
+    /// Add a text attachment and return its reference, for the `attachments` of a result.
+    fn attach(&mut self, id: String, name: &str, text: &str) -> Value {
+        let source = format!("{}-attachment.txt", id);
+        self.files.push((source.clone(), text.to_string()));
+        json!({ "name": name, "source": source, "type": "text/plain" })
+    }
+
+    fn push(&mut self, name: String, value: &Value) {
+        self.files.push((name, value.to_string()));
+    }
+}
+
+/**
+Render the test suite as Allure results: a `-result.json` file per test case, a `-container.json`
+file for the suite, and a `-attachment.txt` file per captured output.
+
+A failed, errored or skipped test case has its message in its `statusDetails`, and the output that
+was captured for it attached. The output of the suite, that of the passing scripts, is attached to
+an after fixture of the container, and so shown with every test case of the suite.
+*/
+pub fn render(testsuite: &TestSuite, seed: u128) -> Vec<(String, String)> {
+    let mut results = Results::default();
+    let mut ids = (0..).map(|n| uuid(seed, n));
+    let suite_start = millis(&testsuite.timestamp);
+    let suite_stop = suite_start + (testsuite.time * 1000.0) as i64;
+
+    let mut children = vec![];
+    for testcase in &testsuite.testcases {
+        let id = ids.next().unwrap();
+        let start = testcase.timestamp.as_deref().map_or(suite_start, millis);
+        let full_name = format!("{}.{}", testcase.classname, testcase.name);
+        let mut result = json!({
+            "uuid": id,
+            "historyId": full_name,
+            "fullName": full_name,
+            "name": testcase.name,
+            "status": status(testcase),
+            "stage": "finished",
+            "start": start,
+            "stop": start + (testcase.time * 1000.0) as i64,
+            "labels": [
+                { "name": "suite", "value": testsuite.name },
+                { "name": "testClass", "value": testcase.classname },
+                { "name": "host", "value": testsuite.hostname },
+                { "name": "framework", "value": "shunit" },
+            ],
+            "attachments": [],
+        });
+        if let Some(problem) = testcase.error.as_ref().or(testcase.failure.as_ref()) {
+            result["statusDetails"] = json!({ "message": problem.message });
+            if !problem.body.is_empty() {
+                let attachment = results.attach(ids.next().unwrap(), "output", &problem.body);
+                result["attachments"] = json!([attachment]);
+            }
+        } else if let Some(skipped) = &testcase.skipped {
+            result["statusDetails"] = json!({ "message": skipped.message });
+        }
+        results.push(format!("{}-result.json", id), &result);
+        children.push(id);
+    }
+
+    let mut attachments = vec![];
+    for (name, output) in [
+        ("std out", &testsuite.system_out),
+        ("std err", &testsuite.system_err),
+    ] {
+        if !output.is_empty() {
+            attachments.push(results.attach(ids.next().unwrap(), name, output));
+        }
+    }
+    let id = ids.next().unwrap();
+    let mut container = json!({
+        "uuid": id,
+        "name": testsuite.name,
+        "children": children,
+        "start": suite_start,
+        "stop": suite_stop,
+    });
+    if !attachments.is_empty() {
+        container["afters"] = json!([{
+            "name": "suite output",
+            "status": "passed",
+            "stage": "finished",
+            "start": suite_start,
+            "stop": suite_stop,
+            "attachments": attachments,
+        }]);
+    }
+    results.push(format!("{}-container.json", id), &container);
+    results.files
+}
+
+#[cfg(test)]
+mod test {
+    use super::render;
+    use crate::model::TestSuite;
+    use serde_json::Value;
+
+    #[test]
+    fn test_render() {
+        let testsuite = TestSuite::from_file("tests/fixtures/compact.xml").unwrap();
+        let files = render(&testsuite, 1);
+        let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "00000000-0000-0000-0000-000100000000-result.json",
+                "00000000-0000-0000-0000-000100000002-attachment.txt",
+                "00000000-0000-0000-0000-000100000001-result.json",
+                "00000000-0000-0000-0000-000100000003-attachment.txt",
+                "00000000-0000-0000-0000-000100000004-attachment.txt",
+                "00000000-0000-0000-0000-000100000005-container.json",
+            ]
+        );
+        let json = |index: usize| serde_json::from_str::<Value>(&files[index].1).unwrap();
+
+        let passed = json(0);
+        assert_eq!(passed["name"], "./test/im_ok.sh");
+        assert_eq!(passed["status"], "passed");
+        assert_eq!(
+            passed["stop"].as_i64(),
+            Some(passed["start"].as_i64().unwrap() + 500)
+        );
+
+        let failed = json(2);
+        assert_eq!(failed["status"], "failed");
+        assert_eq!(failed["statusDetails"]["message"], "Non-zero exit-code: 2");
+        assert_eq!(
+            failed["attachments"][0]["source"],
+            "00000000-0000-0000-0000-000100000002-attachment.txt"
+        );
+        assert_eq!(files[1].1, "ls: cannot access");
+
+        let container = json(5);
+        assert_eq!(container["children"][1], failed["uuid"]);
+        assert_eq!(container["afters"][0]["attachments"][0]["name"], "std out");
+        assert_eq!(container["afters"][0]["attachments"][1]["name"], "std err");
+        assert_eq!(files[3].1, "OK!");
+    }
+}
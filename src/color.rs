@@ -0,0 +1,64 @@
+//! Deciding whether terminal output is colored, and coloring it.
+
+use std::env;
+
+/// When to color the output shunit writes to the terminal.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorChoice {
+    /// Color output to a terminal, unless `NO_COLOR` is set.
+    Auto,
+    /// Always color, even when the output is redirected or `NO_COLOR` is set.
+    Always,
+    /// Never color.
+    Never,
+}
+
+impl ColorChoice {
+    pub const VARIANTS: [&'static str; 3] = ["auto", "always", "never"];
+
+    /// Whether to color output to a stream, given whether that stream is a terminal.
+    pub fn enabled(&self, terminal: bool) -> bool {
+        let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        self.decide(terminal, no_color)
+    }
+
+    fn decide(&self, terminal: bool, no_color: bool) -> bool {
+        match self {
+            ColorChoice::Auto => terminal && !no_color,
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+impl std::str::FromStr for ColorChoice {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(anyhow!("Unknown color choice: {}", s)),
+        }
+    }
+}
+
+/// Color `text` red, for the lines a script writes to std err.
+pub fn red(text: &str) -> String {
+    format!("\u{1b}[31m{}\u{1b}[0m", text)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::color::ColorChoice;
+
+    #[test]
+    fn test_color_choice() {
+        assert!(ColorChoice::Auto.decide(true, false));
+        assert!(!ColorChoice::Auto.decide(true, true));
+        assert!(!ColorChoice::Auto.decide(false, false));
+        assert!(ColorChoice::Always.decide(false, true));
+        assert!(!ColorChoice::Never.decide(true, false));
+    }
+}
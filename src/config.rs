@@ -0,0 +1,529 @@
+//! The `shunit.toml` configuration file, whose keys mirror the command line options, and the
+//! `SHUNIT_*` environment variables, which set the same keys.
+
+use crate::{parse_name_pattern, parse_signal, Opt};
+use serde::Deserialize;
+use shunit::parse_seconds;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::{env, fs, str::FromStr};
+use structopt::clap::ArgMatches;
+use toml::Value;
+
+/// The configuration file read from the current directory, or from the one above it up to the root
+/// of the git repository, unless `--config` names another one.
+pub const CONFIG_FILE: &str = "shunit.toml";
+
+/// The prefix of the environment variables that set options, like `SHUNIT_JOBS` for `--jobs`.
+pub const ENV_PREFIX: &str = "SHUNIT_";
+
+/// The environment variable that names the configuration file, like `--config`.
+const CONFIG_VAR: &str = "SHUNIT_CONFIG";
+
+/**
+The configuration file that applies in `dir`: the `shunit.toml` of `dir`, or of the nearest
+directory above it, up to the root of the git repository that `dir` is in. Outside of a repository,
+only the one of `dir` applies.
+*/
+fn find(dir: &Path) -> Option<PathBuf> {
+    let root = dir
+        .ancestors()
+        .find(|ancestor| ancestor.join(".git").exists())
+        .unwrap_or(dir);
+    dir.ancestors()
+        .take_while(|ancestor| ancestor.starts_with(root))
+        .map(|ancestor| ancestor.join(CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
+/// Defaults for the command line options, read from a configuration file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The `SHUNIT_*` environment variables that are not options, which were left out.
+    #[serde(skip)]
+    pub unknown_vars: Vec<String>,
+    quiet: Option<bool>,
+    silent: Option<bool>,
+    verbose: Option<usize>,
+    timestamp: Option<String>,
+    color: Option<String>,
+    no_testcase_timestamps: Option<bool>,
+    fallback_shebang: Option<bool>,
+    stdin_inherit: Option<bool>,
+    no_progress: Option<bool>,
+    group_output: Option<bool>,
+    combine_output: Option<bool>,
+    parse_tap: Option<bool>,
+    output: Option<OneOrMany>,
+    format: Option<String>,
+    summary_json: Option<String>,
+    events: Option<String>,
+    html_report: Option<String>,
+    summary_md: Option<String>,
+    allure_dir: Option<String>,
+    github_annotations: Option<bool>,
+    suite_name: Option<String>,
+    property: Option<BTreeMap<String, String>>,
+    env: Option<BTreeMap<String, String>>,
+    env_file: Option<Vec<String>>,
+    env_clear: Option<bool>,
+    clean_env: Option<bool>,
+    pass_env: Option<Vec<String>>,
+    allow_unset: Option<bool>,
+    matrix: Option<BTreeMap<String, Vec<String>>>,
+    repeat: Option<u32>,
+    classname_strategy: Option<String>,
+    name_strategy: Option<String>,
+    classname_from_package_file: Option<bool>,
+    category: Option<Vec<String>>,
+    expect_exit: Option<Vec<i32>>,
+    expect_failure: Option<bool>,
+    warn_only: Option<bool>,
+    no_fail: Option<bool>,
+    abort_on_signal: Option<bool>,
+    abort_signals: Option<Vec<String>>,
+    skip_non_executable: Option<bool>,
+    keep_tmp: Option<bool>,
+    setup: Option<String>,
+    teardown: Option<String>,
+    before_each: Option<String>,
+    after_each: Option<String>,
+    hooks_as_cases: Option<bool>,
+    filter: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+    skip: Option<Vec<String>>,
+    xfail: Option<Vec<String>>,
+    merge: Option<Vec<String>>,
+    merge_properties: Option<String>,
+    sort: Option<String>,
+    allow_duplicates: Option<bool>,
+    validate: Option<bool>,
+    append: Option<bool>,
+    compress: Option<bool>,
+    line_pattern: Option<String>,
+    max_output: Option<u64>,
+    retry: Option<u32>,
+    jobs: Option<usize>,
+    bail_after: Option<u32>,
+    max_failures: Option<u32>,
+    fail_fast: Option<bool>,
+    retry_delay: Option<u64>,
+    retry_backoff: Option<f64>,
+    resource_stats: Option<bool>,
+    queue_time: Option<bool>,
+    prefix: Option<bool>,
+    timeout: Option<f64>,
+    idle_timeout: Option<f64>,
+    min_time: Option<f64>,
+    max_total_time: Option<f64>,
+    timeout_signal: Option<String>,
+    kill_grace: Option<f64>,
+    redact: Option<Vec<String>>,
+    redact_defaults: Option<bool>,
+    strip_ansi: Option<bool>,
+    manifest: Option<String>,
+    param_file: Option<String>,
+    pattern: Option<String>,
+    stdin: Option<bool>,
+    null: Option<bool>,
+    scripts: Option<Vec<String>>,
+    script_args: Option<Vec<String>>,
+}
+
+/// A value that may be given as a single string or as a list of strings.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            OneOrMany::One(value) => vec![value],
+            OneOrMany::Many(values) => values,
+        }
+    }
+}
+
+/// Parse a value from the configuration file with the same parser as the command line.
+fn parse<T>(key: &str, value: &str) -> anyhow::Result<T>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|err| anyhow!("Invalid value for {} in configuration: {}", key, err))
+}
+
+/// Parse a list of regexes from the configuration file.
+fn regexes(patterns: &[String]) -> anyhow::Result<Vec<regex::Regex>> {
+    patterns
+        .iter()
+        .map(|pattern| regex::Regex::new(pattern).map_err(anyhow::Error::from))
+        .collect()
+}
+
+/// Whether `table` is a valid configuration, and why not.
+fn check(table: &toml::Table) -> Result<(), String> {
+    Value::Table(table.clone())
+        .try_into::<Config>()
+        .map(drop)
+        .map_err(|err| err.message().to_string())
+}
+
+/**
+The key and the value of the option set by a `SHUNIT_*` environment variable, or `None` if there
+is no such option, as other tools use the prefix too.
+
+The value is read as TOML if it fits the option, like `4`, `true` or `["a", "b"]`, then as a flag
+if it is `1`, `yes` or `on`, or `0`, `no` or `off`, and else as a string. An option that takes
+several values also takes a single one.
+*/
+fn env_option(name: &str, value: &str) -> anyhow::Result<Option<(String, Value)>> {
+    let key = name[ENV_PREFIX.len()..].to_ascii_lowercase();
+    let literal = Value::deserialize(toml::de::ValueDeserializer::new(value)).ok();
+    let flag = match value.to_ascii_lowercase().as_str() {
+        "1" | "yes" | "on" => Some(Value::Boolean(true)),
+        "0" | "no" | "off" => Some(Value::Boolean(false)),
+        _ => None,
+    };
+    let string = Value::String(value.to_string());
+    let candidates = [
+        literal.clone(),
+        flag,
+        Some(string.clone()),
+        literal.map(|literal| Value::Array(vec![literal])),
+        Some(Value::Array(vec![string.clone()])),
+    ];
+    let fits = |value: &Value| check(&toml::Table::from_iter([(key.clone(), value.clone())]));
+    match candidates
+        .into_iter()
+        .flatten()
+        .find(|value| fits(value).is_ok())
+    {
+        Some(value) => Ok(Some((key, value))),
+        None => match fits(&string).unwrap_err() {
+            err if err.starts_with("unknown field") => Ok(None),
+            err => bail!("Invalid {}: {}", name, err),
+        },
+    }
+}
+
+impl Config {
+    /**
+    Read the configuration file, with the options of the `SHUNIT_*` environment variables on top.
+
+    - `path` the file given with `--config`, or else `SHUNIT_CONFIG`, which must exist. Without
+      either, the `shunit.toml` of the current directory, or of the nearest one above it in the
+      same git repository, is read if there is one, and an empty configuration is used if not.
+    - `vars` the environment variables. The ones shunit sets for the scripts, like
+      `SHUNIT_TMPDIR`, are not options, so a script can run shunit in turn. Other `SHUNIT_*`
+      variables that aren't options are left out, and listed in the `unknown_vars`.
+    */
+    pub fn load(
+        path: Option<&str>,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> anyhow::Result<Config> {
+        let vars: BTreeMap<String, String> = vars
+            .into_iter()
+            .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+            .collect();
+        let path = match path.or(vars.get(CONFIG_VAR).map(String::as_str)) {
+            Some(path) => Some(PathBuf::from(path)),
+            None => env::current_dir().ok().as_deref().and_then(find),
+        };
+        let mut table = match path {
+            Some(path) => {
+                let toml = fs::read_to_string(&path)
+                    .map_err(|err| anyhow!("Failed to read {}: {}", path.display(), err))?;
+                let parse_error = |err| anyhow!("Failed to parse {}: {}", path.display(), err);
+                toml::from_str::<Config>(&toml).map_err(parse_error)?;
+                toml::from_str(&toml).map_err(parse_error)?
+            }
+            None => toml::Table::new(),
+        };
+        let is_option =
+            |name: &str| name != CONFIG_VAR && !shunit::SCRIPT_VARIABLES.contains(&name);
+        let mut unknown_vars = vec![];
+        for (name, value) in vars.iter().filter(|(name, _)| is_option(name)) {
+            match env_option(name, value)? {
+                Some((key, value)) => {
+                    table.insert(key, value);
+                }
+                None => unknown_vars.push(name.clone()),
+            }
+        }
+        Ok(Config {
+            unknown_vars,
+            ..Value::Table(table).try_into()?
+        })
+    }
+
+    /**
+    Apply the configuration to the options, except for the ones given on the command line.
+
+    - `opt` the options parsed from `matches`.
+    - `matches` the parsed command line, which tells which options were given explicitly.
+    */
+    pub fn apply(self, opt: &mut Opt, matches: &ArgMatches) -> anyhow::Result<()> {
+        // structopt names the arguments after the fields, in kebab-case.
+        let unset = |field: &str| matches.occurrences_of(field.replace('_', "-")) == 0;
+
+        macro_rules! set {
+            ($field:ident) => {
+                set!($field, |value| Ok(value))
+            };
+            ($field:ident, $convert:expr) => {
+                if let Some(value) = self.$field {
+                    if unset(stringify!($field)) {
+                        let convert: fn(_) -> anyhow::Result<_> = $convert;
+                        opt.$field = convert(value)?;
+                    }
+                }
+            };
+        }
+
+        set!(quiet);
+        set!(silent);
+        set!(verbose);
+        if let Some(ts) = self.timestamp {
+            if unset("ts") {
+                opt.ts = Some(parse("timestamp", &ts)?);
+            }
+        }
+        set!(color, |choice: String| parse("color", &choice));
+        set!(no_testcase_timestamps);
+        set!(fallback_shebang);
+        set!(stdin_inherit);
+        set!(no_progress);
+        set!(group_output);
+        set!(combine_output);
+        set!(parse_tap);
+        set!(output, |output: OneOrMany| output
+            .into_vec()
+            .iter()
+            .map(|target| parse("output", target))
+            .collect());
+        set!(format, |format: String| parse("format", &format));
+        set!(summary_json, |path| Ok(Some(path)));
+        set!(events, |target| Ok(Some(target)));
+        set!(html_report, |path| Ok(Some(path)));
+        set!(summary_md, |path| Ok(Some(path)));
+        set!(allure_dir, |dir| Ok(Some(dir)));
+        set!(github_annotations);
+        set!(suite_name, |name| Ok(Some(name)));
+        set!(property, |properties: BTreeMap<String, String>| Ok(
+            properties.into_iter().collect()
+        ));
+        set!(env, |env: BTreeMap<String, String>| Ok(env
+            .into_iter()
+            .collect()));
+        set!(env_file);
+        set!(env_clear);
+        set!(clean_env);
+        set!(pass_env);
+        set!(allow_unset);
+        set!(matrix, |matrix: BTreeMap<String, Vec<String>>| Ok(matrix
+            .into_iter()
+            .collect()));
+        set!(repeat);
+        set!(classname_strategy, |strategy: String| parse(
+            "classname_strategy",
+            &strategy
+        ));
+        set!(name_strategy, |strategy: String| parse(
+            "name_strategy",
+            &strategy
+        ));
+        set!(classname_from_package_file);
+        set!(category);
+        set!(expect_exit);
+        set!(expect_failure);
+        set!(warn_only);
+        set!(no_fail);
+        set!(abort_on_signal);
+        set!(abort_signals, |signals: Vec<String>| signals
+            .iter()
+            .map(|signal| parse_signal(signal))
+            .collect());
+        set!(skip_non_executable);
+        set!(keep_tmp);
+        set!(setup, |setup| Ok(Some(setup)));
+        set!(manifest, |manifest| Ok(Some(manifest)));
+        set!(param_file, |path| Ok(Some(path)));
+        set!(pattern);
+        set!(stdin);
+        set!(null);
+        set!(teardown, |teardown| Ok(Some(teardown)));
+        set!(before_each, |before_each| Ok(Some(before_each)));
+        set!(after_each, |after_each| Ok(Some(after_each)));
+        set!(hooks_as_cases);
+        set!(filter, |patterns: Vec<String>| regexes(&patterns));
+        set!(exclude, |patterns: Vec<String>| regexes(&patterns));
+        set!(skip, |patterns: Vec<String>| regexes(&patterns));
+        set!(xfail, |patterns: Vec<String>| regexes(&patterns));
+        set!(merge);
+        set!(merge_properties, |strategy: String| parse(
+            "merge_properties",
+            &strategy
+        ));
+        set!(sort, |order: String| parse("sort", &order));
+        set!(allow_duplicates);
+        set!(validate);
+        set!(append);
+        set!(compress);
+        set!(line_pattern, |pattern: String| regex::Regex::new(&pattern)
+            .map_err(anyhow::Error::from));
+        set!(max_output);
+        set!(retry);
+        set!(jobs);
+        set!(bail_after, |limit| Ok(Some(limit)));
+        set!(max_failures, |max| Ok(Some(max)));
+        set!(fail_fast);
+        set!(retry_delay);
+        set!(retry_backoff);
+        set!(resource_stats);
+        set!(queue_time);
+        set!(prefix);
+        set!(timeout, |seconds: f64| parse_seconds(&seconds.to_string())
+            .map(Some));
+        set!(timeout_signal, |signal: String| parse_signal(&signal)
+            .map(Some));
+        set!(kill_grace, |seconds: f64| parse_seconds(
+            &seconds.to_string()
+        ));
+        set!(idle_timeout, |seconds: f64| parse_seconds(
+            &seconds.to_string()
+        )
+        .map(Some));
+        set!(min_time, |seconds: f64| parse_seconds(&seconds.to_string())
+            .map(Some));
+        set!(max_total_time, |seconds: f64| parse_seconds(
+            &seconds.to_string()
+        )
+        .map(Some));
+        set!(redact, |patterns: Vec<String>| patterns
+            .iter()
+            .map(|pattern| parse_name_pattern(pattern).map_err(anyhow::Error::from))
+            .collect());
+        set!(redact_defaults);
+        set!(strip_ansi);
+        set!(scripts);
+        set!(script_args);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Config;
+    use crate::{Format, NameStrategy, Opt};
+    use structopt::StructOpt;
+
+    /// Parse `args` as the command line, with `toml` as the configuration file.
+    fn configured(toml: &str, args: &[&str]) -> Opt {
+        let matches = Opt::clap().get_matches_from(args);
+        let mut opt = Opt::from_clap(&matches);
+        let config: Config = toml::from_str(toml).unwrap();
+        config.apply(&mut opt, &matches).unwrap();
+        opt
+    }
+
+    #[test]
+    fn test_apply_config() {
+        let toml = r#"
+            format = "markdown"
+            name_strategy = "path"
+            expect_exit = [2]
+            strip_ansi = true
+            scripts = ["./test/im_ok.sh"]
+
+            [env]
+            TEST_MODE = "ci"
+        "#;
+        let opt = configured(toml, &["shunit"]);
+        assert_eq!(opt.format, Format::Markdown);
+        assert_eq!(opt.name_strategy, NameStrategy::Path);
+        assert_eq!(opt.expect_exit, [2]);
+        assert!(opt.strip_ansi);
+        assert_eq!(opt.scripts, ["./test/im_ok.sh"]);
+        assert_eq!(opt.env, [("TEST_MODE".to_string(), "ci".to_string())]);
+    }
+
+    #[test]
+    fn test_command_line_overrides_config() {
+        let toml = r#"
+            format = "markdown"
+            expect_exit = [2]
+            scripts = ["./test/im_ok.sh"]
+        "#;
+        let args = [
+            "shunit",
+            "--format",
+            "junit",
+            "--expect-exit",
+            "3",
+            "./test/bad_apple.sh",
+        ];
+        let opt = configured(toml, &args);
+        assert_eq!(opt.format, Format::Junit);
+        assert_eq!(opt.expect_exit, [3]);
+        assert_eq!(opt.scripts, ["./test/bad_apple.sh"]);
+    }
+
+    #[test]
+    fn test_env_options() {
+        let path = std::env::temp_dir().join("shunit_env_options.toml");
+        std::fs::write(&path, "format = \"json\"\njobs = 2\nretry = 3\n").unwrap();
+        let vars = [
+            ("SHUNIT_FORMAT", "markdown"),
+            ("SHUNIT_JOBS", "4"),
+            ("SHUNIT_QUIET", "yes"),
+            ("SHUNIT_EXPECT_EXIT", "2"),
+            ("SHUNIT_FILTER", r#"["im_ok", "slow"]"#),
+            ("SHUNIT_SUITE_NAME", "42"),
+            ("HOME", "/root"),
+            ("SHUNIT_TMPDIR", "/tmp/shunit-1-0"),
+        ];
+        let vars = vars.map(|(name, value)| (name.to_string(), value.to_string()));
+        let config = Config::load(path.to_str(), vars).unwrap();
+        let matches = Opt::clap().get_matches_from(["shunit", "--jobs", "8"]);
+        let mut opt = Opt::from_clap(&matches);
+        config.apply(&mut opt, &matches).unwrap();
+        assert_eq!(opt.format, Format::Markdown);
+        assert_eq!(opt.jobs, 8);
+        assert_eq!(opt.retry, 3);
+        assert!(opt.quiet);
+        assert_eq!(opt.expect_exit, [2]);
+        assert_eq!(opt.filter.len(), 2);
+        assert_eq!(opt.suite_name.as_deref(), Some("42"));
+
+        let invalid = |name: &str, value: &str| {
+            let vars = [(name.to_string(), value.to_string())];
+            Config::load(path.to_str(), vars).unwrap_err().to_string()
+        };
+        assert_eq!(
+            invalid("SHUNIT_JOBS", "many"),
+            "Invalid SHUNIT_JOBS: invalid type: string \"many\", expected usize"
+        );
+        let vars = [("SHUNIT_JOBZ", "4"), ("SHUNIT_TESTS_DIR", "t")];
+        let vars = vars.map(|(name, value)| (name.to_string(), value.to_string()));
+        let config = Config::load(path.to_str(), vars).unwrap();
+        assert_eq!(config.unknown_vars, ["SHUNIT_JOBZ", "SHUNIT_TESTS_DIR"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_reject_invalid_config() {
+        assert!(toml::from_str::<Config>("no_such_option = true").is_err());
+        let matches = Opt::clap().get_matches_from(["shunit"]);
+        let mut opt = Opt::from_clap(&matches);
+        let config: Config = toml::from_str(r#"format = "pdf""#).unwrap();
+        assert!(config.apply(&mut opt, &matches).is_err());
+    }
+}
@@ -0,0 +1,114 @@
+//! Rendering of a test suite as a Common Test Report Format (CTRF) JSON document.
+
+use crate::model::{epoch_millis, Status, TestCase, TestSuite};
+use serde_json::{json, Value};
+
+/// The CTRF status of a test case. CTRF has no errors, so an errored test case failed.
+fn status(testcase: &TestCase) -> &'static str {
+    match testcase.status() {
+        Status::Passed => "passed",
+        Status::Failed | Status::Errored => "failed",
+        Status::Skipped => "skipped",
+    }
+}
+
+/// The entry of a test case in `tests`, with its duration and start and stop times in
+/// milliseconds, and its message and captured output if it failed or was skipped.
+fn test(testcase: &TestCase, testsuite: &TestSuite) -> Value {
+    let start = epoch_millis(
+        testcase
+            .timestamp
+            .as_deref()
+            .unwrap_or(&testsuite.timestamp),
+    );
+    let duration = (testcase.time * 1000.0) as i64;
+    let mut test = json!({
+        "name": testcase.name,
+        "status": status(testcase),
+        "duration": duration,
+        "start": start,
+        "stop": start + duration,
+        "suite": testcase.classname,
+    });
+    if let Some(file) = &testcase.file {
+        test["filePath"] = json!(file);
+    }
+    if let Some(problem) = testcase.error.as_ref().or(testcase.failure.as_ref()) {
+        test["message"] = json!(problem.message);
+        test["trace"] = json!(problem.body);
+        test["rawStatus"] = json!(problem.error_type);
+    } else if let Some(skipped) = &testcase.skipped {
+        test["message"] = json!(skipped.message);
+    }
+    test
+}
+
+/**
+Render the test suite as a CTRF report: the `tool`, a `summary` with the counts and the start and
+stop of the run, and the `tests`, each with its `status`, `duration` and, for failed and skipped
+ones, its `message`. A failure has its captured output in `trace` and its type in `rawStatus`.
+*/
+pub fn render(testsuite: &TestSuite) -> String {
+    let count = |wanted: &str| {
+        testsuite
+            .testcases
+            .iter()
+            .filter(|testcase| status(testcase) == wanted)
+            .count()
+    };
+    let start = epoch_millis(&testsuite.timestamp);
+    let report = json!({
+        "reportFormat": "CTRF",
+        "specVersion": "0.0.0",
+        "results": {
+            "tool": { "name": "shunit", "version": env!("CARGO_PKG_VERSION") },
+            "summary": {
+                "tests": testsuite.testcases.len(),
+                "passed": count("passed"),
+                "failed": count("failed"),
+                "pending": 0,
+                "skipped": count("skipped"),
+                "other": 0,
+                "start": start,
+                "stop": start + (testsuite.time * 1000.0) as i64,
+            },
+            "tests": testsuite
+                .testcases
+                .iter()
+                .map(|testcase| test(testcase, testsuite))
+                .collect::<Vec<_>>(),
+            "environment": { "appName": testsuite.name },
+            "extra": { "hostname": testsuite.hostname },
+        },
+    });
+    serde_json::to_string_pretty(&report).expect("a report is always valid JSON") + "\n"
+}
+
+#[cfg(test)]
+mod test {
+    use super::render;
+    use crate::model::TestSuite;
+    use serde_json::Value;
+
+    #[test]
+    fn test_render() {
+        let testsuite = TestSuite::from_file("tests/fixtures/compact.xml").unwrap();
+        let report: Value = serde_json::from_str(&render(&testsuite)).unwrap();
+        assert_eq!(report["reportFormat"], "CTRF");
+        let results = &report["results"];
+        assert_eq!(results["tool"]["name"], "shunit");
+        assert_eq!(results["summary"]["tests"], 2);
+        assert_eq!(results["summary"]["passed"], 1);
+        assert_eq!(results["summary"]["failed"], 1);
+        assert_eq!(results["summary"]["start"], 1648980828000_i64);
+        assert_eq!(results["summary"]["stop"], 1648980829500_i64);
+        assert_eq!(results["tests"][0]["name"], "./test/im_ok.sh");
+        assert_eq!(results["tests"][0]["duration"], 500);
+        assert_eq!(results["tests"][0].get("message"), None);
+        assert_eq!(results["tests"][1]["status"], "failed");
+        assert_eq!(results["tests"][1]["message"], "Non-zero exit-code: 2");
+        assert_eq!(results["tests"][1]["trace"], "ls: cannot access");
+        assert_eq!(results["tests"][1]["suite"], "test/bad_apple.sh");
+        assert_eq!(results["environment"]["appName"], "compact");
+    }
+}
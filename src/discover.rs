@@ -0,0 +1,357 @@
+//! Discovery of the scripts in the directories and globs given as script arguments, like `tests/`
+//! or `'tests/**/*.sh'`, without the files of the `.shunitignore` files along the way.
+
+use regex::Regex;
+use std::collections::HashSet;
+use std::{fs, io, path};
+
+/// The file names that are discovered in a directory by default.
+pub const DEFAULT_PATTERN: &str = "*.sh";
+
+/// The file of a directory that lists what discovery leaves out of it, in gitignore syntax.
+pub const IGNORE_FILE: &str = ".shunitignore";
+
+/// Whether `text` has glob wildcards: `*`, `?` or a `[...]` class.
+pub fn is_glob(text: &str) -> bool {
+    text.contains(['*', '?', '['])
+}
+
+/// Translate a glob into a regex that matches entire paths.
+///
+/// `*` matches anything but a `/`, `?` one character but a `/`, and `[...]` a character of the
+/// class, or not of it with `[!...]`. `**` matches anything, `/`s included, and `**/` any number of
+/// leading directories, none included.
+pub fn glob_regex(glob: &str) -> Result<Regex, regex::Error> {
+    let mut regex = String::from("^");
+    let mut rest = glob;
+    while let Some(c) = rest.chars().next() {
+        rest = &rest[c.len_utf8()..];
+        match c {
+            '*' if rest.starts_with("*/") => {
+                regex.push_str("(?:.*/)?");
+                rest = &rest[2..];
+            }
+            '*' if rest.starts_with('*') => {
+                regex.push_str(".*");
+                rest = &rest[1..];
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' if rest.contains(']') => {
+                let (class, after) = rest.split_once(']').unwrap();
+                let (negated, class) = match class.strip_prefix('!') {
+                    Some(class) => ("^", class),
+                    None => ("", class),
+                };
+                regex.push_str(&format!("[{}{}]", negated, class.replace('\\', "\\\\")));
+                rest = after;
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex)
+}
+
+/// A pattern of an ignore file.
+#[derive(Debug)]
+struct IgnoreRule {
+    regex: Regex,
+    /// Whether the pattern starts with a `!`, which includes again what an earlier one left out.
+    negated: bool,
+    /// Whether the pattern ends with a `/`, which only matches directories.
+    dir_only: bool,
+}
+
+/// The rules of an ignore file, which apply to the paths under the directory it is in.
+#[derive(Debug)]
+pub struct IgnoreFile {
+    dir: path::PathBuf,
+    rules: Vec<IgnoreRule>,
+}
+
+impl IgnoreFile {
+    /**
+    Parse the contents of the ignore file of `dir`, in gitignore syntax: a glob per line, with blank
+    lines and `#` comments left out.
+
+    A pattern with a `/` other than at its end is relative to `dir`, and one without matches a name
+    at any depth. A trailing `/` matches only directories and a leading `!` includes a path again.
+    A `\` escapes a leading `#` or `!`.
+    */
+    pub fn parse(dir: &path::Path, text: &str) -> Result<Self, regex::Error> {
+        let mut rules = vec![];
+        for line in text.lines().map(str::trim_end) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negated, pattern) = match line.strip_prefix('!') {
+                Some(pattern) => (true, pattern),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let (dir_only, pattern) = match pattern.strip_suffix('/') {
+                Some(pattern) => (true, pattern),
+                None => (false, pattern),
+            };
+            let glob = match pattern.strip_prefix('/') {
+                Some(pattern) => pattern.to_string(),
+                None if pattern.contains('/') => pattern.to_string(),
+                None => format!("**/{}", pattern),
+            };
+            rules.push(IgnoreRule {
+                regex: glob_regex(&glob)?,
+                negated,
+                dir_only,
+            });
+        }
+        Ok(IgnoreFile {
+            dir: dir.to_path_buf(),
+            rules,
+        })
+    }
+
+    /// Whether the rules leave out `path`, if it is under the directory of the file, or include it
+    /// again, or `None` if no rule matches it. The last matching rule wins.
+    fn matches(&self, path: &path::Path, is_dir: bool) -> Option<bool> {
+        let relative = path.strip_prefix(&self.dir).ok()?.to_string_lossy();
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| (!rule.dir_only || is_dir) && rule.regex.is_match(&relative))
+            .map(|rule| !rule.negated)
+    }
+}
+
+/// Whether the ignore files leave out `path`. The files of deeper directories win.
+fn is_ignored(ignores: &[IgnoreFile], path: &path::Path, is_dir: bool) -> bool {
+    ignores
+        .iter()
+        .rev()
+        .find_map(|ignore| ignore.matches(path, is_dir))
+        .unwrap_or(false)
+}
+
+/// The ignore file of `dir`, if it has one.
+fn read_ignore_file(dir: &path::Path) -> io::Result<Option<IgnoreFile>> {
+    let path = dir.join(IGNORE_FILE);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let invalid = |err| {
+        let message = format!("{}: {}", path.display(), err);
+        io::Error::new(io::ErrorKind::InvalidData, message)
+    };
+    IgnoreFile::parse(dir, &text).map(Some).map_err(invalid)
+}
+
+/// The files under `dir`, depth first and sorted by name, without the ones left out by the ignore
+/// files of `dir` and the directories under it, on top of the `ignores` of the directories above.
+/// Hidden entries, whose names start with a `.`, are left out too, and symbolic links to
+/// directories aren't followed.
+fn walk(
+    dir: &path::Path,
+    ignores: &mut Vec<IgnoreFile>,
+    files: &mut Vec<path::PathBuf>,
+) -> io::Result<()> {
+    let ignore = read_ignore_file(dir)?;
+    let has_ignore = ignore.is_some();
+    ignores.extend(ignore);
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        let is_dir = entry.file_type()?.is_dir();
+        if is_ignored(ignores, &path, is_dir) {
+            continue;
+        }
+        if is_dir {
+            walk(&path, ignores, files)?;
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+    if has_ignore {
+        ignores.pop();
+    }
+    Ok(())
+}
+
+/// The executable files under `dir` whose names match `pattern`.
+fn discover_dir(dir: &path::Path, pattern: &Regex) -> io::Result<Vec<path::PathBuf>> {
+    let mut files = vec![];
+    walk(dir, &mut vec![], &mut files)?;
+    files.retain(|file| {
+        let name = file.file_name().unwrap_or_default().to_string_lossy();
+        pattern.is_match(&name) && crate::is_executable(file)
+    });
+    Ok(files)
+}
+
+/// The executable files that match `glob`, found under its leading directories without wildcards.
+fn discover_glob(glob: &str) -> io::Result<Vec<path::PathBuf>> {
+    let regex = glob_regex(glob).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let wildcard = glob.find(['*', '?', '[']).unwrap_or(glob.len());
+    let base = match glob[..wildcard].rfind('/') {
+        Some(0) => "/",
+        Some(slash) => &glob[..slash],
+        None => ".",
+    };
+    let mut files = vec![];
+    if path::Path::new(base).is_dir() {
+        walk(path::Path::new(base), &mut vec![], &mut files)?;
+    }
+    if base == "." {
+        // The paths of the walk start with `./`, which the glob doesn't.
+        for file in &mut files {
+            *file = file.strip_prefix(".").unwrap_or(file).to_path_buf();
+        }
+    }
+    files.retain(|file| regex.is_match(&file.to_string_lossy()) && crate::is_executable(file));
+    Ok(files)
+}
+
+/// The paths of a list of scripts, like the output of `find`: a path per line, or separated by NUL
+/// characters with `null`. Blank lines are left out, and so are the `\r`s of `\r\n` line endings.
+pub fn parse_script_list(text: &str, null: bool) -> Vec<String> {
+    let paths: Vec<&str> = if null {
+        text.split('\0').collect()
+    } else {
+        text.lines().collect()
+    };
+    paths
+        .into_iter()
+        .filter(|path| !path.is_empty() && (null || !path.trim().is_empty()))
+        .map(String::from)
+        .collect()
+}
+
+/**
+Replace the directories and globs among the script arguments with the scripts they contain.
+
+A directory stands for the executable files under it whose names match the glob `pattern`, and a
+glob for the executable files whose paths match it. An argument that names an existing file is a
+script even with wildcards in its name. Either is searched depth first, in the order of the names,
+without the paths left out by the `.shunitignore` files along the way, and a script that was
+already given, or found, is left out. Scripts that are given more than once by name are kept, to
+run every time.
+*/
+pub fn expand_scripts(args: &[String], pattern: &str) -> io::Result<Vec<String>> {
+    let pattern =
+        glob_regex(pattern).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let mut seen = HashSet::new();
+    let mut scripts = vec![];
+    for arg in args {
+        let arg_path = path::Path::new(arg);
+        let found = if arg_path.is_dir() {
+            discover_dir(arg_path, &pattern)
+        } else if is_glob(arg) && !arg_path.exists() {
+            discover_glob(arg)
+        } else {
+            seen.insert(fs::canonicalize(arg_path).unwrap_or_else(|_| arg_path.to_path_buf()));
+            scripts.push(arg.clone());
+            continue;
+        }
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", arg, err)))?;
+        if found.is_empty() {
+            warn!("No scripts found in {}", arg);
+        }
+        for file in found {
+            if seen.insert(fs::canonicalize(&file).unwrap_or_else(|_| file.clone())) {
+                scripts.push(file.to_string_lossy().into_owned());
+            }
+        }
+    }
+    Ok(scripts)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{expand_scripts, glob_regex, is_glob, is_ignored, parse_script_list, IgnoreFile};
+    use std::path::Path;
+
+    #[test]
+    fn test_glob_regex() {
+        let matches = |glob: &str, path: &str| glob_regex(glob).unwrap().is_match(path);
+        assert!(matches("*.sh", "im_ok.sh"));
+        assert!(!matches("*.sh", "test/im_ok.sh"));
+        assert!(!matches("*.sh", "im_ok.sh.bak"));
+        assert!(matches("test/**/*.sh", "test/im_ok.sh"));
+        assert!(matches("test/**/*.sh", "test/a/b/im_ok.sh"));
+        assert!(matches("test/**", "test/a/b"));
+        assert!(matches("test_?.sh", "test_1.sh"));
+        assert!(!matches("test_?.sh", "test_10.sh"));
+        assert!(matches("test_[0-9].sh", "test_1.sh"));
+        assert!(!matches("test_[!0-9].sh", "test_1.sh"));
+        assert!(matches("a+b (1).sh", "a+b (1).sh"));
+        assert!(matches("[oops", "[oops"));
+        assert!(is_glob("tests/*.sh"));
+        assert!(!is_glob("tests/im_ok.sh"));
+    }
+
+    #[test]
+    fn test_parse_script_list() {
+        assert_eq!(
+            parse_script_list("test/im_ok.sh\r\n\n  \ntest/with space.sh\n", false),
+            ["test/im_ok.sh", "test/with space.sh"]
+        );
+        assert_eq!(
+            parse_script_list("test/im_ok.sh\0test/new\nline.sh\0", true),
+            ["test/im_ok.sh", "test/new\nline.sh"]
+        );
+    }
+
+    #[test]
+    fn test_ignore_file() {
+        let text = "# sourced by the tests\n\
+                    helpers/\n\
+                    /vendor/**\n\
+                    !/vendor/keep.sh\n\
+                    *_wip.sh\n\
+                    \n\
+                    \\#hash.sh\n";
+        let ignores = [IgnoreFile::parse(Path::new("suite"), text).unwrap()];
+        let ignored = |path: &str, is_dir| is_ignored(&ignores, Path::new(path), is_dir);
+        assert!(ignored("suite/helpers", true));
+        assert!(ignored("suite/nested/helpers", true));
+        assert!(!ignored("suite/helpers", false));
+        assert!(ignored("suite/vendor/bats/test.sh", false));
+        assert!(!ignored("suite/vendor/keep.sh", false));
+        assert!(!ignored("suite/nested/vendor/test.sh", false));
+        assert!(ignored("suite/nested/draft_wip.sh", false));
+        assert!(ignored("suite/#hash.sh", false));
+        assert!(!ignored("suite/passes.sh", false));
+        assert!(!ignored("other/draft_wip.sh", false));
+
+        let nested = IgnoreFile::parse(Path::new("suite/nested"), "!draft_wip.sh").unwrap();
+        let ignores = [ignores.into_iter().next().unwrap(), nested];
+        assert!(!is_ignored(
+            &ignores,
+            Path::new("suite/nested/draft_wip.sh"),
+            false
+        ));
+    }
+
+    #[test]
+    fn test_expand_scripts() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            expand_scripts(&args(&["test/im_ok.sh", "test"]), "*.sh").unwrap(),
+            ["test/im_ok.sh", "test/bad_apple.sh", "test/slow.sh"]
+        );
+        assert_eq!(
+            expand_scripts(&args(&["test"]), "*_*.sh").unwrap(),
+            ["test/bad_apple.sh", "test/im_ok.sh"]
+        );
+        assert_eq!(
+            expand_scripts(&args(&["test/*_*.sh", "test/**"]), "*.sh").unwrap(),
+            ["test/bad_apple.sh", "test/im_ok.sh", "test/slow.sh"]
+        );
+        let twice = expand_scripts(&args(&["test/im_ok.sh", "test/im_ok.sh"]), "*.sh").unwrap();
+        assert_eq!(twice.len(), 2);
+    }
+}
@@ -0,0 +1,121 @@
+//! The `--env-file`s, which set environment variables for every script like a `.env` file.
+
+use std::fs;
+
+/// Whether `name` can be the name of an environment variable in a shell.
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/**
+The value of a variable as it is written after the `=`. Between `'...'` it is taken as it is, and
+between `"..."` a `\` escapes the next character, with `\n` for a newline. Without quotes, a `#`
+after whitespace starts a comment.
+*/
+fn parse_value(text: &str) -> anyhow::Result<String> {
+    let quoted = |quote: char| {
+        (text.len() >= 2 && text.starts_with(quote) && text.ends_with(quote))
+            .then(|| &text[1..text.len() - 1])
+    };
+    if let Some(value) = quoted('\'') {
+        return Ok(value.to_string());
+    }
+    if let Some(value) = quoted('"') {
+        let mut unescaped = String::new();
+        let mut chars = value.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('n') => unescaped.push('\n'),
+                    Some(c) => unescaped.push(c),
+                    None => bail!("Trailing \\ in `{}`", text),
+                },
+                c => unescaped.push(c),
+            }
+        }
+        return Ok(unescaped);
+    }
+    if text.starts_with(['\'', '"']) {
+        bail!("Unterminated quote in `{}`", text);
+    }
+    let value = match text.find(" #").or_else(|| text.find("\t#")) {
+        Some(comment) => &text[..comment],
+        None => text,
+    };
+    Ok(value.trim_end().to_string())
+}
+
+/// Parse an env file into its variables, in order. Blank lines and `#` comments are left out, and
+/// an `export` in front of a variable is ignored.
+fn parse(text: &str) -> anyhow::Result<Vec<(String, String)>> {
+    let mut env = vec![];
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |message: String| anyhow!("line {}: {}", number + 1, message);
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (name, value) = line
+            .split_once('=')
+            .ok_or_else(|| error(format!("no `=` found in `{}`", line)))?;
+        let name = name.trim();
+        if !is_name(name) {
+            return Err(error(format!("invalid variable name `{}`", name)));
+        }
+        let value = parse_value(value.trim()).map_err(|err| error(err.to_string()))?;
+        env.push((name.to_string(), value));
+    }
+    Ok(env)
+}
+
+/// Read the variables of the env file at `path`.
+pub fn load(path: &str) -> anyhow::Result<Vec<(String, String)>> {
+    let text =
+        fs::read_to_string(path).map_err(|err| anyhow!("Failed to read {}: {}", path, err))?;
+    parse(&text).map_err(|err| anyhow!("Failed to parse {}: {}", path, err))
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse;
+
+    #[test]
+    fn test_parse_env_file() {
+        let text = r#"
+# the database of the fixtures
+DB_HOST=localhost
+export DB_PORT = 5432
+GREETING="hello\n  \"world\""
+PATTERN='a\nb # c'
+EMPTY=
+URL=http://example.com/#top # the home page
+"#;
+        let env = parse(text).unwrap();
+        let env: Vec<(&str, &str)> = env
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        assert_eq!(
+            env,
+            [
+                ("DB_HOST", "localhost"),
+                ("DB_PORT", "5432"),
+                ("GREETING", "hello\n  \"world\""),
+                ("PATTERN", "a\\nb # c"),
+                ("EMPTY", ""),
+                ("URL", "http://example.com/#top"),
+            ]
+        );
+        assert_eq!(
+            parse("A=1\nB\n").unwrap_err().to_string(),
+            "line 2: no `=` found in `B`"
+        );
+        assert!(parse("1A=x").is_err());
+        assert!(parse("A=\"open").is_err());
+    }
+}
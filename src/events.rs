@@ -0,0 +1,168 @@
+//! The live events of a run, written as JSON lines as they happen, e.g. for dashboards.
+
+use crate::model::{Status, TestCase, TestSuite};
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+/// Something that happened during a run, tagged with its kind in `event`.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event<'a> {
+    /// The run started, with `tests` scripts to run.
+    SuiteStart { tests: usize },
+    /// A script started, or started another attempt.
+    TestStart { script: &'a str, name: &'a str },
+    /// A script wrote a line to `stream`, `stdout` or `stderr`. Markers aren't reported.
+    TestOutputLine {
+        script: &'a str,
+        stream: &'static str,
+        line: &'a str,
+    },
+    /// A test case of a script is complete, after its retries. The test case is nested, as its
+    /// `timestamp` is when the script started.
+    TestEnd {
+        script: &'a str,
+        status: Status,
+        testcase: &'a TestCase,
+    },
+    /// The run is complete, with the totals of the suite.
+    SuiteEnd {
+        name: &'a str,
+        tests: u32,
+        failures: u32,
+        errors: u32,
+        skipped: u32,
+        time: f32,
+    },
+}
+
+impl<'a> Event<'a> {
+    /// The event for a test case of `script` that is complete.
+    pub fn test_end(script: &'a str, testcase: &'a TestCase) -> Self {
+        Event::TestEnd {
+            script,
+            status: testcase.status(),
+            testcase,
+        }
+    }
+
+    /// The event for the suite of the run, once it is complete.
+    pub fn suite_end(testsuite: &'a TestSuite) -> Self {
+        Event::SuiteEnd {
+            name: &testsuite.name,
+            tests: testsuite.tests,
+            failures: testsuite.failures,
+            errors: testsuite.errors,
+            skipped: testsuite.skipped,
+            time: testsuite.time,
+        }
+    }
+}
+
+/// An event with the time it happened, as it is written.
+#[derive(Serialize)]
+struct Line<'a> {
+    timestamp: String,
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+}
+
+struct Sink {
+    writer: Box<dyn Write + Send>,
+    failed: bool,
+}
+
+/// Where the events of a run are written, a JSON object per line. Clones write to the same place.
+#[derive(Clone)]
+pub struct EventSink(Arc<Mutex<Sink>>);
+
+impl std::fmt::Debug for EventSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EventSink")
+    }
+}
+
+impl EventSink {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        EventSink(Arc::new(Mutex::new(Sink {
+            writer: Box::new(writer),
+            failed: false,
+        })))
+    }
+
+    /**
+    Write `event` as a line, stamped with the current time, and flush it right away.
+
+    The run doesn't depend on its events, so a failed write is only logged, the first time.
+    */
+    pub fn emit(&self, event: &Event) {
+        let line = Line {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            event,
+        };
+        let json = serde_json::to_string(&line).expect("an event is always valid JSON") + "\n";
+        let mut sink = self
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let written = sink
+            .writer
+            .write_all(json.as_bytes())
+            .and_then(|_| sink.writer.flush());
+        if let Err(err) = written {
+            if !sink.failed {
+                warn!("Failed to write an event: {}", err);
+            }
+            sink.failed = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Event, EventSink};
+    use crate::model::TestCase;
+    use std::sync::{Arc, Mutex};
+
+    /// A writer that keeps what is written, to look at after the sink is done with it.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_emit() {
+        let written = Shared::default();
+        let sink = EventSink::new(written.clone());
+        sink.emit(&Event::SuiteStart { tests: 1 });
+        let testcase = TestCase {
+            name: String::from("ok"),
+            classname: String::from("test"),
+            ..Default::default()
+        };
+        sink.emit(&Event::test_end("test/ok.sh", &testcase));
+
+        let written = String::from_utf8(written.0.lock().unwrap().clone()).unwrap();
+        let events: Vec<serde_json::Value> = written
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(events[0]["event"], "suite-start");
+        assert_eq!(events[0]["tests"], 1);
+        assert!(events[0]["timestamp"].is_string());
+        assert_eq!(events[1]["event"], "test-end");
+        assert_eq!(events[1]["script"], "test/ok.sh");
+        assert_eq!(events[1]["status"], "passed");
+        assert_eq!(events[1]["testcase"]["name"], "ok");
+    }
+}
@@ -0,0 +1,86 @@
+//! Expansion of `${VAR}` references in the script paths, arguments and working directories.
+
+/**
+Replace every `${VAR}` in `text` with the value `lookup` gives for `VAR`. `$$` is a literal `$`,
+and a `$` that is followed by neither is kept as it is. Values are inserted as they are, so a
+`${VAR}` in a value isn't expanded again.
+
+An undefined variable is an error, unless `allow_unset` is set, in which case it expands to
+nothing.
+*/
+pub fn expand(
+    text: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+    allow_unset: bool,
+) -> anyhow::Result<String> {
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        if let Some(after) = after.strip_prefix('$') {
+            expanded.push('$');
+            rest = after;
+        } else if let Some(reference) = after.strip_prefix('{') {
+            let end = reference
+                .find('}')
+                .ok_or_else(|| anyhow!("Unterminated ${{ in `{}`", text))?;
+            let name = &reference[..end];
+            let valid_name = !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid_name {
+                bail!("Invalid variable name `{}` in `{}`", name, text);
+            }
+            match lookup(name) {
+                Some(value) => expanded.push_str(&value),
+                None if allow_unset => {}
+                None => bail!(
+                    "Undefined variable {} in `{}`, use --allow-unset to expand it to nothing",
+                    name,
+                    text
+                ),
+            }
+            rest = &reference[end + 1..];
+        } else {
+            expanded.push('$');
+            rest = after;
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod test {
+    use super::expand;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "BIN" => Some(String::from("/opt/bin")),
+            "NESTED" => Some(String::from("${BIN}")),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_expand() {
+        assert_eq!(
+            expand("${BIN}/test.sh", lookup, false).unwrap(),
+            "/opt/bin/test.sh"
+        );
+        assert_eq!(expand("${NESTED}", lookup, false).unwrap(), "${BIN}");
+        assert_eq!(
+            expand("cost: $$5, $HOME", lookup, false).unwrap(),
+            "cost: $5, $HOME"
+        );
+        assert_eq!(expand("$${BIN}", lookup, false).unwrap(), "${BIN}");
+        assert_eq!(expand("a${MISSING}b", lookup, true).unwrap(), "ab");
+        assert_eq!(
+            expand("${MISSING}/test.sh", lookup, false).unwrap_err().to_string(),
+            "Undefined variable MISSING in `${MISSING}/test.sh`, use --allow-unset to expand it to nothing"
+        );
+        assert!(expand("${BIN", lookup, true).is_err());
+        assert!(expand("${B-IN}", lookup, true).is_err());
+    }
+}
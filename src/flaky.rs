@@ -0,0 +1,129 @@
+//! The flakiness of the scripts of a `--repeat` run: how often each of them passed over its runs.
+
+use crate::model::{Status, TestSuite};
+use serde::Serialize;
+
+/// The name of a run of a repeated test case, like `db (run 2/5)`.
+pub fn run_name(name: &str, run: u32, runs: u32) -> String {
+    format!("{} (run {}/{})", name, run, runs)
+}
+
+/// The name of a test case without its run, if it is named like a run of a repeated one.
+fn repeated_name(name: &str) -> Option<&str> {
+    let (name, run) = name.strip_suffix(')')?.rsplit_once(" (run ")?;
+    let (run, runs) = run.split_once('/')?;
+    let is_number = |text: &str| !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit());
+    (is_number(run) && is_number(runs)).then_some(name)
+}
+
+/// How often a repeated test case passed. Skipped runs don't count.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct PassRate<'a> {
+    pub classname: &'a str,
+    pub name: &'a str,
+    pub runs: u32,
+    pub passed: u32,
+    /// The share of the runs that passed, from 0 to 1.
+    pub pass_rate: f64,
+    /// Whether some runs passed and others didn't.
+    pub flaky: bool,
+}
+
+/// The pass rates of the repeated test cases of the suite, in the order of their first runs. A
+/// suite without repeated test cases has none.
+pub fn pass_rates(testsuite: &TestSuite) -> Vec<PassRate<'_>> {
+    let mut rates: Vec<PassRate> = vec![];
+    for testcase in &testsuite.testcases {
+        let Some(name) = repeated_name(&testcase.name) else {
+            continue;
+        };
+        let status = testcase.status();
+        if status == Status::Skipped {
+            continue;
+        }
+        let classname = testcase.classname.as_str();
+        let index = match rates
+            .iter()
+            .position(|rate| rate.classname == classname && rate.name == name)
+        {
+            Some(index) => index,
+            None => {
+                rates.push(PassRate {
+                    classname,
+                    name,
+                    runs: 0,
+                    passed: 0,
+                    pass_rate: 0.0,
+                    flaky: false,
+                });
+                rates.len() - 1
+            }
+        };
+        let rate = &mut rates[index];
+        rate.runs += 1;
+        rate.passed += u32::from(status == Status::Passed);
+    }
+    for rate in &mut rates {
+        rate.pass_rate = f64::from(rate.passed) / f64::from(rate.runs);
+        rate.flaky = rate.passed > 0 && rate.passed < rate.runs;
+    }
+    rates
+}
+
+/// The pass rates of the repeated test cases with mixed results, which are worth a quarantine.
+pub fn flaky(testsuite: &TestSuite) -> Vec<PassRate<'_>> {
+    pass_rates(testsuite)
+        .into_iter()
+        .filter(|rate| rate.flaky)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{flaky, pass_rates, repeated_name, run_name};
+    use crate::model::{Skipped, TestCase, TestError, TestSuite};
+
+    #[test]
+    fn test_pass_rates() {
+        assert_eq!(repeated_name(&run_name("db", 2, 5)), Some("db"));
+        assert_eq!(repeated_name("db (run two)"), None);
+        assert_eq!(repeated_name("db"), None);
+
+        let run = |name: &str, run, passed: bool| TestCase {
+            classname: String::from("test"),
+            name: run_name(name, run, 3),
+            failure: (!passed).then(TestError::default),
+            ..Default::default()
+        };
+        let mut skipped = run("skipped", 1, true);
+        skipped.skipped = Some(Skipped::default());
+        let testsuite = TestSuite {
+            testcases: vec![
+                run("db", 1, true),
+                run("api", 1, true),
+                run("db", 2, false),
+                run("api", 2, true),
+                run("db", 3, true),
+                run("api", 3, true),
+                skipped,
+                TestCase {
+                    name: String::from("once"),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let rates = pass_rates(&testsuite);
+        assert_eq!(rates.len(), 2);
+        assert_eq!(
+            (rates[0].name, rates[0].runs, rates[0].passed),
+            ("db", 3, 2)
+        );
+        assert!(rates[0].flaky);
+        assert!((rates[0].pass_rate - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!((rates[1].name, rates[1].pass_rate), ("api", 1.0));
+        assert!(!rates[1].flaky);
+        let flaky: Vec<&str> = flaky(&testsuite).iter().map(|rate| rate.name).collect();
+        assert_eq!(flaky, ["db"]);
+    }
+}
@@ -0,0 +1,114 @@
+//! The formats a test suite can be rendered in.
+
+use crate::model::TestSuite;
+use crate::{ctrf, html, jsonl, markdown, sonar, tap, teamcity};
+
+/// The formats a report can be written in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// A JUnit XML document.
+    Junit,
+    /// A Markdown summary, e.g. for pull request comments.
+    Markdown,
+    /// A standalone HTML page.
+    Html,
+    /// A JSON object per test case, written as soon as it completes, and a summary object.
+    Jsonl,
+    /// A TAP version 13 stream, with a test point per test case.
+    Tap,
+    /// A JSON document with the fields of the test suite and its test cases.
+    Json,
+    /// TeamCity service messages, written as the test cases complete.
+    Teamcity,
+    /// SonarQube's generic test execution XML, with a file per script.
+    Sonar,
+    /// A Common Test Report Format JSON document.
+    Ctrf,
+}
+
+impl Format {
+    pub const VARIANTS: [&'static str; 9] = [
+        "junit", "markdown", "html", "jsonl", "tap", "json", "teamcity", "sonar", "ctrf",
+    ];
+
+    /// Whether the test cases are written as they complete, rather than once the run is done.
+    pub fn is_streamed(&self) -> bool {
+        matches!(self, Format::Jsonl | Format::Teamcity)
+    }
+
+    /// Render the test suite in this format.
+    pub fn render(&self, testsuite: &TestSuite) -> anyhow::Result<String> {
+        match self {
+            Format::Junit => render_junit(testsuite),
+            Format::Markdown => Ok(markdown::render(testsuite)),
+            Format::Html => Ok(html::render(testsuite)),
+            Format::Jsonl => Ok(jsonl::render(testsuite)),
+            Format::Tap => Ok(tap::render(testsuite)),
+            Format::Json => Ok(render_json(testsuite)),
+            Format::Teamcity => Ok(teamcity::render(testsuite)),
+            Format::Sonar => Ok(sonar::render(testsuite)),
+            Format::Ctrf => Ok(ctrf::render(testsuite)),
+        }
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "junit" => Ok(Format::Junit),
+            "markdown" => Ok(Format::Markdown),
+            "html" => Ok(Format::Html),
+            "jsonl" => Ok(Format::Jsonl),
+            "tap" => Ok(Format::Tap),
+            "json" => Ok(Format::Json),
+            "teamcity" => Ok(Format::Teamcity),
+            "sonar" => Ok(Format::Sonar),
+            "ctrf" => Ok(Format::Ctrf),
+            _ => Err(anyhow!("Unknown format: {}", s)),
+        }
+    }
+}
+
+/// Render a test suite, or several in a `TestSuites`, as an indented JUnit XML document.
+pub fn render_junit<T: yaserde::YaSerialize>(testsuite: &T) -> anyhow::Result<String> {
+    let yaserde_cfg = yaserde::ser::Config {
+        perform_indent: true,
+        ..Default::default()
+    };
+    yaserde::ser::to_string_with_config(testsuite, &yaserde_cfg)
+        .map_err(|err| anyhow!("Failed to serialize test result: {}", err))
+}
+
+/// Render a test suite as a pretty-printed JSON document, with the same fields as the JUnit one.
+pub fn render_json(testsuite: &TestSuite) -> String {
+    serde_json::to_string_pretty(testsuite).expect("a test suite is always valid JSON") + "\n"
+}
+
+#[cfg(test)]
+mod test {
+    use crate::format::{render_json, render_junit};
+    use crate::model::TestSuite;
+
+    #[test]
+    fn test_reemit_compact_report() {
+        let compact = TestSuite::from_file("tests/fixtures/compact.xml").unwrap();
+        let pretty = render_junit(&compact).unwrap();
+        assert!(pretty.contains("\n  <testcase "));
+        let reparsed: TestSuite = yaserde::de::from_str(&pretty).unwrap();
+        assert_eq!(reparsed, compact);
+    }
+
+    #[test]
+    fn test_render_json() {
+        let compact = TestSuite::from_file("tests/fixtures/compact.xml").unwrap();
+        let json: serde_json::Value = serde_json::from_str(&render_json(&compact)).unwrap();
+        assert_eq!(json["name"], "compact");
+        assert_eq!(json["failures"], 1);
+        assert_eq!(json["properties"][0]["name"], "CI");
+        assert_eq!(json["testcases"][1]["failure"]["type"], "Assertion failed");
+        assert_eq!(json["testcases"][1]["failure"]["body"], "ls: cannot access");
+        assert_eq!(json["system_err"], "ls: cannot access");
+    }
+}
@@ -0,0 +1,76 @@
+//! GitHub Actions workflow commands, which annotate the failing test cases in the web UI.
+
+use crate::model::TestCase;
+use std::path::Path;
+
+/// Escape the message of a workflow command.
+fn escape_data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escape a property value of a workflow command, which also ends at a `,` or `:`.
+fn escape_property(text: &str) -> String {
+    escape_data(text).replace(':', "%3A").replace(',', "%2C")
+}
+
+/**
+The annotation of a failed or errored test case, like
+`::error file=test/db.sh,line=3,title=db::Non-zero exit-code: 1`, or `None` if it didn't fail.
+
+The `file` of the test case is made relative to `base`, usually the root of the repository, as
+GitHub expects. `level` is the command, `error` or `warning`.
+*/
+pub fn annotation(testcase: &TestCase, base: &Path, level: &str) -> Option<String> {
+    let problem = testcase.error.as_ref().or(testcase.failure.as_ref())?;
+    let mut properties = vec![];
+    if let Some(file) = &testcase.file {
+        let file = Path::new(file);
+        let file = file.strip_prefix(base).unwrap_or(file);
+        properties.push(format!("file={}", escape_property(&file.to_string_lossy())));
+    }
+    if let Some(line) = testcase.line {
+        properties.push(format!("line={}", line));
+    }
+    properties.push(format!("title={}", escape_property(&testcase.name)));
+    Some(format!(
+        "::{} {}::{}",
+        level,
+        properties.join(","),
+        escape_data(&problem.message)
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::annotation;
+    use crate::model::{TestCase, TestError};
+    use std::path::Path;
+
+    #[test]
+    fn test_annotation() {
+        let mut testcase = TestCase {
+            name: String::from("db: migrate, seed"),
+            file: Some(String::from("/repo/test/db.sh")),
+            line: Some(3),
+            failure: Some(TestError {
+                message: String::from("100% broken\nreally"),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            annotation(&testcase, Path::new("/repo"), "error").unwrap(),
+            "::error file=test/db.sh,line=3,title=db%3A migrate%2C seed::100%25 broken%0Areally"
+        );
+        testcase.file = Some(String::from("/elsewhere/db.sh"));
+        testcase.line = None;
+        assert_eq!(
+            annotation(&testcase, Path::new("/repo"), "warning").unwrap(),
+            "::warning file=/elsewhere/db.sh,title=db%3A migrate%2C seed::100%25 broken%0Areally"
+        );
+        testcase.failure = None;
+        assert_eq!(annotation(&testcase, Path::new("/repo"), "error"), None);
+    }
+}
@@ -0,0 +1,150 @@
+//! Rendering of a test suite as a standalone HTML page.
+
+use crate::model::{Status, TestCase, TestSuite};
+use crate::text::escape_html;
+
+const STYLE: &str = r#"
+body { font-family: sans-serif; margin: 2em; color: #222; }
+.summary span { display: inline-block; margin-right: 1.5em; font-size: 1.2em; }
+table { border-collapse: collapse; width: 100%; margin-top: 1em; }
+th, td { text-align: left; padding: 0.4em 0.8em; border-bottom: 1px solid #ddd; }
+th { cursor: pointer; user-select: none; background: #f4f4f4; }
+th::after { content: " \2195"; color: #aaa; }
+td.time { text-align: right; font-variant-numeric: tabular-nums; }
+tr.passed td.status { color: #1a7f37; }
+tr.failed td.status { color: #cf222e; }
+tr.errored td.status { color: #9a6700; }
+tr.skipped td.status { color: #6e7781; }
+details summary { cursor: pointer; }
+pre { background: #f6f8fa; padding: 0.8em; overflow-x: auto; white-space: pre-wrap; }
+"#;
+
+const SCRIPT: &str = r#"
+document.querySelectorAll("th").forEach((th, column) => {
+  th.addEventListener("click", () => {
+    const tbody = th.closest("table").tBodies[0];
+    const ascending = th.dataset.order !== "asc";
+    th.dataset.order = ascending ? "asc" : "desc";
+    const key = row => row.cells[column].dataset.sort || row.cells[column].textContent;
+    const rows = Array.from(tbody.rows);
+    rows.sort((a, b) => {
+      const x = key(a), y = key(b);
+      const order = isNaN(x) || isNaN(y) ? x.localeCompare(y) : x - y;
+      return ascending ? order : -order;
+    });
+    rows.forEach(row => tbody.appendChild(row));
+  });
+});
+"#;
+
+/// The CSS class and label of a test case outcome.
+fn status(status: Status) -> (&'static str, &'static str) {
+    match status {
+        Status::Passed => ("passed", "Passed"),
+        Status::Failed => ("failed", "Failed"),
+        Status::Errored => ("errored", "Error"),
+        Status::Skipped => ("skipped", "Skipped"),
+    }
+}
+
+/// The table row of a test case, with the captured output of failures in an expandable block.
+fn row(testcase: &TestCase) -> String {
+    let (class, label) = status(testcase.status());
+    let problem = testcase
+        .error
+        .as_ref()
+        .or(testcase.failure.as_ref())
+        .map(|problem| {
+            format!(
+                "<details><summary>{}</summary><pre>{}</pre></details>",
+                escape_html(&problem.message),
+                escape_html(&problem.body)
+            )
+        })
+        .unwrap_or_default();
+    format!(
+        "<tr class=\"{}\"><td>{}</td><td>{}</td><td class=\"status\">{}</td><td class=\"time\" data-sort=\"{}\">{:.2}s</td><td>{}</td></tr>\n",
+        class,
+        escape_html(&testcase.name),
+        escape_html(&testcase.classname),
+        label,
+        testcase.time,
+        testcase.time,
+        problem
+    )
+}
+
+/// A collapsible block with suite-level output, if there is any.
+fn output(title: &str, text: &str) -> String {
+    if text.is_empty() {
+        return String::new();
+    }
+    format!(
+        "<details><summary>{}</summary><pre>{}</pre></details>\n",
+        title,
+        escape_html(text)
+    )
+}
+
+/// Render the test suite as a self-contained HTML page, with inline styles and no external assets.
+pub fn render(testsuite: &TestSuite) -> String {
+    let passed = testsuite
+        .testcases
+        .iter()
+        .filter(|testcase| testcase.status() == Status::Passed)
+        .count();
+    let rows: String = testsuite.testcases.iter().map(row).collect();
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>{name}</title>
+<style>{style}</style>
+</head>
+<body>
+<h1>{name}</h1>
+<div class="summary">
+<span>{tests} tests</span><span>{passed} passed</span><span>{failures} failures</span><span>{errors} errors</span><span>{skipped} skipped</span><span>{time:.2}s</span>
+</div>
+<table>
+<thead><tr><th>Test</th><th>Class</th><th>Status</th><th>Duration</th><th>Details</th></tr></thead>
+<tbody>
+{rows}</tbody>
+</table>
+{system_out}{system_err}<script>{script}</script>
+</body>
+</html>
+"#,
+        name = escape_html(&testsuite.name),
+        style = STYLE,
+        tests = testsuite.tests,
+        passed = passed,
+        failures = testsuite.failures,
+        errors = testsuite.errors,
+        skipped = testsuite.skipped,
+        time = testsuite.time,
+        rows = rows,
+        system_out = output("system-out", &testsuite.system_out),
+        system_err = output("system-err", &testsuite.system_err),
+        script = SCRIPT,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::render;
+    use crate::model::TestSuite;
+
+    #[test]
+    fn test_render() {
+        let mut testsuite = TestSuite::from_file("tests/fixtures/compact.xml").unwrap();
+        testsuite.testcases[1].failure.as_mut().unwrap().body = String::from("<b>&</b>");
+        let html = render(&testsuite);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<span>2 tests</span><span>1 passed</span><span>1 failures</span>"));
+        assert!(html.contains("<tr class=\"failed\"><td>./test/bad_apple.sh</td>"));
+        assert!(html.contains("<pre>&lt;b&gt;&amp;&lt;/b&gt;</pre>"));
+        assert!(!html.contains("<link") && !html.contains(" src="));
+    }
+}
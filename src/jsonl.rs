@@ -0,0 +1,103 @@
+//! Rendering of a test suite as JSON Lines: an object per test case, followed by a summary.
+
+use crate::model::{Status, TestCase, TestSuite};
+use serde::Serialize;
+
+/// A test case line, the serialized test case with its status.
+#[derive(Serialize)]
+struct CaseLine<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    status: Status,
+    #[serde(flatten)]
+    testcase: &'a TestCase,
+}
+
+/// The last line, with the totals of the suite.
+#[derive(Serialize)]
+struct SummaryLine<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    name: &'a str,
+    tests: u32,
+    failures: u32,
+    errors: u32,
+    skipped: u32,
+    time: f32,
+}
+
+/// Render a test case as a single line of JSON, without the line ending.
+pub fn render_case(testcase: &TestCase) -> String {
+    let line = CaseLine {
+        kind: "testcase",
+        status: testcase.status(),
+        testcase,
+    };
+    serde_json::to_string(&line).expect("a test case is always valid JSON")
+}
+
+/// Render the totals of a test suite as a single line of JSON, without the line ending.
+pub fn render_summary(testsuite: &TestSuite) -> String {
+    let line = SummaryLine {
+        kind: "summary",
+        name: &testsuite.name,
+        tests: testsuite.tests,
+        failures: testsuite.failures,
+        errors: testsuite.errors,
+        skipped: testsuite.skipped,
+        time: testsuite.time,
+    };
+    serde_json::to_string(&line).expect("a summary is always valid JSON")
+}
+
+/// Render every test case of the suite and then the summary, a line each.
+pub fn render(testsuite: &TestSuite) -> String {
+    let mut lines: Vec<String> = testsuite.testcases.iter().map(render_case).collect();
+    lines.push(render_summary(testsuite));
+    lines.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod test {
+    use crate::jsonl::render;
+    use crate::model::{Skipped, TestCase, TestSuite};
+
+    #[test]
+    fn test_render_jsonl() {
+        let testsuite = TestSuite {
+            name: String::from("suite"),
+            tests: 2,
+            skipped: 1,
+            testcases: vec![
+                TestCase {
+                    name: String::from("ok"),
+                    classname: String::from("test"),
+                    ..Default::default()
+                },
+                TestCase {
+                    name: String::from("later"),
+                    classname: String::from("test"),
+                    skipped: Some(Skipped {
+                        message: String::from("Not yet"),
+                    }),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let lines: Vec<serde_json::Value> = render(&testsuite)
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["type"], "testcase");
+        assert_eq!(lines[0]["status"], "passed");
+        assert_eq!(lines[0]["name"], "ok");
+        assert!(lines[0].get("error").is_none());
+        assert_eq!(lines[1]["status"], "skipped");
+        assert_eq!(lines[1]["skipped"]["message"], "Not yet");
+        assert_eq!(lines[2]["type"], "summary");
+        assert_eq!(lines[2]["tests"], 2);
+        assert_eq!(lines[2]["skipped"], 1);
+    }
+}
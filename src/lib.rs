@@ -0,0 +1,2120 @@
+/*!
+Run shell scripts as test cases and collect their results as a JUnit test suite.
+
+The `shunit` binary is a thin command line wrapper around [`run_suite`]. The same suite can be run
+from Rust, e.g. from a build script or a custom test harness:
+
+```no_run
+# async fn example() {
+let options = shunit::Options::default();
+let testsuite = shunit::run_suite(&["test/im_ok.sh".to_string()], &options).await;
+println!("{}", shunit::Format::Junit.render(&testsuite).unwrap());
+# }
+```
+*/
+
+use crate::events::{Event, EventSink};
+use crate::signal::{
+    is_kill, kill, own_process_group, parse_signal, send_signal, signal_name, terminating_signal,
+    INTERRUPT,
+};
+use crate::tap::Directive;
+use chrono::DateTime;
+use chrono::{SecondsFormat, Utc};
+use indicatif::{ProgressBar, ProgressStyle};
+use std::collections::BTreeSet;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::{env, fs, io, path, process, time};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Command;
+use tokio::sync::{mpsc, Notify};
+use tokio::task::JoinHandle;
+
+#[macro_use]
+extern crate anyhow;
+
+#[macro_use]
+extern crate log;
+
+#[macro_use]
+extern crate yaserde_derive;
+
+/// How long a running script may continue after Ctrl-C before it is killed.
+const INTERRUPT_GRACE: time::Duration = time::Duration::from_secs(5);
+
+#[cfg(windows)]
+const LINE_ENDING: &str = "\r\n";
+#[cfg(not(windows))]
+const LINE_ENDING: &str = "\n";
+
+pub mod allure;
+pub mod color;
+pub mod ctrf;
+pub mod discover;
+pub mod events;
+pub mod flaky;
+pub mod format;
+pub mod github;
+pub mod html;
+pub mod jsonl;
+pub mod markdown;
+pub mod model;
+pub mod naming;
+pub mod resources;
+pub mod signal;
+pub mod sonar;
+pub mod spec;
+pub mod summary;
+pub mod tap;
+pub mod teamcity;
+pub mod text;
+pub mod validate;
+
+pub use color::ColorChoice;
+pub use format::{render_junit, Format};
+pub use model::*;
+pub use naming::{package_classname, ClassnameStrategy, NameStrategy, PACKAGE_FILE};
+pub use resources::ResourceUsage;
+pub use spec::TestSpec;
+
+/// A line of output and the time it was read.
+pub type LogLine = (DateTime<Utc>, String);
+
+/// The comment in the leading comment block of a script that overrides the timeout for it.
+const TIMEOUT_DIRECTIVE: &str = "shunit-timeout:";
+
+/// The comment directive that marks a script as expected to fail, with an optional reason.
+const XFAIL_DIRECTIVE: &str = "shunit-xfail:";
+
+/// The default `line_pattern`, matching lines like `FAIL at line 42`.
+pub const DEFAULT_LINE_PATTERN: &str = r"FAIL at line (\d+)";
+
+/// The prefix of the lines a script prints on std out to report to shunit, like `##shunit-assertions: 12`.
+const MARKER_PREFIX: &str = "##shunit-";
+
+/// The exit code of a script that skipped itself, like a test that needs a missing tool, as in
+/// automake.
+pub const SKIP_EXIT_CODE: i32 = 77;
+
+/// The environment variables shunit sets for the scripts and hooks, besides `TMPDIR`.
+pub const SCRIPT_VARIABLES: [&str; 4] = [
+    "SHUNIT_TMPDIR",
+    "SHUNIT_TEST_NAME",
+    "SHUNIT_TEST_SCRIPT",
+    "SHUNIT_TEST_STATUS",
+];
+
+/// The exit status of a script and the lines it wrote to std out and std err.
+#[derive(Debug)]
+pub struct ScriptOutput {
+    pub status: process::ExitStatus,
+    pub stdout: Vec<LogLine>,
+    pub stderr: Vec<LogLine>,
+    /// The `##shunit-KEY: VALUE` markers printed on std out, which aren't part of `stdout`.
+    pub markers: Vec<(String, String)>,
+    /// Whether the script was killed because it ran longer than its timeout.
+    pub timed_out: bool,
+    /// Whether the script was killed because it wrote no output for longer than `idle_timeout`.
+    pub idle_timed_out: bool,
+    /// Whether the script had to be killed after ignoring the `timeout_signal` for `kill_grace`.
+    pub force_killed: bool,
+    /// The resources the script used, if `resource_stats` is set and the platform reports them.
+    pub resources: Option<ResourceUsage>,
+}
+
+pub type ScriptResult = anyhow::Result<ScriptOutput>;
+
+/// How the scripts of a suite are run and reported. The defaults match the command line defaults.
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Environment variables set for every script.
+    pub env: Vec<(String, String)>,
+    /// Start every script from an empty environment, only applying `env`.
+    pub env_clear: bool,
+    /// Start every script from an empty environment, except for the variables of shunit named in
+    /// `pass_env`, and report only these as the properties of the suite.
+    pub clean_env: bool,
+    /// The environment variables passed on to the scripts with `clean_env`.
+    pub pass_env: Vec<String>,
+    /// How the JUnit classname is derived from the script path.
+    pub classname_strategy: ClassnameStrategy,
+    /// How the JUnit test case name is derived from the script path.
+    pub name_strategy: NameStrategy,
+    /// Derive the classname from the nearest `.shunit-package` file in the script's ancestry.
+    pub classname_from_package_file: bool,
+    /// Categories every test case is tagged with, as `category` properties.
+    pub categories: Vec<String>,
+    /// Exit codes treated as a pass, in addition to 0.
+    pub expect_exit: Vec<i32>,
+    /// The exit code that reports a script as skipped, unless it is one of the `expect_exit`.
+    pub skip_exit: Option<i32>,
+    /// Invert the result of every script.
+    pub expect_failure: bool,
+    /// Abort the whole run when a script is killed by one of the `abort_signals`.
+    pub abort_on_signal: bool,
+    /// Signals treated as infrastructure failures by `abort_on_signal`.
+    pub abort_signals: Vec<i32>,
+    /// Skip scripts without execute permission, instead of reporting them as errors.
+    pub skip_non_executable: bool,
+    /// Keep the temporary directories of the scripts that failed or had errors, instead of deleting
+    /// them with the others.
+    pub keep_tmp: bool,
+    /// Report the scripts whose path matches one of these as skipped, without running them.
+    pub skip: Vec<regex::Regex>,
+    /// Expect the scripts whose path matches one of these to fail: a failure passes, with an
+    /// `xfail` property, and a pass is a failure. Scripts can also expect it with `shunit-xfail`.
+    pub xfail: Vec<regex::Regex>,
+    /// A script run before the first test case, which aborts the run if it fails.
+    pub setup: Option<String>,
+    /// A script run after the last test case, even if the run failed or was interrupted.
+    pub teardown: Option<String>,
+    /// A script run before every script, which the script doesn't run without.
+    pub before_each: Option<String>,
+    /// A script run after every script, even if it failed.
+    pub after_each: Option<String>,
+    /// Report the setup and teardown as test cases, instead of only their failures as suite errors.
+    pub hooks_as_cases: bool,
+    /// Finds the line a failing script failed at in its std err, in the first capture group.
+    pub line_pattern: Option<regex::Regex>,
+    /// The maximum number of bytes of output captured per script.
+    pub max_output: u64,
+    /// Record the peak memory and CPU time of every script as test case properties.
+    pub resource_stats: bool,
+    /// Record how long every script waited for a free job, from the end of the setup until it
+    /// started, as a `queue_seconds` test case property.
+    pub queue_time: bool,
+    /// Prefix the forwarded output with its timestamp and stream. The captured output is unchanged.
+    pub prefix: bool,
+    /// How many scripts run at the same time.
+    pub jobs: usize,
+    /// Rerun a failing script up to this many times, until it passes.
+    pub retry: u32,
+    /// Stop starting scripts once this many test cases failed or had errors.
+    pub bail_after: Option<u32>,
+    /// Stop starting scripts after the first test case that failed or had an error, and report the
+    /// scripts that didn't start as skipped.
+    pub fail_fast: bool,
+    /// How long to wait before the first retry.
+    pub retry_delay: time::Duration,
+    /// The factor the delay is multiplied by after every retry.
+    pub retry_backoff: f64,
+    /// How long a script may run before it is killed, unless it sets its own `shunit-timeout`.
+    pub timeout: Option<time::Duration>,
+    /// How long a script may go without writing a line to std out or std err before it is killed.
+    pub idle_timeout: Option<time::Duration>,
+    /// Fail a passing script that finished faster than this, as it probably did nothing.
+    pub min_time: Option<time::Duration>,
+    /// How long the whole run may take. The scripts still running then are stopped, and the ones
+    /// that didn't start are reported as errors.
+    pub max_total_time: Option<time::Duration>,
+    /// The signal sent to a script that times out, instead of killing it right away.
+    pub timeout_signal: Option<i32>,
+    /// How long a script may take to exit after the `timeout_signal` before it is killed.
+    pub kill_grace: time::Duration,
+    /// Record when every script started in the `timestamp` of its test case.
+    pub testcase_timestamps: bool,
+    /// Run a script that can't be executed, like one without execute permission, with the
+    /// interpreter of its `#!` line.
+    pub fallback_shebang: bool,
+    /// Let the scripts read the std in of shunit, instead of giving them an empty one.
+    pub stdin_inherit: bool,
+    /// When to color the forwarded std err lines of the scripts.
+    pub color: ColorChoice,
+    /// Show a progress bar, if std err is an interactive terminal.
+    pub progress: bool,
+    /// The name of the suite, instead of the current directory.
+    pub suite_name: Option<String>,
+    /// Properties of the suite besides the environment. One replaces an environment variable of the
+    /// same name.
+    pub properties: Vec<(String, String)>,
+    /// Print the output of the scripts as it arrives, besides capturing it.
+    pub forward_output: bool,
+    /// Where the events of the run are written as they happen, like scripts starting and their
+    /// output.
+    pub events: Option<EventSink>,
+    /// Report a test case per TAP test point a script prints to std out, instead of one per script.
+    pub parse_tap: bool,
+    /// Put std out and std err, in the order they were written, in the `system-out` of the suite,
+    /// and leave its `system-err` empty.
+    pub combine_output: bool,
+    /// Print the output of every script as a block under a `== script ==` header once it exits,
+    /// instead of line by line as it arrives.
+    pub group_output: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            env: vec![],
+            env_clear: false,
+            clean_env: false,
+            pass_env: vec![],
+            classname_strategy: ClassnameStrategy::Absolute,
+            name_strategy: NameStrategy::Stem,
+            classname_from_package_file: false,
+            categories: vec![],
+            expect_exit: vec![],
+            skip_exit: Some(SKIP_EXIT_CODE),
+            expect_failure: false,
+            abort_on_signal: false,
+            abort_signals: ["KILL", "BUS"]
+                .iter()
+                .filter_map(|name| parse_signal(name).ok())
+                .collect(),
+            skip_non_executable: false,
+            keep_tmp: false,
+            skip: vec![],
+            xfail: vec![],
+            setup: None,
+            teardown: None,
+            before_each: None,
+            after_each: None,
+            hooks_as_cases: false,
+            line_pattern: Some(regex::Regex::new(DEFAULT_LINE_PATTERN).unwrap()),
+            max_output: 10 * 1024 * 1024,
+            resource_stats: false,
+            queue_time: false,
+            prefix: false,
+            jobs: 1,
+            retry: 0,
+            bail_after: None,
+            fail_fast: false,
+            retry_delay: time::Duration::ZERO,
+            retry_backoff: 1.0,
+            timeout: None,
+            idle_timeout: None,
+            min_time: None,
+            max_total_time: None,
+            timeout_signal: None,
+            kill_grace: time::Duration::from_secs(5),
+            testcase_timestamps: true,
+            fallback_shebang: false,
+            stdin_inherit: false,
+            color: ColorChoice::Auto,
+            progress: false,
+            suite_name: None,
+            properties: vec![],
+            forward_output: true,
+            events: None,
+            parse_tap: false,
+            combine_output: false,
+            group_output: false,
+        }
+    }
+}
+
+impl Options {
+    /// Derive the classname of the script at `absolute_path` according to the options.
+    pub fn classname(&self, absolute_path: &path::Path) -> String {
+        self.classname_from_package_file
+            .then(|| package_classname(absolute_path))
+            .flatten()
+            .unwrap_or_else(|| self.classname_strategy.classname(absolute_path))
+    }
+
+    /// The name of the suite: the `suite_name`, or the current directory.
+    pub fn suite_name(&self) -> String {
+        self.suite_name.clone().unwrap_or_else(|| {
+            env::current_dir()
+                .map(|dir| dir.display().to_string())
+                .unwrap_or_else(|_| "Unknown".to_string())
+        })
+    }
+
+    /// The environment variables of shunit the scripts get, unless `env_clear`: all of them, or
+    /// with `clean_env` only the ones named in `pass_env`.
+    fn inherited_env(&self) -> Vec<(String, String)> {
+        env::vars()
+            .filter(|(name, _)| !self.clean_env || self.pass_env.contains(name))
+            .collect()
+    }
+
+    /// Write `event` to the `events`, if any.
+    fn emit(&self, event: &Event) {
+        if let Some(events) = &self.events {
+            events.emit(event);
+        }
+    }
+
+    /// The properties of every test case, with a `category` property per category.
+    fn testcase_properties(&self) -> Option<Properties> {
+        let properties = self
+            .categories
+            .iter()
+            .map(|category| Property {
+                name: String::from("category"),
+                value: category.clone(),
+            })
+            .collect();
+        (!self.categories.is_empty()).then_some(Properties { properties })
+    }
+
+    /// The line the script failed at according to the last match of `line_pattern` in `stderr`.
+    fn failure_line(&self, stderr: &[LogLine]) -> Option<u32> {
+        let pattern = self.line_pattern.as_ref()?;
+        stderr.iter().rev().find_map(|(_, line)| {
+            pattern
+                .captures(line)
+                .and_then(|captures| captures.get(1))
+                .and_then(|line| line.as_str().parse().ok())
+        })
+    }
+
+    /// Whether a script that exited with `status` passed, honoring `expect_exit` and
+    /// `expect_failure`.
+    pub fn is_pass(&self, status: &process::ExitStatus) -> bool {
+        let expected = status.success()
+            || status
+                .code()
+                .is_some_and(|code| self.expect_exit.contains(&code));
+        expected != self.expect_failure
+    }
+
+    /// Whether a script that exited with `code` skipped itself. An expected exit code is a pass.
+    pub fn is_skip(&self, code: i32) -> bool {
+        self.skip_exit == Some(code) && !self.expect_exit.contains(&code)
+    }
+
+    /// A progress bar for `len` scripts, hidden unless std err is an interactive terminal.
+    fn progress_bar(&self, len: usize) -> ProgressBar {
+        if !self.progress || !io::stderr().is_terminal() {
+            return ProgressBar::hidden();
+        }
+        let progress = ProgressBar::new(len as u64);
+        progress.set_style(
+            ProgressStyle::with_template("{spinner} [{elapsed}] {pos}/{len} {wide_msg}").unwrap(),
+        );
+        progress.enable_steady_tick(time::Duration::from_millis(100));
+        progress
+    }
+}
+
+/// Tracks whether the run was interrupted with Ctrl-C.
+#[derive(Clone, Default)]
+pub struct Interrupt {
+    interrupted: Arc<AtomicBool>,
+    notify: Arc<Notify>,
+}
+
+impl Interrupt {
+    /// Start listening for Ctrl-C, which replaces the default behavior of exiting immediately.
+    pub fn listen() -> Self {
+        let interrupt = Interrupt::default();
+        let listener = interrupt.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                warn!("Interrupted, finishing the running script before writing the report");
+                listener.interrupted.store(true, Ordering::SeqCst);
+                listener.notify.notify_waiters();
+            }
+        });
+        interrupt
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::SeqCst)
+    }
+
+    /// Wait until the run is interrupted.
+    pub async fn interrupted(&self) {
+        let notified = self.notify.notified();
+        if !self.is_interrupted() {
+            notified.await;
+        }
+    }
+}
+
+/// The outcome of running a suite.
+#[derive(Debug)]
+pub struct SuiteRun {
+    /// The test suite with a test case per script that was run.
+    pub testsuite: TestSuite,
+    /// The signal that aborted the run, if a script was killed by one of the `abort_signals`.
+    pub aborted_by: Option<i32>,
+    /// Whether the run stopped early, because `bail_after` test cases failed or had errors.
+    pub bailed_out: bool,
+}
+
+/// Run `scripts` one after the other and collect the results as a test suite.
+pub async fn run_suite(scripts: &[String], options: &Options) -> TestSuite {
+    let tests: Vec<TestSpec> = scripts.iter().cloned().map(TestSpec::from).collect();
+    run(&tests, options, &Interrupt::default()).await.testsuite
+}
+
+/**
+Run `tests`, up to `options.jobs` at a time, stopping early if the run is aborted or interrupted.
+The test cases of the suite are in the order of `tests`, whichever script finishes first.
+
+The `setup` hook runs before the first script, and if it fails, no script runs and every one is
+reported as an error. The `teardown` hook runs after the last one, even if the run was aborted or
+interrupted.
+
+- `tests` the scripts to run, each with the settings that override `options` for it.
+- `interrupt` stops the run after the running script, which is killed if it doesn't finish within
+  a grace period.
+*/
+pub async fn run(tests: &[TestSpec], options: &Options, interrupt: &Interrupt) -> SuiteRun {
+    run_observed(tests, options, interrupt, &mut |_| {}).await
+}
+
+/**
+Like [`run`], but `observer` is called with every test case as soon as it is complete, e.g. to
+stream the results. The test cases are observed in the order of the resulting suite, so with
+several `jobs` a test case waits for the ones before it.
+*/
+pub async fn run_observed(
+    tests: &[TestSpec],
+    options: &Options,
+    interrupt: &Interrupt,
+    observer: &mut dyn FnMut(&TestCase),
+) -> SuiteRun {
+    let start = time::Instant::now();
+    let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+
+    let mut stdout_messages: Vec<LogLine> = vec![];
+    let mut stderr_messages: Vec<LogLine> = vec![];
+    let mut testcases: Vec<TestCase> = vec![];
+    let mut setup_case: Option<TestCase> = None;
+    let mut teardown_case: Option<TestCase> = None;
+    let mut aborted_by: Option<i32> = None;
+    let mut bailed_out = false;
+    let mut out_of_time = false;
+    let mut unsuccessful = 0;
+    // Scripts are stopped at the deadline, and none start after it.
+    let deadline = options
+        .max_total_time
+        .map(|max_total_time| start + max_total_time);
+    let past_deadline = || deadline.is_some_and(|deadline| time::Instant::now() >= deadline);
+
+    let progress = options.progress_bar(tests.len());
+    options.emit(&Event::SuiteStart { tests: tests.len() });
+
+    let mut setup_failed = false;
+    if let Some(setup) = &options.setup {
+        progress.set_message(format!("setup {}", setup));
+        let mut hook = run_hook("setup", setup, options, &progress, interrupt, deadline, &[]).await;
+        stdout_messages.append(&mut hook.stdout);
+        stderr_messages.append(&mut hook.stderr);
+        setup_failed = hook.testcase.status() != Status::Passed;
+        options.emit(&Event::test_end(setup, &hook.testcase));
+        if setup_failed {
+            error!("Aborting: the setup {} failed", setup);
+        }
+        if options.hooks_as_cases {
+            progress.suspend(|| observer(&hook.testcase));
+        }
+        setup_case = Some(hook.testcase);
+    }
+
+    // The scripts run as tasks, up to `jobs` of them at a time, which report back with their index,
+    // so the test cases end up in the order of `tests` whichever finishes first.
+    let jobs = options.jobs.max(1);
+    // Every script is queued once the setup is done, and waits there until a job is free.
+    let queued_at = time::Instant::now();
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let mut cases: Vec<Option<CaseRun>> = tests.iter().map(|_| None).collect();
+    let mut started = 0;
+    // The indexes of the scripts that are running, for the progress bar.
+    let mut running = BTreeSet::new();
+    let mut observed = 0;
+    let mut stopped = setup_failed;
+    loop {
+        if !stopped && started < tests.len() && past_deadline() {
+            out_of_time = true;
+            stopped = true;
+            error!(
+                "Out of time: the run exceeded --max-total-time of {}s",
+                options.max_total_time.unwrap_or_default().as_secs_f64()
+            );
+        }
+        while !stopped
+            && running.len() < jobs
+            && started < tests.len()
+            && !interrupt.is_interrupted()
+        {
+            let index = started;
+            let test = tests[index].clone();
+            let test_options = test.options(options);
+            running.insert(index);
+            progress.set_message(running_message(tests, &running));
+            let (progress, interrupt, sender) =
+                (progress.clone(), interrupt.clone(), sender.clone());
+            tokio::spawn(async move {
+                let queued = queued_at.elapsed();
+                let case =
+                    run_with_retries(&test, &test_options, &progress, &interrupt, deadline).await;
+                let _ = sender.send((index, queued, case));
+            });
+            started += 1;
+        }
+        if running.is_empty() {
+            break;
+        }
+        let Some((index, queued, mut case)) = receiver.recv().await else {
+            break;
+        };
+        running.remove(&index);
+        progress.set_message(running_message(tests, &running));
+        if options.queue_time {
+            for testcase in std::iter::once(&mut case.testcase).chain(&mut case.points) {
+                testcase
+                    .properties
+                    .get_or_insert_with(Properties::default)
+                    .properties
+                    .push(Property {
+                        name: String::from("queue_seconds"),
+                        value: format!("{:.3}", queued.as_secs_f64()),
+                    });
+            }
+        }
+        progress.inc(1);
+        unsuccessful += case.unsuccessful() as u32;
+        for testcase in case.testcases() {
+            options.emit(&Event::test_end(&tests[index].script, testcase));
+        }
+
+        if let Some(signal) = case.aborted_by.filter(|_| aborted_by.is_none()) {
+            aborted_by = Some(signal);
+            stopped = true;
+            error!(
+                "Aborting: {} was killed by signal {}",
+                tests[index].script,
+                signal_name(signal)
+            );
+        }
+        let bail = options
+            .fail_fast
+            .then_some(1)
+            .or(options.bail_after)
+            .is_some_and(|limit| unsuccessful >= limit);
+        if bail && !stopped {
+            bailed_out = true;
+            stopped = true;
+            error!(
+                "Bailing out: {} test cases failed or had errors",
+                unsuccessful
+            );
+        }
+
+        cases[index] = Some(case);
+        // Observe the test cases in order, as soon as all the ones before them are complete.
+        while let Some(Some(case)) = cases.get(observed) {
+            progress.suspend(|| case.testcases().for_each(&mut *observer));
+            observed += 1;
+        }
+    }
+    // The scripts that didn't start in time, after a failed setup, or after a failure with
+    // `fail_fast`, are still reported, so the report lists every script.
+    if out_of_time || setup_failed || (bailed_out && options.fail_fast) {
+        for (test, case) in tests.iter().zip(&mut cases).skip(started) {
+            let mut run = not_run(test, options);
+            if setup_failed {
+                run.testcase.error = Some(TestError {
+                    message: format!(
+                        "Not run, the setup {} failed",
+                        options.setup.as_deref().unwrap_or_default()
+                    ),
+                    error_type: String::from("setup"),
+                    body: String::new(),
+                });
+            } else if out_of_time {
+                run.testcase.error = Some(TestError {
+                    message: format!(
+                        "Not run, the run exceeded --max-total-time of {}s",
+                        options.max_total_time.unwrap_or_default().as_secs_f64()
+                    ),
+                    error_type: String::from("max-total-time"),
+                    body: String::new(),
+                });
+            } else {
+                run.testcase.skipped = Some(Skipped {
+                    message: String::from("Not run, --fail-fast stopped the run after a failure"),
+                });
+            }
+            let case = case.insert(run);
+            options.emit(&Event::test_end(&test.script, &case.testcase));
+            progress.suspend(|| observer(&case.testcase));
+        }
+    }
+    // The scripts start in order, and every started one completes, so there are no gaps.
+    for mut case in cases.into_iter().flatten() {
+        stdout_messages.append(&mut case.stdout);
+        stderr_messages.append(&mut case.stderr);
+        testcases.extend(case.testcases().cloned());
+    }
+
+    if let Some(teardown) = &options.teardown {
+        // The teardown runs to completion, even after Ctrl-C.
+        progress.set_message(format!("teardown {}", teardown));
+        let mut hook = run_hook(
+            "teardown",
+            teardown,
+            options,
+            &progress,
+            &Interrupt::default(),
+            None,
+            &[],
+        )
+        .await;
+        stdout_messages.append(&mut hook.stdout);
+        stderr_messages.append(&mut hook.stderr);
+        options.emit(&Event::test_end(teardown, &hook.testcase));
+        if options.hooks_as_cases {
+            progress.suspend(|| observer(&hook.testcase));
+        }
+        teardown_case = Some(hook.testcase);
+    }
+
+    progress.finish_and_clear();
+
+    let duration = start.elapsed();
+
+    // Failed hooks are suite-level errors, unless they are reported as test cases.
+    let mut hook_errors = 0;
+    if options.hooks_as_cases {
+        testcases.splice(0..0, setup_case);
+        testcases.extend(teardown_case);
+    } else {
+        let hooks = setup_case.iter().chain(&teardown_case);
+        for error in hooks.filter_map(|hook| hook.error.as_ref()) {
+            hook_errors += 1;
+            stderr_messages.push((Utc::now(), error.message.clone()));
+        }
+    }
+
+    let count = |status: Status| {
+        testcases
+            .iter()
+            .filter(|testcase| testcase.status() == status)
+            .count() as u32
+    };
+    let error_count = count(Status::Errored) + hook_errors;
+    let failure_count = count(Status::Failed);
+    let skipped_count = count(Status::Skipped);
+    let test_count = testcases.len() as u32;
+
+    let explicit = |name: &String| options.properties.iter().any(|(key, _)| key == name);
+    let properties: Vec<Property> = options
+        .inherited_env()
+        .into_iter()
+        .filter(|(name, _)| !explicit(name))
+        .chain(options.properties.iter().cloned())
+        .map(|(name, value)| Property { name, value })
+        .collect();
+
+    if options.combine_output {
+        stdout_messages = join_and_sort(stdout_messages, stderr_messages);
+        stderr_messages = vec![];
+    }
+    let system_out: Vec<String> = stdout_messages.into_iter().map(|line| line.1).collect();
+    let system_err: Vec<String> = stderr_messages.into_iter().map(|line| line.1).collect();
+
+    let testsuite = TestSuite {
+        testcases,
+        errors: error_count,
+        failures: failure_count,
+        skipped: skipped_count,
+        time: duration.as_secs_f32(),
+        tests: test_count,
+        system_out: system_out.join(LINE_ENDING),
+        system_err: system_err.join(LINE_ENDING),
+        name: options.suite_name(),
+        hostname: hostname(),
+        timestamp,
+        properties: Properties { properties },
+    };
+
+    options.emit(&Event::suite_end(&testsuite));
+    SuiteRun {
+        testsuite,
+        aborted_by,
+        bailed_out,
+    }
+}
+
+/// The message of the progress bar: how many scripts are running, and which ones.
+fn running_message(tests: &[TestSpec], running: &BTreeSet<usize>) -> String {
+    let scripts: Vec<&str> = running
+        .iter()
+        .map(|index| tests[*index].script.as_str())
+        .collect();
+    format!("{} running: {}", scripts.len(), scripts.join(", "))
+}
+
+/// A finished test case, with the output captured from its script.
+struct CaseRun {
+    testcase: TestCase,
+    stdout: Vec<LogLine>,
+    stderr: Vec<LogLine>,
+    /// The signal that killed the script, if it should abort the run.
+    aborted_by: Option<i32>,
+    /// With `parse_tap`, a test case per TAP test point the script printed.
+    points: Vec<TestCase>,
+}
+
+impl CaseRun {
+    /// The test cases of the TAP test points, if any, followed by the one of the script. That one
+    /// is left out if it passed, as the test points replace it.
+    fn testcases(&self) -> impl Iterator<Item = &TestCase> {
+        let script = (self.points.is_empty() || self.testcase.status() != Status::Passed)
+            .then_some(&self.testcase);
+        self.points.iter().chain(script)
+    }
+
+    /// How many of the test cases failed or had errors.
+    fn unsuccessful(&self) -> usize {
+        self.testcases()
+            .filter(|testcase| matches!(testcase.status(), Status::Failed | Status::Errored))
+            .count()
+    }
+}
+
+/// A test case for a script that didn't start, as the run stopped before, without its outcome.
+fn not_run(test: &TestSpec, options: &Options) -> CaseRun {
+    let name = test.script.as_str();
+    let absolute_path = resolve_script(name).ok();
+    CaseRun {
+        testcase: TestCase {
+            classname: match &absolute_path {
+                Some(absolute_path) => options.classname(absolute_path),
+                None => name.to_string(),
+            },
+            name: test
+                .name
+                .clone()
+                .unwrap_or_else(|| options.name_strategy.name(name)),
+            file: Some(match &absolute_path {
+                Some(absolute_path) => options.classname_strategy.file(absolute_path),
+                None => name.to_string(),
+            }),
+            properties: options.testcase_properties(),
+            ..Default::default()
+        },
+        stdout: vec![],
+        stderr: vec![],
+        aborted_by: None,
+        points: vec![],
+    }
+}
+
+/// Run the script of `test` and classify the result as a test case. The script is stopped at the
+/// `deadline` of the run, if it hasn't timed out before. A `hook` doesn't read the `shunit-xfail`
+/// directive of its script.
+async fn run_test_case(
+    test: &TestSpec,
+    options: &Options,
+    progress: &ProgressBar,
+    interrupt: &Interrupt,
+    deadline: Option<time::Instant>,
+    hook: bool,
+) -> CaseRun {
+    let name = test.script.as_str();
+    let mut case = CaseRun {
+        testcase: TestCase {
+            classname: name.to_string(),
+            name: test
+                .name
+                .clone()
+                .unwrap_or_else(|| options.name_strategy.name(name)),
+            file: Some(name.to_string()),
+            properties: options.testcase_properties(),
+            ..Default::default()
+        },
+        stdout: vec![],
+        stderr: vec![],
+        aborted_by: None,
+        points: vec![],
+    };
+
+    // A skipped script doesn't need to exist, like one that is only there on some platforms.
+    if let Some(pattern) = options.skip.iter().find(|pattern| pattern.is_match(name)) {
+        info!("Skipping {}: matches --skip {}", name, pattern);
+        let mut case = not_run(test, options);
+        case.testcase.skipped = Some(Skipped {
+            message: format!("Skipped by --skip pattern: {}", pattern),
+        });
+        return case;
+    }
+
+    let absolute_path = match resolve_script(name) {
+        Ok(absolute_path) => absolute_path,
+        Err(err) => {
+            let message = if is_bare_name(name) && err.kind() == io::ErrorKind::NotFound {
+                format!(
+                    "Command not found: {}, neither a file nor on the PATH",
+                    name
+                )
+            } else {
+                format!("Script not found: {}", name)
+            };
+            warn!("{}: {}", message, err);
+            case.testcase.error = Some(TestError {
+                message,
+                error_type: io_error_type(Some(err.kind())),
+                body: err.to_string(),
+            });
+            return case;
+        }
+    };
+    let mut testcase = TestCase {
+        classname: options.classname(&absolute_path),
+        file: Some(options.classname_strategy.file(&absolute_path)),
+        ..case.testcase
+    };
+
+    if !is_executable(&absolute_path) && !options.fallback_shebang {
+        let message = format!("{} is not executable, try `chmod +x {}`", name, name);
+        if options.skip_non_executable {
+            warn!("Skipping {}: not executable", name);
+            testcase.skipped = Some(Skipped { message });
+        } else {
+            testcase.error = Some(TestError {
+                message,
+                error_type: String::from("IO error"),
+                body: String::new(),
+            });
+        }
+        case.testcase = testcase;
+        return case;
+    }
+
+    let timeout = timeout_directive(&absolute_path).or(options.timeout);
+    let xfail = match options.xfail.iter().find(|pattern| pattern.is_match(name)) {
+        Some(pattern) => Some(format!("matches --xfail {}", pattern)),
+        None if hook => None,
+        None => header_directive(&absolute_path, XFAIL_DIRECTIVE),
+    };
+    let left = deadline.map(|deadline| deadline.saturating_duration_since(time::Instant::now()));
+    let out_of_time = left.is_some_and(|left| timeout.is_none_or(|timeout| left < timeout));
+    let timeout = if out_of_time { left } else { timeout };
+    if options.testcase_timestamps {
+        testcase.timestamp = Some(Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true));
+    }
+    let started = time::Instant::now();
+    let started_at = Utc::now();
+    options.emit(&Event::TestStart {
+        script: name,
+        name: &testcase.name,
+    });
+    let result = run_script(test, options, timeout, progress, interrupt).await;
+    let elapsed = started.elapsed();
+    testcase.time = elapsed.as_secs_f32();
+
+    match result {
+        Ok(ScriptOutput {
+            status: exit_code,
+            stdout,
+            stderr,
+            markers,
+            timed_out,
+            idle_timed_out,
+            force_killed,
+            resources,
+        }) => {
+            if let Some(resources) = resources {
+                testcase
+                    .properties
+                    .get_or_insert_with(Properties::default)
+                    .properties
+                    .extend(resources.properties());
+            }
+            testcase.assertions = marker(&markers, "assertions").and_then(|assertions| {
+                assertions
+                    .parse()
+                    .map_err(|_| warn!("Ignoring invalid assertions from {}: {}", name, assertions))
+                    .ok()
+            });
+            if test.name.is_none() {
+                if let Some(marked) = identity_marker(&markers, "name", name) {
+                    testcase.name = marked;
+                }
+            }
+            if let Some(marked) = identity_marker(&markers, "classname", name) {
+                testcase.classname = marked;
+            }
+            let body = || {
+                let body = join_and_sort(join_log_lines(&stdout), join_log_lines(&stderr));
+                let body: Vec<String> = body.into_iter().map(|line| line.1).collect();
+                body.join(LINE_ENDING)
+            };
+            // How the script was stopped after a timeout, if not by killing it right away.
+            let stopped = match options.timeout_signal {
+                Some(signal) if force_killed => format!(
+                    ", killed after ignoring {} for {}s",
+                    signal_name(signal),
+                    options.kill_grace.as_secs_f64()
+                ),
+                Some(signal) if !is_kill(signal) => {
+                    format!(", stopped by {}", signal_name(signal))
+                }
+                _ => String::new(),
+            };
+            let abort_signal = terminating_signal(&exit_code)
+                .filter(|signal| options.abort_on_signal && options.abort_signals.contains(signal));
+            if timed_out && out_of_time {
+                testcase.error = Some(TestError {
+                    message: format!(
+                        "Stopped, the run exceeded --max-total-time of {}s{}",
+                        options.max_total_time.unwrap_or_default().as_secs_f64(),
+                        stopped
+                    ),
+                    error_type: String::from("max-total-time"),
+                    body: body(),
+                });
+            } else if timed_out {
+                testcase.error = Some(TestError {
+                    message: format!(
+                        "Script exceeded timeout of {}s{}",
+                        timeout.unwrap_or_default().as_secs_f64(),
+                        stopped
+                    ),
+                    error_type: String::from("timeout"),
+                    body: body(),
+                });
+            } else if idle_timed_out {
+                testcase.error = Some(TestError {
+                    message: format!(
+                        "No output for {}s{}",
+                        options.idle_timeout.unwrap_or_default().as_secs_f64(),
+                        stopped
+                    ),
+                    error_type: String::from("idle-timeout"),
+                    body: body(),
+                });
+            } else if let Some(signal) = abort_signal {
+                case.aborted_by = Some(signal);
+                testcase.error = Some(TestError {
+                    message: format!("Killed by signal {}", signal_name(signal)),
+                    error_type: String::from("Infrastructure error"),
+                    body: body(),
+                });
+            } else if exit_code.code().is_some_and(|code| options.is_skip(code)) {
+                testcase.skipped = Some(Skipped {
+                    message: format!(
+                        "Skipped by the script, exit-code: {}",
+                        exit_code.code().unwrap_or_default()
+                    ),
+                });
+            } else if !options.is_pass(&exit_code) {
+                let code = exit_code.code().unwrap_or(-1);
+                let message = if options.expect_failure {
+                    format!("Expected failure, but got exit-code: {}", code)
+                } else {
+                    format!("Non-zero exit-code: {}", code)
+                };
+                testcase.failure = Some(TestError {
+                    message,
+                    error_type: String::from(ASSERTION_FAILED),
+                    body: body(),
+                });
+            } else if let Some(min_time) = options.min_time.filter(|min_time| elapsed < *min_time) {
+                testcase.failure = Some(TestError {
+                    message: format!(
+                        "Finished in {:.3}s, faster than the minimum of {}s",
+                        elapsed.as_secs_f64(),
+                        min_time.as_secs_f64()
+                    ),
+                    error_type: String::from("too-fast"),
+                    body: body(),
+                });
+            }
+            if let Some(reason) = &xfail {
+                expect_failure_of(&mut testcase, reason, body());
+            }
+            if options.parse_tap {
+                let (points, problem) = tap_cases(&testcase, &stdout, started_at);
+                let explained = points.iter().any(|point| point.failure.is_some());
+                match problem {
+                    Some(message) if testcase.error.is_none() => {
+                        testcase.failure = None;
+                        testcase.error = Some(TestError {
+                            message,
+                            error_type: String::from("tap"),
+                            body: body(),
+                        });
+                    }
+                    _ if explained => testcase.failure = None,
+                    _ => {}
+                }
+                case.points = points;
+            }
+            if matches!(testcase.status(), Status::Failed | Status::Errored) {
+                testcase.line = options.failure_line(&stderr);
+            }
+            case.stdout = stdout;
+            case.stderr = stderr;
+        }
+        Err(error) => {
+            let kind = error
+                .chain()
+                .find_map(|cause| cause.downcast_ref::<io::Error>())
+                .map(io::Error::kind);
+            testcase.error = Some(TestError {
+                message: error.to_string(),
+                error_type: io_error_type(kind),
+                body: String::new(),
+            });
+        }
+    }
+
+    case.testcase = testcase;
+    case
+}
+
+/**
+Turn the TAP test points in the `stdout` of the script of `testcase` into test cases with its
+classname, named after their descriptions. A test point takes the time since the one before it, or
+since the script `started`. A failing `# TODO` test point is skipped rather than failed.
+
+Also returns the problem with the stream as a whole, if it bailed out, has no test points or
+doesn't match its plan.
+*/
+fn tap_cases(
+    testcase: &TestCase,
+    stdout: &[LogLine],
+    started: DateTime<Utc>,
+) -> (Vec<TestCase>, Option<String>) {
+    let stream = tap::parse(stdout.iter().map(|line| line.1.as_str()));
+    let mut previous = started;
+    let points: Vec<TestCase> = stream
+        .points
+        .into_iter()
+        .map(|point| {
+            let at = stdout[point.line].0;
+            let time = (at - previous).to_std().unwrap_or_default().as_secs_f32();
+            previous = at;
+            let mut case = TestCase {
+                classname: testcase.classname.clone(),
+                name: match point.description.as_str() {
+                    "" => format!("test {}", point.number),
+                    description => description.to_string(),
+                },
+                time,
+                file: testcase.file.clone(),
+                properties: testcase.properties.clone(),
+                ..Default::default()
+            };
+            match point.directive {
+                Some(Directive::Skip(reason)) => case.skipped = Some(Skipped { message: reason }),
+                Some(Directive::Todo(reason)) if !point.ok => {
+                    case.skipped = Some(Skipped {
+                        message: format!("TODO {}", reason).trim_end().to_string(),
+                    })
+                }
+                _ if !point.ok => {
+                    case.failure = Some(TestError {
+                        message: format!("not ok {}", point.number),
+                        error_type: String::from("tap"),
+                        body: point.diagnostics.join(LINE_ENDING),
+                    })
+                }
+                _ => {}
+            }
+            case
+        })
+        .collect();
+
+    let problem = if let Some(reason) = stream.bail_out {
+        Some(format!("Bail out! {}", reason).trim_end().to_string())
+    } else if points.is_empty() {
+        Some(String::from("No TAP test points in the output"))
+    } else {
+        stream
+            .plan
+            .filter(|&plan| plan as usize != points.len())
+            .map(|plan| format!("Planned {} tests, but ran {}", plan, points.len()))
+    };
+    (points, problem)
+}
+
+/// The `error_type` of a script that couldn't be run: the `kind` of the IO error, like `NotFound`,
+/// or `IO error` for uncommon kinds and other errors.
+fn io_error_type(kind: Option<io::ErrorKind>) -> String {
+    use io::ErrorKind::*;
+    match kind {
+        Some(
+            kind @ (NotFound | PermissionDenied | TimedOut | BrokenPipe | Interrupted
+            | UnexpectedEof | InvalidData | InvalidInput | OutOfMemory | AlreadyExists),
+        ) => format!("{:?}", kind),
+        _ => String::from("IO error"),
+    }
+}
+
+/**
+Run the script of `test` until it passes, at most `1 + retry` times, waiting between the attempts.
+
+The reported time is the sum of the attempts, without the waits, and the captured output is that of
+every attempt. With retries enabled, an `attempts` property records how many runs it took, and a
+test case that passed on a retry keeps the failed attempts as its flaky failures and errors, each
+with its own output. No attempt starts after the `deadline` of the run.
+*/
+async fn run_with_retries(
+    test: &TestSpec,
+    options: &Options,
+    progress: &ProgressBar,
+    interrupt: &Interrupt,
+    deadline: Option<time::Instant>,
+) -> CaseRun {
+    let mut case = run_in_tmpdir(test, options, progress, interrupt, deadline).await;
+    let mut delay = options.retry_delay;
+    let mut attempts = 1;
+    while attempts <= options.retry
+        && case.unsuccessful() > 0
+        && case.aborted_by.is_none()
+        && deadline.is_none_or(|deadline| time::Instant::now() < deadline)
+    {
+        info!(
+            "Retrying {} in {}ms, attempt {} of {}",
+            test.script,
+            delay.as_millis(),
+            attempts + 1,
+            options.retry + 1
+        );
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = interrupt.interrupted() => break,
+        }
+        delay = time::Duration::try_from_secs_f64(delay.as_secs_f64() * options.retry_backoff)
+            .unwrap_or(time::Duration::MAX);
+
+        let mut retry = run_in_tmpdir(test, options, progress, interrupt, deadline).await;
+        retry.testcase.flaky_failures = std::mem::take(&mut case.testcase.flaky_failures);
+        retry.testcase.flaky_errors = std::mem::take(&mut case.testcase.flaky_errors);
+        let flaky = |problem: &TestError| FlakyFailure {
+            message: problem.message.clone(),
+            error_type: problem.error_type.clone(),
+            system_out: problem.body.clone(),
+        };
+        if let Some(error) = &case.testcase.error {
+            retry.testcase.flaky_errors.push(flaky(error));
+        } else if let Some(failure) = &case.testcase.failure {
+            retry.testcase.flaky_failures.push(flaky(failure));
+        }
+        retry.testcase.time += case.testcase.time;
+        retry.testcase.timestamp = case.testcase.timestamp.take();
+        case.stdout.append(&mut retry.stdout);
+        case.stderr.append(&mut retry.stderr);
+        retry.stdout = case.stdout;
+        retry.stderr = case.stderr;
+        case = retry;
+        attempts += 1;
+    }
+    // The flaky attempts only matter to a test case that passed in the end.
+    if case.testcase.status() != Status::Passed {
+        case.testcase.flaky_failures.clear();
+        case.testcase.flaky_errors.clear();
+    }
+    if options.retry > 0 {
+        case.testcase
+            .properties
+            .get_or_insert_with(Properties::default)
+            .properties
+            .push(Property {
+                name: String::from("attempts"),
+                value: attempts.to_string(),
+            });
+    }
+    case
+}
+
+/**
+Run the script of `test` between the `before_each` and `after_each` hooks, with the output of the
+hooks captured around the output of the script, or only the script without them.
+
+The hooks get the name of the test case and its script in `SHUNIT_TEST_NAME` and
+`SHUNIT_TEST_SCRIPT`, and the after-each hook also gets its status in `SHUNIT_TEST_STATUS`. The
+script doesn't run if the before-each hook fails, which makes the test case an error, as does an
+after-each hook that fails after the script passed. The after-each hook runs to completion, even
+after Ctrl-C. A script that is skipped by pattern runs without the hooks.
+*/
+async fn run_between_hooks(
+    test: &TestSpec,
+    options: &Options,
+    progress: &ProgressBar,
+    interrupt: &Interrupt,
+    deadline: Option<time::Instant>,
+) -> CaseRun {
+    let skipped = options
+        .skip
+        .iter()
+        .any(|pattern| pattern.is_match(&test.script));
+    if skipped || (options.before_each.is_none() && options.after_each.is_none()) {
+        return run_test_case(test, options, progress, interrupt, deadline, false).await;
+    }
+    let name = test
+        .name
+        .clone()
+        .unwrap_or_else(|| options.name_strategy.name(&test.script));
+    let mut env = vec![
+        (String::from("SHUNIT_TEST_NAME"), name),
+        (String::from("SHUNIT_TEST_SCRIPT"), test.script.clone()),
+    ];
+    let mut stdout = vec![];
+    let mut stderr = vec![];
+
+    let mut before_error = None;
+    if let Some(before) = &options.before_each {
+        let mut hook = run_hook(
+            "before-each",
+            before,
+            options,
+            progress,
+            interrupt,
+            deadline,
+            &env,
+        )
+        .await;
+        stdout.append(&mut hook.stdout);
+        stderr.append(&mut hook.stderr);
+        before_error = hook.testcase.error;
+    }
+    let mut case = match before_error {
+        None => run_test_case(test, options, progress, interrupt, deadline, false).await,
+        Some(error) => {
+            warn!("Not running {}: {}", test.script, error.message);
+            let mut case = not_run(test, options);
+            case.testcase.error = Some(error);
+            case
+        }
+    };
+    stdout.append(&mut case.stdout);
+    stderr.append(&mut case.stderr);
+
+    if let Some(after) = &options.after_each {
+        let status = match case.testcase.status() {
+            Status::Passed => "passed",
+            Status::Failed => "failed",
+            Status::Errored => "errored",
+            Status::Skipped => "skipped",
+        };
+        env.push((String::from("SHUNIT_TEST_STATUS"), status.to_string()));
+        let mut hook = run_hook(
+            "after-each",
+            after,
+            options,
+            progress,
+            &Interrupt::default(),
+            None,
+            &env,
+        )
+        .await;
+        stdout.append(&mut hook.stdout);
+        stderr.append(&mut hook.stderr);
+        match hook.testcase.error {
+            Some(error) if case.testcase.status() == Status::Passed => {
+                case.testcase.error = Some(error);
+            }
+            Some(error) => warn!("{}", error.message),
+            None => {}
+        }
+    }
+    case.stdout = stdout;
+    case.stderr = stderr;
+    case
+}
+
+/**
+Run the script of `test` between its hooks in a fresh temporary directory, which `TMPDIR` and
+`SHUNIT_TMPDIR` point to. The directory is deleted afterwards, unless the test case failed or had an
+error and `keep_tmp` is set, in which case its path is reported as a `tmpdir` property.
+
+The `env` of the options is set after these variables, so it can still point `TMPDIR` elsewhere.
+*/
+async fn run_in_tmpdir(
+    test: &TestSpec,
+    options: &Options,
+    progress: &ProgressBar,
+    interrupt: &Interrupt,
+    deadline: Option<time::Instant>,
+) -> CaseRun {
+    let tmpdir = match create_tmpdir() {
+        Ok(tmpdir) => tmpdir,
+        Err(err) => {
+            warn!("Failed to create a temporary directory: {}", err);
+            let mut case = not_run(test, options);
+            case.testcase.error = Some(TestError {
+                message: String::from("Failed to create a temporary directory"),
+                error_type: io_error_type(Some(err.kind())),
+                body: err.to_string(),
+            });
+            return case;
+        }
+    };
+    let path = tmpdir.to_string_lossy().into_owned();
+    let mut tmpdir_options = options.clone();
+    tmpdir_options.env.splice(
+        0..0,
+        [
+            (String::from("TMPDIR"), path.clone()),
+            (String::from("SHUNIT_TMPDIR"), path.clone()),
+        ],
+    );
+    let mut case = run_between_hooks(test, &tmpdir_options, progress, interrupt, deadline).await;
+
+    let failed = matches!(case.testcase.status(), Status::Failed | Status::Errored);
+    if options.keep_tmp && failed {
+        info!(
+            "Keeping the temporary directory of {}: {}",
+            test.script, path
+        );
+        case.testcase
+            .properties
+            .get_or_insert_with(Properties::default)
+            .properties
+            .push(Property {
+                name: String::from("tmpdir"),
+                value: path,
+            });
+    } else if let Err(err) = fs::remove_dir_all(&tmpdir) {
+        warn!("Failed to remove the temporary directory {}: {}", path, err);
+    }
+    case
+}
+
+/// Create an empty directory for a script, in the temporary directory of shunit, like
+/// `/tmp/shunit-1234-5`.
+fn create_tmpdir() -> io::Result<path::PathBuf> {
+    static CREATED: AtomicUsize = AtomicUsize::new(0);
+    loop {
+        let n = CREATED.fetch_add(1, Ordering::Relaxed);
+        let tmpdir = env::temp_dir().join(format!("shunit-{}-{}", process::id(), n));
+        match fs::create_dir(&tmpdir) {
+            // A directory left behind by an earlier process with the same id is skipped.
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            result => return result.map(|()| tmpdir),
+        }
+    }
+}
+
+/**
+Run a setup, teardown, before-each or after-each hook as a test case named after its `kind`, with
+the variables of `env` set on top of the ones of the options.
+
+A hook passes when it exits with 0, regardless of `expect_exit`, `skip_exit`, `expect_failure`,
+`xfail` and `min_time`, and errors otherwise. It is stopped at the `deadline` of the run, if it has one.
+*/
+async fn run_hook(
+    kind: &str,
+    script: &str,
+    options: &Options,
+    progress: &ProgressBar,
+    interrupt: &Interrupt,
+    deadline: Option<time::Instant>,
+    env: &[(String, String)],
+) -> CaseRun {
+    let mut hook_options = Options {
+        expect_exit: vec![],
+        skip_exit: None,
+        expect_failure: false,
+        abort_on_signal: false,
+        skip_non_executable: false,
+        skip: vec![],
+        xfail: vec![],
+        min_time: None,
+        parse_tap: false,
+        ..options.clone()
+    };
+    hook_options.env.extend(env.iter().cloned());
+    let mut hook = run_test_case(
+        &TestSpec::from(script),
+        &hook_options,
+        progress,
+        interrupt,
+        deadline,
+        true,
+    )
+    .await;
+    hook.testcase.name = kind.to_string();
+    if let Some(failure) = hook.testcase.failure.take() {
+        hook.testcase.error = Some(TestError {
+            error_type: String::from("Hook failed"),
+            ..failure
+        });
+    }
+    if let Some(error) = &mut hook.testcase.error {
+        error.message = format!("The {} {} failed: {}", kind, script, error.message);
+    }
+    hook
+}
+
+/// The name of the machine the scripts run on, for the `hostname` of the suite.
+#[cfg(unix)]
+fn hostname() -> String {
+    let mut name = [0u8; 256];
+    // SAFETY: the buffer outlives the call, which writes at most its length.
+    let result = unsafe { libc::gethostname(name.as_mut_ptr().cast(), name.len()) };
+    let length = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+    match String::from_utf8_lossy(&name[..length]) {
+        name if result == 0 && !name.is_empty() => name.into_owned(),
+        _ => String::from("localhost"),
+    }
+}
+
+#[cfg(not(unix))]
+fn hostname() -> String {
+    env::var("COMPUTERNAME").unwrap_or_else(|_| String::from("localhost"))
+}
+
+/**
+Find the file a script argument names: the path itself, or for a bare name that isn't a file in the
+current directory, like `my-test-tool`, the first executable of that name on the `PATH`, the way a
+shell would run it.
+*/
+pub fn resolve_script(name: &str) -> io::Result<path::PathBuf> {
+    match fs::canonicalize(name) {
+        Err(err) if is_bare_name(name) => which::which(name)
+            .ok()
+            .and_then(|path| fs::canonicalize(path).ok())
+            .ok_or(err),
+        result => result,
+    }
+}
+
+/// Whether `name` has no path separator, so it is run from the `PATH` rather than as a path.
+fn is_bare_name(name: &str) -> bool {
+    !name.contains('/') && !name.contains(path::MAIN_SEPARATOR)
+}
+
+/// Whether the file at `path` has any execute permission bit set.
+#[cfg(unix)]
+fn is_executable(path: &path::Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|metadata| metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_path: &path::Path) -> bool {
+    true
+}
+
+/**
+Read the value of a `# DIRECTIVE VALUE` comment of a script, like `# shunit-timeout: 30`.
+
+Only the leading comment block of the script is scanned, so the directive must appear before the
+first command.
+*/
+fn header_directive(path: &path::Path, directive: &str) -> Option<String> {
+    use std::io::BufRead;
+    let file = fs::File::open(path).ok()?;
+    io::BufReader::new(file)
+        .split(b'\n')
+        .map_while(Result::ok)
+        .map(|line| String::from_utf8_lossy(&line).trim().to_string())
+        .take_while(|line| line.is_empty() || line.starts_with('#'))
+        .find_map(|line| {
+            let comment = line.trim_start_matches('#').trim_start();
+            comment
+                .strip_prefix(directive)
+                .map(|value| value.trim().to_string())
+        })
+}
+
+/// Read the timeout a script sets for itself with a `# shunit-timeout: SECONDS` comment. Returns
+/// `None` if there is no valid directive.
+fn timeout_directive(path: &path::Path) -> Option<time::Duration> {
+    let value = header_directive(path, TIMEOUT_DIRECTIVE)?;
+    match parse_seconds(&value) {
+        Ok(timeout) => Some(timeout),
+        Err(err) => {
+            warn!("Ignoring the timeout in {}: {}", path.display(), err);
+            None
+        }
+    }
+}
+
+/**
+Turn the result of a script that is expected to fail, for `reason`, around: a failure passes, noted
+in an `xfail` property, and a pass is an unexpected pass, `XPASS`, with the `body` of the output.
+Errors and skips stay as they are.
+*/
+fn expect_failure_of(testcase: &mut TestCase, reason: &str, body: String) {
+    match testcase.status() {
+        Status::Failed => {
+            let failure = testcase
+                .failure
+                .take()
+                .expect("a failed test case has a failure");
+            testcase
+                .properties
+                .get_or_insert_with(Properties::default)
+                .properties
+                .push(Property {
+                    name: String::from("xfail"),
+                    value: failure.message,
+                });
+        }
+        Status::Passed => {
+            let reason = match reason {
+                "" => String::new(),
+                reason => format!(", expected to fail: {}", reason),
+            };
+            testcase.failure = Some(TestError {
+                message: format!("Unexpected pass (XPASS){}", reason),
+                error_type: String::from("xpass"),
+                body,
+            });
+        }
+        Status::Errored | Status::Skipped => {}
+    }
+}
+
+/// Parse a duration given in seconds, like `90` or `0.5`.
+pub fn parse_seconds(s: &str) -> anyhow::Result<time::Duration> {
+    let seconds: f64 = s
+        .parse()
+        .map_err(|_| anyhow!("Invalid number of seconds: {}", s))?;
+    time::Duration::try_from_secs_f64(seconds)
+        .map_err(|_| anyhow!("Invalid number of seconds: {}", s))
+}
+
+/**
+Parse a `##shunit-KEY: VALUE` marker line into its key and value.
+
+Returns `None` for ordinary output, including lines that start with the prefix but aren't
+well-formed markers.
+*/
+fn parse_marker(line: &str) -> Option<(String, String)> {
+    let (key, value) = line.strip_prefix(MARKER_PREFIX)?.split_once(':')?;
+    let valid_key = !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    valid_key.then(|| (key.to_string(), value.trim().to_string()))
+}
+
+/// The value of the last marker named `key`.
+fn marker<'a>(markers: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    markers
+        .iter()
+        .rev()
+        .find(|(name, _)| name == key)
+        .map(|(_, value)| value.as_str())
+}
+
+/// The value of the last `name` or `classname` marker of `script`, unless it is empty.
+fn identity_marker(markers: &[(String, String)], key: &str, script: &str) -> Option<String> {
+    let value = marker(markers, key)?;
+    if value.is_empty() {
+        warn!("Ignoring empty {} from {}", key, script);
+        return None;
+    }
+    Some(value.to_string())
+}
+
+/// Format a byte count in the largest binary unit that represents it exactly.
+fn format_bytes(bytes: u64) -> String {
+    const MIB: u64 = 1024 * 1024;
+    const KIB: u64 = 1024;
+    match bytes {
+        0 => String::from("0 bytes"),
+        _ if bytes.is_multiple_of(MIB) => format!("{} MiB", bytes / MIB),
+        _ if bytes.is_multiple_of(KIB) => format!("{} KiB", bytes / KIB),
+        _ => format!("{} bytes", bytes),
+    }
+}
+
+/// Merge two log streams and sort the contents,
+pub fn join_and_sort(stdout: Vec<LogLine>, stderr: Vec<LogLine>) -> Vec<LogLine> {
+    let stdout = stdout[..].as_ref();
+    let stderr = stderr[..].as_ref();
+    let mut result = [stdout, stderr].concat();
+    result.sort_by_key(|line| line.0);
+    result
+}
+
+/**
+Join log messages so there is one message per line ending with a new line.
+
+Log messages are interleaved so if a line was sliced into two messages, they become a single message,
+with the timestamp from the first message.
+
+- `messages` a vector of log messages and timestamps sorted in ascending order.
+*/
+pub fn join_log_lines(messages: &[(DateTime<Utc>, String)]) -> Vec<LogLine> {
+    let mut joined_messages: Vec<LogLine> = vec![];
+    let mut line: String = String::new();
+    let mut first_ts: Option<DateTime<Utc>> = None;
+
+    for (index, (ts, message)) in messages.iter().enumerate() {
+        line.push_str(message);
+        if first_ts.is_none() {
+            first_ts = Some(*ts);
+        }
+        if message.ends_with('\n') || index == (messages.len() - 1) {
+            joined_messages.push((first_ts.unwrap(), line));
+            first_ts = None;
+            line = String::new();
+        }
+    }
+
+    joined_messages
+}
+
+/**
+Read the next line from `reader`, without the line ending.
+
+Unlike `AsyncBufReadExt::lines`, invalid UTF-8 doesn't fail the read, but is replaced with
+`U+FFFD REPLACEMENT CHARACTER`. Returns `None` at the end of the stream.
+*/
+async fn next_line_lossy<R: AsyncBufRead + Unpin>(reader: &mut R) -> io::Result<Option<String>> {
+    let mut buffer = vec![];
+    if reader.read_until(b'\n', &mut buffer).await? == 0 {
+        return Ok(None);
+    }
+    if buffer.ends_with(b"\n") {
+        buffer.pop();
+        if buffer.ends_with(b"\r") {
+            buffer.pop();
+        }
+    }
+    Ok(Some(String::from_utf8_lossy(&buffer).into_owned()))
+}
+
+/// The output streams of a script.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    /// The name of the stream in events.
+    fn name(&self) -> &'static str {
+        match self {
+            Stream::Stdout => "stdout",
+            Stream::Stderr => "stderr",
+        }
+    }
+
+    /// The tag of the stream in prefixed output.
+    fn tag(&self) -> &'static str {
+        match self {
+            Stream::Stdout => "[out]",
+            Stream::Stderr => "[err]",
+        }
+    }
+
+    /// Print a forwarded line on the console stream this one goes to.
+    fn print(&self, text: &str) {
+        match self {
+            Stream::Stdout => println!("{}", text),
+            Stream::Stderr => eprintln!("{}", text),
+        }
+    }
+}
+
+/// Forward the lines read from `reader` to `sender`, stamped with the time they were read.
+fn read_lines<R>(
+    reader: R,
+    stream: Stream,
+    sender: mpsc::UnboundedSender<(Stream, LogLine)>,
+) -> JoinHandle<io::Result<()>>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut reader = BufReader::new(reader);
+        while let Some(line) = next_line_lossy(&mut reader).await? {
+            if sender.send((stream, (Utc::now(), line))).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    })
+}
+
+/**
+Run a single script, forwarding its output while capturing it.
+
+- `test` the script, its arguments and its working directory.
+- `timeout` kills the script if it runs longer.
+- `options.idle_timeout` kills the script if it goes that long without writing a line.
+- `progress` is suspended while output is forwarded, so the two don't garble each other.
+- `options.group_output` holds the forwarded output back until the script exits.
+- `interrupt` kills the script if it is still running a grace period after Ctrl-C.
+*/
+// https://stackoverflow.com/questions/68173678/read-childstdout-without-blocking
+// https://stackoverflow.com/questions/34611742/how-do-i-read-the-output-of-a-child-process-without-blocking-in-rust
+pub async fn run_script(
+    test: &TestSpec,
+    options: &Options,
+    timeout: Option<time::Duration>,
+    progress: &ProgressBar,
+    interrupt: &Interrupt,
+) -> ScriptResult {
+    let program = test.script.as_str();
+    // A relative path would be ambiguous with another working directory, and a bare name of a
+    // local file would be looked up on the `PATH` instead.
+    let script = match &test.cwd {
+        Some(_) => resolve_script(program)?.into_os_string(),
+        None if is_bare_name(program) && path::Path::new(program).is_file() => {
+            path::Path::new(".").join(program).into_os_string()
+        }
+        None => program.into(),
+    };
+    // A script runs in a process group of its own, so that a timeout kills the processes it
+    // started too. One that gets the terminal's stdin stays in the foreground group to read it.
+    let group = cfg!(unix) && !options.stdin_inherit;
+    // The command running the script, or the `interpreter` of its shebang with the script.
+    let command = |interpreter: Option<&Shebang>| {
+        let mut command = match interpreter {
+            Some((interpreter, args)) => {
+                let mut command = Command::new(interpreter);
+                command.args(args).arg(&script);
+                command
+            }
+            None => Command::new(&script),
+        };
+        if let Some(cwd) = &test.cwd {
+            command.current_dir(cwd);
+        }
+        command.args(&test.args);
+        if options.env_clear {
+            command.env_clear();
+        } else if options.clean_env {
+            command.env_clear().envs(options.inherited_env());
+        }
+        command
+            .envs(options.env.iter().map(|(key, value)| (key, value)))
+            .stdin(if options.stdin_inherit {
+                process::Stdio::inherit()
+            } else {
+                process::Stdio::null()
+            })
+            .stdout(process::Stdio::piped())
+            .stderr(process::Stdio::piped());
+        if group {
+            own_process_group(&mut command);
+        }
+        command
+    };
+    let mut child = match command(None).spawn() {
+        Ok(child) => child,
+        Err(err) if options.fallback_shebang && is_exec_error(&err) => {
+            let Some(shebang) = read_shebang(path::Path::new(program)) else {
+                return Err(err.into());
+            };
+            info!("Running {} with the interpreter {}", program, shebang.0);
+            command(Some(&shebang)).spawn()?
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow!("No stdout handle?"))?;
+
+    let stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| anyhow!("No stdout handle?"))?;
+
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let stdout_reader = read_lines(stdout, Stream::Stdout, sender.clone());
+    let stderr_reader = read_lines(stderr, Stream::Stderr, sender);
+
+    let mut stdout_vector: Vec<LogLine> = vec![];
+    let mut stderr_vector: Vec<LogLine> = vec![];
+    let mut markers = vec![];
+    let mut captured: u64 = 0;
+    let mut truncated = false;
+
+    // Keep the captured output within `--max-output`, leaving a marker where it was cut off.
+    let mut capture = |vector: &mut Vec<LogLine>, line: LogLine| {
+        if truncated {
+            return;
+        }
+        captured += line.1.len() as u64 + 1;
+        if captured <= options.max_output {
+            vector.push(line);
+        } else {
+            truncated = true;
+            let marker = format!(
+                "... output truncated at {} ...",
+                format_bytes(options.max_output)
+            );
+            vector.push((line.0, marker));
+        }
+    };
+
+    // The line as it is forwarded to the console, with `--prefix` and `--color` applied. With several
+    // jobs the prefix names the script too, as the lines of the scripts interleave.
+    let color = options.color.enabled(io::stderr().is_terminal());
+    let forwarded = |stream: Stream, line: &LogLine| {
+        let text = if options.prefix {
+            let timestamp = line.0.to_rfc3339_opts(SecondsFormat::Millis, true);
+            match options.jobs {
+                0 | 1 => format!("{} {} {}", timestamp, stream.tag(), line.1),
+                _ => format!("{} {} {}: {}", timestamp, stream.tag(), program, line.1),
+            }
+        } else {
+            line.1.clone()
+        };
+        match stream {
+            Stream::Stderr if color => color::red(&text),
+            _ => text,
+        }
+    };
+    // With `group_output` the lines are held back, to be printed as a block once the script exits.
+    let mut grouped: Vec<(Stream, String)> = vec![];
+    let mut forward = |stream: Stream, line: &LogLine| {
+        options.emit(&Event::TestOutputLine {
+            script: &test.script,
+            stream: stream.name(),
+            line: &line.1,
+        });
+        if !options.forward_output {
+            return;
+        }
+        let text = forwarded(stream, line);
+        if options.group_output {
+            grouped.push((stream, text));
+        } else {
+            progress.suspend(|| stream.print(&text));
+        }
+    };
+
+    // After Ctrl-C the script gets a grace period to finish on its own.
+    let grace = async {
+        interrupt.interrupted().await;
+        tokio::time::sleep(INTERRUPT_GRACE).await;
+    };
+    tokio::pin!(grace);
+    let deadline = async {
+        match timeout {
+            Some(timeout) => tokio::time::sleep(timeout).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(deadline);
+    // Restarted by every line the script writes, on either stream.
+    let idle = tokio::time::sleep(options.idle_timeout.unwrap_or_default());
+    tokio::pin!(idle);
+    // The `timeout_signal` escalates to a kill if the script is still running after `kill_grace`.
+    let escalation = tokio::time::sleep(options.kill_grace);
+    tokio::pin!(escalation);
+    let mut escalating = false;
+    let mut killed = false;
+    let mut timed_out = false;
+    let mut idle_timed_out = false;
+    let mut force_killed = false;
+    let mut passed_on = false;
+
+    loop {
+        tokio::select! {
+            message = receiver.recv() => {
+                let Some((stream, line)) = message else { break };
+                if let Some(idle_timeout) = options.idle_timeout {
+                    idle.as_mut().reset(tokio::time::Instant::now() + idle_timeout);
+                }
+                match stream {
+                    Stream::Stdout => match parse_marker(&line.1) {
+                        Some(marker) => markers.push(marker),
+                        None => {
+                            forward(Stream::Stdout, &line);
+                            capture(&mut stdout_vector, line);
+                        }
+                    },
+                    Stream::Stderr => {
+                        forward(Stream::Stderr, &line);
+                        capture(&mut stderr_vector, line);
+                    }
+                }
+            }
+            // Outside the foreground group the script doesn't see the Ctrl-C, so it's passed on.
+            _ = interrupt.interrupted(), if group && !passed_on => {
+                signaled(send_signal(&mut child, INTERRUPT, group), program);
+                passed_on = true;
+            }
+            _ = &mut grace, if !killed => {
+                warn!("Killing {} after the grace period", program);
+                signaled(kill(&mut child, group), program);
+                killed = true;
+            }
+            _ = &mut deadline, if !killed => {
+                warn!("Stopping {} after the timeout", program);
+                escalating = signaled(stop(&mut child, options, group), program);
+                escalation.as_mut().reset(tokio::time::Instant::now() + options.kill_grace);
+                killed = true;
+                timed_out = true;
+            }
+            _ = &mut idle, if options.idle_timeout.is_some() && !killed => {
+                warn!("Stopping {} after no output for the idle timeout", program);
+                escalating = signaled(stop(&mut child, options, group), program);
+                escalation.as_mut().reset(tokio::time::Instant::now() + options.kill_grace);
+                killed = true;
+                idle_timed_out = true;
+            }
+            _ = &mut escalation, if escalating => {
+                warn!("Killing {}, which is still running after the kill grace", program);
+                signaled(kill(&mut child, group), program);
+                escalating = false;
+                force_killed = true;
+            }
+        }
+    }
+
+    if !grouped.is_empty() {
+        progress.suspend(|| {
+            println!("== {} ==", program);
+            for (stream, text) in &grouped {
+                stream.print(text);
+            }
+        });
+    }
+
+    stdout_reader.await??;
+    stderr_reader.await??;
+    let (exit_code, resources) = if options.resource_stats {
+        resources::wait_with_usage(child).await?
+    } else {
+        (child.wait().await?, None)
+    };
+    Ok(ScriptOutput {
+        status: exit_code,
+        stdout: stdout_vector,
+        stderr: stderr_vector,
+        markers,
+        timed_out,
+        idle_timed_out,
+        force_killed,
+        resources,
+    })
+}
+
+/// The interpreter and its arguments named by the `#!` line of a script.
+type Shebang = (String, Vec<String>);
+
+/// Parse a `#!/usr/bin/env python3 -u` line into its interpreter and arguments.
+fn parse_shebang(line: &str) -> Option<Shebang> {
+    let mut words = line.strip_prefix("#!")?.split_whitespace();
+    let interpreter = words.next()?.to_string();
+    Some((interpreter, words.map(String::from).collect()))
+}
+
+/// Read the shebang of the script at `path`, if its first line is one.
+fn read_shebang(path: &path::Path) -> Option<Shebang> {
+    let file = fs::File::open(path).ok()?;
+    let line = io::BufRead::lines(io::BufReader::new(file)).next()?.ok()?;
+    parse_shebang(&line)
+}
+
+/// Whether running a script failed because it can't be executed itself, rather than being missing.
+#[cfg(unix)]
+fn is_exec_error(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::PermissionDenied || err.raw_os_error() == Some(libc::ENOEXEC)
+}
+
+#[cfg(not(unix))]
+fn is_exec_error(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::PermissionDenied
+}
+
+/// Stop a timed out script with the `timeout_signal`, or kill it, with `group` together with the
+/// processes it started. Returns whether it was only signaled, and may still have to be killed.
+fn stop(child: &mut tokio::process::Child, options: &Options, group: bool) -> io::Result<bool> {
+    match options.timeout_signal {
+        Some(signal) if !is_kill(signal) => send_signal(child, signal, group).map(|_| true),
+        _ => kill(child, group).map(|_| false),
+    }
+}
+
+/**
+The result of signaling a script, or the default if that failed, which is only logged: the script
+most likely exited just before, e.g. right at its timeout, and is still waited for and reported.
+*/
+fn signaled<T: Default>(result: io::Result<T>, program: &str) -> T {
+    result.unwrap_or_else(|err| {
+        warn!("Couldn't signal {}: {}", program, err);
+        T::default()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        format_bytes, io_error_type, join_log_lines, parse_marker, parse_shebang,
+        timeout_directive, LogLine,
+    };
+    use chrono::DateTime;
+    use std::{io, path::Path, str::FromStr, time::Duration};
+
+    #[test]
+    fn test_join_log_lines() {
+        let ts1 = DateTime::from_str("2022-04-03 10:13:48 UTC").unwrap();
+        let ts2 = DateTime::from_str("2022-04-03 10:13:49 UTC").unwrap();
+        let ts3 = DateTime::from_str("2022-04-03 10:13:50 UTC").unwrap();
+        let messages: Vec<LogLine> = vec![
+            (ts1, "A".to_string()),
+            (ts2, "B\n".to_string()),
+            (ts3, "C".to_string()),
+        ];
+        let joined = join_log_lines(&messages);
+        assert_eq!(joined.len(), 2);
+        assert_eq!(joined[0], (ts1, "AB\n".to_string()));
+        assert_eq!(joined[1], (ts3, "C".to_string()));
+    }
+
+    #[test]
+    fn test_io_error_type() {
+        assert_eq!(io_error_type(Some(io::ErrorKind::NotFound)), "NotFound");
+        assert_eq!(
+            io_error_type(Some(io::ErrorKind::PermissionDenied)),
+            "PermissionDenied"
+        );
+        assert_eq!(io_error_type(Some(io::ErrorKind::Other)), "IO error");
+        assert_eq!(io_error_type(None), "IO error");
+    }
+
+    #[test]
+    fn test_parse_shebang() {
+        assert_eq!(
+            parse_shebang("#!/bin/bash"),
+            Some(("/bin/bash".to_string(), vec![]))
+        );
+        assert_eq!(
+            parse_shebang("#! /usr/bin/env python3 -u"),
+            Some((
+                "/usr/bin/env".to_string(),
+                vec!["python3".to_string(), "-u".to_string()]
+            ))
+        );
+        assert_eq!(parse_shebang("#!"), None);
+        assert_eq!(parse_shebang("echo hello"), None);
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(10 * 1024 * 1024), "10 MiB");
+        assert_eq!(format_bytes(2048), "2 KiB");
+        assert_eq!(format_bytes(1000), "1000 bytes");
+    }
+
+    #[test]
+    fn test_timeout_directive() {
+        assert_eq!(
+            timeout_directive(Path::new("tests/fixtures/timeout_directive.sh")),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(timeout_directive(Path::new("test/im_ok.sh")), None);
+        assert_eq!(
+            timeout_directive(Path::new("tests/fixtures/late_directive.sh")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_marker() {
+        assert_eq!(
+            parse_marker("##shunit-assertions: 12"),
+            Some(("assertions".to_string(), "12".to_string()))
+        );
+        assert_eq!(
+            parse_marker("##shunit-assertions:12"),
+            Some(("assertions".to_string(), "12".to_string()))
+        );
+        assert_eq!(parse_marker("##shunit-assertions 12"), None);
+        assert_eq!(parse_marker("##shunit-: 12"), None);
+        assert_eq!(parse_marker("  ##shunit-assertions: 12"), None);
+    }
+}
@@ -1,280 +1,1392 @@
-use crate::model::*;
-use chrono::DateTime;
-use chrono::Utc;
-use std::{env, fs, io, path, process, time};
+use crate::config::Config;
+use flate2::{write::GzEncoder, Compression};
+use shunit::events::EventSink;
+use shunit::signal::parse_signal;
+use shunit::{
+    render_junit, ClassnameStrategy, ColorChoice, Format, Interrupt, MergeProperties, NameStrategy,
+    Property, SortOrder, TestCase, TestSpec, TestSuite, TestSuites, UniqueNames,
+};
+use std::collections::HashMap;
+use std::io::Write;
+use std::{fs, io, path, process, time};
 use structopt::StructOpt;
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::process::Command;
 
 #[macro_use]
 extern crate anyhow;
 
 #[macro_use]
-extern crate yaserde_derive;
+extern crate log;
 
-#[cfg(windows)]
-const LINE_ENDING: &str = "\r\n";
-#[cfg(not(windows))]
-const LINE_ENDING: &str = "\n";
+/// Exit code used when a test case failed.
+const EXIT_FAILURES: i32 = 1;
 
-mod model;
+/// Exit code used when a script couldn't be run, the run was aborted or a report couldn't be read
+/// or written.
+const EXIT_INFRA_ERROR: i32 = 2;
 
-type LogLine = (DateTime<Utc>, String);
+/// Exit code used when the run was interrupted with Ctrl-C.
+const EXIT_INTERRUPTED: i32 = 3;
 
-type ScriptResult = anyhow::Result<(process::ExitStatus, Vec<LogLine>, Vec<LogLine>)>;
+/// Exit code used for invalid arguments, configuration files and manifests.
+const EXIT_USAGE: i32 = 4;
+
+/// Property name patterns redacted by `--redact-defaults`.
+const DEFAULT_REDACT_PATTERNS: [&str; 4] = [".*SECRET.*", ".*TOKEN.*", ".*PASSWORD.*", ".*KEY.*"];
+
+/// The value of redacted properties.
+const REDACTED: &str = "***";
+
+/// The most runs per script the `--matrix` may expand into.
+const MAX_MATRIX_RUNS: usize = 1000;
+
+mod config;
+mod env_file;
+mod expand;
+mod manifest;
+mod params;
 
 #[derive(StructOpt, Debug)]
 #[structopt()]
 struct Opt {
-    // /// Silence all output
-    // #[structopt(short = "q", long)]
-    // quiet: bool,
-
-    // /// Verbose mode (-v, -vv, -vvv, -vvvv). The levels are warnings, informational, debugging, and trace message.
-    // #[structopt(short = "v", long, parse(from_occurrences))]
-    // verbose: usize,
-
-    // /// Timestamp (sec, ms, ns, none)
-    // #[structopt(short = "t", long = "timestamp")]
-    // ts: Option<stderrlog::Timestamp>,
-    /// An optional target file to write the result to.
-    #[structopt(short = "o", long)]
-    output: Option<String>,
-
-    /// Test scripts.
+    /// A configuration file with defaults for the options [default: the nearest shunit.toml].
+    #[structopt(long, value_name = "PATH")]
+    config: Option<String>,
+
+    /// Silence the logs, the summary and the forwarded output of the scripts. Reports are still written, to std out
+    /// without `--output`
+    #[structopt(short = "q", long)]
+    quiet: bool,
+
+    /// Like `--quiet`, and only write the reports of `--output` to files, never to std out
+    #[structopt(long)]
+    silent: bool,
+
+    /// Verbose mode (-v, -vv, -vvv, -vvvv). The levels are warnings, informational, debugging, and trace message.
+    #[structopt(short = "v", long, parse(from_occurrences))]
+    verbose: usize,
+
+    /// Timestamp (sec, ms, ns, none)
+    #[structopt(short = "t", long = "timestamp")]
+    ts: Option<stderrlog::Timestamp>,
+
+    /// Send this signal to a script that times out, instead of killing it right away, and only kill
+    /// it if it is still running after `--kill-grace`. Only supported on Unix.
+    #[structopt(long, value_name = "SIGNAL", parse(try_from_str = parse_signal))]
+    timeout_signal: Option<i32>,
+
+    /// How long a script may take to exit after the `--timeout-signal` before it is killed.
+    #[structopt(long, value_name = "SECONDS", default_value = "5", parse(try_from_str = shunit::parse_seconds))]
+    kill_grace: time::Duration,
+
+    /// Leave out the `timestamp` of when each script started from its test case.
+    #[structopt(long)]
+    no_testcase_timestamps: bool,
+
+    /// Run a script that can't be executed, like one without execute permission or on a noexec
+    /// mount, with the interpreter of its `#!` line.
+    #[structopt(long)]
+    fallback_shebang: bool,
+
+    /// Let the scripts read the std in of shunit, e.g. for interactive use. By default they get an
+    /// empty std in, so a script that reads it doesn't block or consume the input of a pipeline.
+    #[structopt(long)]
+    stdin_inherit: bool,
+
+    /// When to color the std err lines forwarded from the scripts. `auto` colors them on a terminal,
+    /// unless `NO_COLOR` is set.
+    #[structopt(long, value_name = "WHEN", default_value = "auto", possible_values = &ColorChoice::VARIANTS)]
+    color: ColorChoice,
+
+    /// Don't show a progress bar, even when std err is a terminal.
+    #[structopt(long)]
+    no_progress: bool,
+
+    /// Print the output of every script as a block under a `== script ==` header once it exits, instead of line by
+    /// line as it arrives.
+    #[structopt(long)]
+    group_output: bool,
+
+    /// Report std out and std err together in `system-out`, in the order they were written, like a terminal shows
+    /// them, and leave `system-err` empty.
+    #[structopt(long)]
+    combine_output: bool,
+
+    /// Parse the TAP a script prints to std out, and report a test case per test point instead of one per script.
+    #[structopt(long)]
+    parse_tap: bool,
+
+    /// A file to write the result to, optionally prefixed with its format, like `markdown:summary.md` or
+    /// `json=report.json`. May be repeated.
+    #[structopt(short = "o", long, value_name = "[FORMAT:]PATH", number_of_values = 1)]
+    output: Vec<OutputTarget>,
+
+    /// The name of the test suite in the report [default: the current directory].
+    #[structopt(long, value_name = "NAME")]
+    suite_name: Option<String>,
+
+    /// Add a property (KEY=VALUE) to the suite, like `git.sha=$SHA`, instead of an environment variable of the same
+    /// name. May be repeated.
+    #[structopt(
+        short = "D",
+        long,
+        value_name = "KEY=VALUE",
+        parse(try_from_str = parse_key_val),
+        number_of_values = 1
+    )]
+    property: Vec<(String, String)>,
+
+    /// Also write the totals, the start and end timestamps and the names of the failing test cases to this JSON
+    /// file, e.g. for a status badge.
+    #[structopt(long, value_name = "PATH")]
+    summary_json: Option<String>,
+
+    /// Also write a compact Markdown table of the test cases, with the first error line of each, to this file, e.g.
+    /// `$GITHUB_STEP_SUMMARY`. Appended to with `--append`.
+    #[structopt(long, value_name = "PATH")]
+    summary_md: Option<String>,
+
+    /// Annotate every failed test case in GitHub Actions, with an `::error` workflow command on std err that links
+    /// to its script. `::warning` with `--warn-only`.
+    #[structopt(long)]
+    github_annotations: bool,
+
+    /// Also write the result as a standalone HTML page to this file, like `--output html:PATH`.
+    #[structopt(long, value_name = "PATH")]
+    html_report: Option<String>,
+
+    /// Also write the result as Allure results to this directory: a JSON file per test case, one for the suite, and
+    /// the captured output as attachments. The results of earlier runs in it are kept.
+    #[structopt(long, value_name = "DIR")]
+    allure_dir: Option<String>,
+
+    /// Write the events of the run as JSON lines while it runs: the suite and every script starting and ending, and
+    /// every line of output. To a file, or an open file descriptor given by its number, like `3`.
+    #[structopt(long, value_name = "FILE|FD")]
+    events: Option<String>,
+
+    /// The format of the result.
+    #[structopt(long, default_value = "junit", possible_values = &Format::VARIANTS)]
+    format: Format,
+
+    /// Set an environment variable (KEY=VALUE) for every script. May be repeated.
+    #[structopt(long = "env", parse(try_from_str = parse_key_val), number_of_values = 1)]
+    env: Vec<(String, String)>,
+
+    /// A file of KEY=VALUE lines, like a `.env` file, with environment variables for every script. The `--env`
+    /// entries take precedence. May be repeated.
+    #[structopt(long, value_name = "PATH", number_of_values = 1)]
+    env_file: Vec<String>,
+
+    /// Run every script once per value (VAR=A,B,C or VAR=A|B|C), with the variable set to it.
+    /// Several `--matrix` run every combination of their values.
+    #[structopt(long, value_name = "VAR=VALUES", parse(try_from_str = parse_matrix), number_of_values = 1)]
+    matrix: Vec<(String, Vec<String>)>,
+
+    /// Run all the scripts N times over, e.g. to measure how often a flaky one fails. Each run of a script is its own
+    /// test case, named like `db (run 2/5)`.
+    #[structopt(long, value_name = "N", default_value = "1")]
+    repeat: u32,
+
+    /// Start every script from an empty environment, only applying the `--env` entries.
+    #[structopt(long)]
+    env_clear: bool,
+
+    /// Start every script from an empty environment, except for the `--pass-env` variables and the `--env` entries.
+    /// Only the passed variables are reported as the properties of the suite.
+    #[structopt(long)]
+    clean_env: bool,
+
+    /// The environment variables passed on to the scripts with `--clean-env`, like `PATH,HOME,CI`.
+    #[structopt(long, value_name = "NAME", use_delimiter = true)]
+    pass_env: Vec<String>,
+
+    /// Expand `${VAR}` references to undefined variables in script paths and arguments to nothing, instead of
+    /// failing.
+    #[structopt(long)]
+    allow_unset: bool,
+
+    /// How the JUnit classname is derived from the script path.
+    #[structopt(long, default_value = "absolute", possible_values = &ClassnameStrategy::VARIANTS)]
+    classname_strategy: ClassnameStrategy,
+
+    /// How the JUnit test case name is derived from the script path.
+    #[structopt(long, default_value = "stem", possible_values = &NameStrategy::VARIANTS)]
+    name_strategy: NameStrategy,
+
+    /// Derive the classname from the nearest `.shunit-package` file in the script's ancestry, if any.
+    #[structopt(long)]
+    classname_from_package_file: bool,
+
+    /// Tag every test case with a `category` property, for grouping in the report. May be repeated.
+    #[structopt(long, value_name = "NAME", number_of_values = 1)]
+    category: Vec<String>,
+
+    /// Treat this exit code as a pass, in addition to 0. May be repeated.
+    #[structopt(long, value_name = "CODE", number_of_values = 1)]
+    expect_exit: Vec<i32>,
+
+    /// Invert the result of every script, so exiting with 0 (or an `--expect-exit` code) is a failure.
+    #[structopt(long)]
+    expect_failure: bool,
+
+    /// Exit with 0 even if test cases failed or had errors, and log a warning for each of them instead. They are
+    /// still reported as usual.
+    #[structopt(long)]
+    warn_only: bool,
+
+    /// Exit with 0 even if test cases failed or had errors, silently, unlike `--warn-only`. They are still
+    /// reported as usual.
+    #[structopt(long)]
+    no_fail: bool,
+
+    /// Abort the whole run when a script is killed by one of the `--abort-signals`.
+    #[structopt(long)]
+    abort_on_signal: bool,
+
+    /// Signals treated as infrastructure failures by `--abort-on-signal`.
+    #[structopt(
+        long,
+        default_value = "KILL,BUS",
+        use_delimiter = true,
+        parse(try_from_str = parse_signal)
+    )]
+    abort_signals: Vec<i32>,
+
+    /// Skip scripts without execute permission with a warning, instead of reporting them as errors.
+    #[structopt(long)]
+    skip_non_executable: bool,
+
+    /// Keep the temporary directories of the scripts that failed, instead of deleting them.
+    #[structopt(long)]
+    keep_tmp: bool,
+
+    /// A script to run before the first test case. The run is aborted if it fails.
+    #[structopt(long, value_name = "SCRIPT")]
+    setup: Option<String>,
+
+    /// A script to run after the last test case, even if the run failed or was interrupted.
+    #[structopt(long, value_name = "SCRIPT")]
+    teardown: Option<String>,
+
+    /// A script to run before every script, e.g. to reset a database. A script doesn't run if it
+    /// fails. It gets the test case in `SHUNIT_TEST_NAME` and the script in `SHUNIT_TEST_SCRIPT`.
+    #[structopt(long, value_name = "SCRIPT")]
+    before_each: Option<String>,
+
+    /// A script to run after every script, even if it failed. It also gets the status of the test case
+    /// in `SHUNIT_TEST_STATUS`: `passed`, `failed`, `errored` or `skipped`.
+    #[structopt(long, value_name = "SCRIPT")]
+    after_each: Option<String>,
+
+    /// Report `--setup` and `--teardown` as test cases, instead of only their failures as suite errors.
+    #[structopt(long)]
+    hooks_as_cases: bool,
+
+    /// Only run the scripts whose path matches this regular expression anywhere. May be repeated.
+    #[structopt(long, value_name = "PATTERN", number_of_values = 1)]
+    filter: Vec<regex::Regex>,
+
+    /// Don't run the scripts whose path matches this regular expression anywhere. May be repeated.
+    #[structopt(long, value_name = "PATTERN", number_of_values = 1)]
+    exclude: Vec<regex::Regex>,
+
+    /// Report the scripts whose path matches this regular expression anywhere as skipped, without
+    /// running them. May be repeated.
+    #[structopt(long, value_name = "PATTERN", number_of_values = 1)]
+    skip: Vec<regex::Regex>,
+
+    /// Expect the scripts whose path matches this regular expression anywhere to fail: a failure is
+    /// reported as a pass, and a pass as a failure (XPASS). May be repeated.
+    #[structopt(long, value_name = "PATTERN", number_of_values = 1)]
+    xfail: Vec<regex::Regex>,
+
+    /// Print the scripts that would run, with their classnames, without running them.
+    #[structopt(long)]
+    list: bool,
+
+    /// Merge the test cases of an existing JUnit report into the result. May be repeated.
+    #[structopt(long, value_name = "REPORT", number_of_values = 1)]
+    merge: Vec<String>,
+
+    /// How duplicate property names are resolved when merging reports.
+    #[structopt(long, default_value = "first", possible_values = &MergeProperties::VARIANTS)]
+    merge_properties: MergeProperties,
+
+    /// The order of the test cases in the report. `input` keeps the order the scripts were given in,
+    /// `duration` puts the slowest first.
+    #[structopt(long, value_name = "ORDER", default_value = "input", possible_values = &SortOrder::VARIANTS)]
+    sort: SortOrder,
+
+    /// Keep test cases with the same classname and name as an earlier one, instead of renaming them
+    /// like `name#2`.
+    #[structopt(long)]
+    allow_duplicates: bool,
+
+    /// Check that the JUnit report is accepted by common JUnit schemas, and fail if it isn't.
+    #[structopt(long)]
+    validate: bool,
+
+    /// Gzip every `--output` file, like a `.gz` suffix does for a single one. Std out is never compressed.
+    #[structopt(long)]
+    compress: bool,
+
+    /// Add the result to existing `--output` files. JUnit files get a `<testsuites>` root.
+    #[structopt(long)]
+    append: bool,
+
+    /// A regex finding the line a failing script failed at in its std err, in the first capture group.
+    #[structopt(long, value_name = "REGEX", default_value = r"FAIL at line (\d+)")]
+    line_pattern: regex::Regex,
+
+    /// Rerun a failing script up to N times, until it passes. Also `--retries`.
+    #[structopt(long, alias = "retries", value_name = "N", default_value = "0")]
+    retry: u32,
+
+    /// Run up to N scripts at the same time. The test cases are still reported in the order of the scripts.
+    #[structopt(short = "j", long, value_name = "N", default_value = "1")]
+    jobs: usize,
+
+    /// Stop starting scripts once N test cases failed or had errors, and report the ones that ran.
+    #[structopt(long, value_name = "N")]
+    bail_after: Option<u32>,
+
+    /// Stop starting scripts after the first test case that failed or had an error, like `--bail-after 1`, and
+    /// report the scripts that didn't start as skipped.
+    #[structopt(long)]
+    fail_fast: bool,
+
+    /// Exit with 0 if at most N test cases failed or had errors. They are still reported as usual.
+    #[structopt(long, value_name = "N")]
+    max_failures: Option<u32>,
+
+    /// How long to wait before the first `--retry`, in milliseconds.
+    #[structopt(long, value_name = "MS", default_value = "0")]
+    retry_delay: u64,
+
+    /// The factor the `--retry-delay` is multiplied by after every retry.
+    #[structopt(long, value_name = "FACTOR", default_value = "1")]
+    retry_backoff: f64,
+
+    /// Record the peak memory and CPU time of every script as test case properties, where supported.
+    #[structopt(long)]
+    resource_stats: bool,
+
+    /// Record how long every script waited for its turn, from the end of the setup, as a `queue_seconds` property.
+    #[structopt(long)]
+    queue_time: bool,
+
+    /// Prefix every forwarded line of output with its timestamp and `[out]` or `[err]`.
+    #[structopt(long)]
+    prefix: bool,
+
+    /// The maximum number of bytes of output captured per script. Output is still forwarded beyond it.
+    #[structopt(long, value_name = "BYTES", default_value = "10485760")]
+    max_output: u64,
+
+    /// Kill a script that runs longer than this, unless it sets its own `# shunit-timeout: SECONDS`.
+    #[structopt(long, value_name = "SECONDS", parse(try_from_str = shunit::parse_seconds))]
+    timeout: Option<time::Duration>,
+
+    /// Kill a script that writes no line to std out or std err for this long.
+    #[structopt(long, value_name = "SECONDS", parse(try_from_str = shunit::parse_seconds))]
+    idle_timeout: Option<time::Duration>,
+
+    /// Fail a script that passes in less than this, as it probably exited before doing any work.
+    #[structopt(long, value_name = "SECONDS", parse(try_from_str = shunit::parse_seconds))]
+    min_time: Option<time::Duration>,
+
+    /// Stop the run after this long. Running scripts are stopped, and the rest are reported as errors.
+    #[structopt(long, value_name = "SECONDS", parse(try_from_str = shunit::parse_seconds))]
+    max_total_time: Option<time::Duration>,
+
+    /// Mask the value of properties whose entire name matches this regex. May be repeated.
+    #[structopt(long, value_name = "REGEX", number_of_values = 1, parse(try_from_str = parse_name_pattern))]
+    redact: Vec<regex::Regex>,
+
+    /// Also mask the properties whose names contain SECRET, TOKEN, PASSWORD or KEY.
+    #[structopt(long)]
+    redact_defaults: bool,
+
+    /// Remove ANSI escape sequences, like colors, from the captured output.
+    #[structopt(long)]
+    strip_ansi: bool,
+
+    /// Re-emit an existing JUnit report instead of running any scripts.
+    #[structopt(long, value_name = "REPORT")]
+    reemit: Option<String>,
+
+    /// A TOML file, or YAML if it ends in `.yaml` or `.yml`, listing scripts to run after the
+    /// script arguments, each with its own `args`, `env`, `timeout`, `cwd`, `name` and
+    /// `expect_exit`.
+    #[structopt(long, value_name = "PATH")]
+    manifest: Option<String>,
+
+    /// A file with a set of arguments per line, like `--prefix /opt`, to run every script once per
+    /// set with, after its other arguments.
+    #[structopt(long, value_name = "PATH")]
+    param_file: Option<String>,
+
+    /// The glob the names of the scripts found in a directory argument match.
+    #[structopt(long, value_name = "GLOB", default_value = shunit::discover::DEFAULT_PATTERN)]
+    pattern: String,
+
+    /// Read a list of scripts from std in, a path per line, and run them after the script
+    /// arguments. A `-` argument reads them in its place instead.
+    #[structopt(long)]
+    stdin: bool,
+
+    /// Separate the paths of the list read from std in with NUL characters instead of newlines, like
+    /// the output of `find -print0`.
+    #[structopt(short = "0", long)]
+    null: bool,
+
+    /// Test scripts. Arguments ending in `.xml` are JUnit reports to merge, like with `--merge`, and
+    /// `-` reads a list of scripts from std in, like `--stdin`.
+    /// A directory stands for the executable scripts under it that match `--pattern`, and a glob,
+    /// like `'tests/**/*.sh'`, for the executable scripts that match it.
     scripts: Vec<String>,
+
+    /// Arguments passed to every script, after the ones of the `--manifest`, given after `--`.
+    #[structopt(last = true)]
+    script_args: Vec<String>,
 }
 
-#[tokio::main]
-async fn main() {
-    let opt = Opt::from_args();
-    let script_count = opt.scripts.len() as u32;
+impl Opt {
+    /// The options of the library that runs the scripts.
+    fn options(&self) -> shunit::Options {
+        shunit::Options {
+            env: self.env.clone(),
+            env_clear: self.env_clear,
+            clean_env: self.clean_env,
+            pass_env: self.pass_env.clone(),
+            classname_strategy: self.classname_strategy,
+            name_strategy: self.name_strategy,
+            classname_from_package_file: self.classname_from_package_file,
+            categories: self.category.clone(),
+            expect_exit: self.expect_exit.clone(),
+            skip_exit: Some(shunit::SKIP_EXIT_CODE),
+            expect_failure: self.expect_failure,
+            abort_on_signal: self.abort_on_signal,
+            abort_signals: self.abort_signals.clone(),
+            skip_non_executable: self.skip_non_executable,
+            keep_tmp: self.keep_tmp,
+            skip: self.skip.clone(),
+            xfail: self.xfail.clone(),
+            setup: self.setup.clone(),
+            teardown: self.teardown.clone(),
+            before_each: self.before_each.clone(),
+            after_each: self.after_each.clone(),
+            hooks_as_cases: self.hooks_as_cases,
+            line_pattern: Some(self.line_pattern.clone()),
+            max_output: self.max_output,
+            resource_stats: self.resource_stats,
+            queue_time: self.queue_time,
+            prefix: self.prefix,
+            jobs: self.jobs,
+            retry: self.retry,
+            bail_after: self.bail_after,
+            fail_fast: self.fail_fast,
+            retry_delay: time::Duration::from_millis(self.retry_delay),
+            retry_backoff: self.retry_backoff,
+            timeout: self.timeout,
+            idle_timeout: self.idle_timeout,
+            min_time: self.min_time,
+            max_total_time: self.max_total_time,
+            timeout_signal: self.timeout_signal,
+            kill_grace: self.kill_grace,
+            testcase_timestamps: !self.no_testcase_timestamps,
+            fallback_shebang: self.fallback_shebang,
+            stdin_inherit: self.stdin_inherit,
+            color: self.color,
+            progress: !self.quiet && !self.no_progress && self.format != Format::Tap,
+            forward_output: !self.quiet,
+            group_output: self.group_output,
+            suite_name: self.suite_name.clone(),
+            properties: self.property.clone(),
+            combine_output: self.combine_output,
+            parse_tap: self.parse_tap,
+            events: None,
+        }
+    }
 
-    // let _ = stderrlog::new()
-    //     .module(module_path!())
-    //     .quiet(opt.quiet)
-    //     .verbosity(opt.verbose)
-    //     .timestamp(opt.ts.unwrap_or(stderrlog::Timestamp::Off))
-    //     .init();
+    /// Put the variables of the `--env-file`s, in order, in front of the `--env` entries, so that
+    /// these override them.
+    fn load_env_files(&mut self) -> anyhow::Result<()> {
+        let mut env = vec![];
+        for path in &self.env_file {
+            env.extend(env_file::load(path)?);
+        }
+        env.append(&mut self.env);
+        self.env = env;
+        Ok(())
+    }
 
-    let mut error_count = 0;
-    let mut failure_count = 0;
+    /// The tests that are executed: the script arguments except the JUnit reports, with the scripts
+    /// of the directories and globs among them, followed by the tests of the `--manifest`, with
+    /// their variables expanded and without the ones left out by `--filter` and `--exclude`, once
+    /// per `--param-file` set, `--matrix` combination and `--repeat`.
+    fn tests_to_run(&self) -> anyhow::Result<Vec<TestSpec>> {
+        let manifest = match &self.manifest {
+            Some(path) => manifest::load(path)?,
+            None => vec![],
+        };
+        let params = match &self.param_file {
+            Some(path) => params::load(path)?,
+            None => vec![],
+        };
+        let mut list = self
+            .reads_stdin()
+            .then(|| read_script_list(self.null))
+            .transpose()?;
+        let mut scripts = vec![];
+        for name in self.scripts.iter().filter(|name| !is_report(name)) {
+            if name == "-" {
+                scripts.extend(list.take().unwrap_or_default());
+            } else {
+                scripts.push(name.clone());
+            }
+        }
+        scripts.extend(list.unwrap_or_default());
+        let scripts = shunit::discover::expand_scripts(&scripts, &self.pattern)?
+            .into_iter()
+            .map(TestSpec::from);
+        let tests = scripts
+            .chain(manifest)
+            .map(|mut test| {
+                test.args.extend(self.script_args.iter().cloned());
+                test
+            })
+            .map(|test| self.expand_variables(test))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let tests: Vec<TestSpec> = tests
+            .into_iter()
+            .filter(|test| {
+                let name = &test.script;
+                self.filter.is_empty() || self.filter.iter().any(|filter| filter.is_match(name))
+            })
+            .filter(|test| {
+                !self
+                    .exclude
+                    .iter()
+                    .any(|exclude| exclude.is_match(&test.script))
+            })
+            .flat_map(|test| self.expand_params(test, &params))
+            .flat_map(|test| self.expand_matrix(test))
+            .collect();
+        Ok(self.repeat_tests(tests))
+    }
 
-    if opt.scripts.is_empty() {
-        return;
+    /// The tests `--repeat` times over, all of them for every run, named like `script (run 2/5)`.
+    fn repeat_tests(&self, tests: Vec<TestSpec>) -> Vec<TestSpec> {
+        if self.repeat <= 1 {
+            return tests;
+        }
+        (1..=self.repeat)
+            .flat_map(|run| {
+                tests.iter().cloned().map(move |mut test| {
+                    let name = test
+                        .name
+                        .take()
+                        .unwrap_or_else(|| self.name_strategy.name(&test.script));
+                    test.name = Some(shunit::flaky::run_name(&name, run, self.repeat));
+                    test
+                })
+            })
+            .collect()
     }
 
-    let start = time::Instant::now();
-
-    let mut stdout_messages: Vec<LogLine> = vec![];
-    let mut stderr_messages: Vec<LogLine> = vec![];
-    let mut testcases: Vec<TestCase> = vec![];
-
-    for name in opt.scripts {
-        let absolute_path = fs::canonicalize(&name).unwrap();
-        let classname = absolute_path.into_os_string().into_string().unwrap();
-
-        let duration = start.elapsed();
-        let result = run_script(&name[..]).await;
-        let time = duration.as_secs_f32();
-
-        let error = match result {
-            Ok((exit_code, stdout, stderr)) => {
-                stdout_messages.extend(stdout.iter().cloned());
-                stderr_messages.extend(stderr.iter().cloned());
-                if exit_code.success() {
-                    None
-                } else {
-                    failure_count += 1;
-                    let body = join_and_sort(join_log_lines(&stdout), join_log_lines(&stderr));
-                    let body: Vec<String> = body.into_iter().map(|line| line.1).collect();
-                    let body = body.join(LINE_ENDING);
-                    Some(TestError {
-                        message: format!("Non-zero exit-code: {}", exit_code.code().unwrap_or(-1)),
-                        error_type: String::from("Assertion failed"),
-                        body,
-                    })
-                }
+    /// `test` with the `${VAR}` references in its script, arguments and working directory expanded
+    /// from the environment it runs in: the one of shunit, unless `--env-clear`, or only its
+    /// `--pass-env` variables with `--clean-env`, with the `--env` entries and the variables of the
+    /// test on top.
+    fn expand_variables(&self, mut test: TestSpec) -> anyhow::Result<TestSpec> {
+        let inherited = std::env::vars().filter(|(name, _)| {
+            !self.env_clear && (!self.clean_env || self.pass_env.contains(name))
+        });
+        let env: HashMap<String, String> = inherited
+            .chain(self.env.iter().cloned())
+            .chain(test.env.iter().cloned())
+            .collect();
+        let expand =
+            |text: &str| expand::expand(text, |name| env.get(name).cloned(), self.allow_unset);
+        test.script = expand(&test.script)?;
+        for arg in &mut test.args {
+            *arg = expand(arg)?;
+        }
+        if let Some(cwd) = test.cwd.as_mut() {
+            if let Some(text) = cwd.to_str() {
+                *cwd = path::PathBuf::from(expand(text)?);
             }
-            Err(error) => {
-                error_count += 1;
-                Some(TestError {
-                    message: error.to_string(),
-                    error_type: String::from("IO error"),
-                    body: String::new(),
+        }
+        Ok(test)
+    }
+
+    /// The combinations of the `--matrix` values, as the variables to set for each run.
+    fn matrix_combinations(&self) -> Vec<Vec<(String, String)>> {
+        let mut combinations = vec![vec![]];
+        for (variable, values) in &self.matrix {
+            combinations = combinations
+                .into_iter()
+                .flat_map(|combination: Vec<(String, String)>| {
+                    values.iter().map(move |value| {
+                        let mut combination = combination.clone();
+                        combination.push((variable.clone(), value.clone()));
+                        combination
+                    })
                 })
-            }
-        };
+                .collect();
+        }
+        combinations
+    }
 
-        let testcase = TestCase {
-            classname,
-            name,
-            time,
-            error,
+    /// A run of `test` per set of the `--param-file`, with the arguments of the set appended, named
+    /// like `script[--prefix /opt]`.
+    fn expand_params(&self, test: TestSpec, params: &[Vec<String>]) -> Vec<TestSpec> {
+        if params.is_empty() {
+            return vec![test];
+        }
+        let name = test
+            .name
+            .clone()
+            .unwrap_or_else(|| self.name_strategy.name(&test.script));
+        params
+            .iter()
+            .map(|args| {
+                let mut run = test.clone();
+                run.name = Some(format!("{}[{}]", name, args.join(" ")));
+                run.args.extend(args.iter().cloned());
+                run
+            })
+            .collect()
+    }
+
+    /// A run of `test` per `--matrix` combination, named like `script[VAR=a,OTHER=x]`.
+    fn expand_matrix(&self, test: TestSpec) -> Vec<TestSpec> {
+        if self.matrix.is_empty() {
+            return vec![test];
+        }
+        let name = test
+            .name
+            .clone()
+            .unwrap_or_else(|| self.name_strategy.name(&test.script));
+        self.matrix_combinations()
+            .into_iter()
+            .map(|combination| {
+                let parameters: Vec<String> = combination
+                    .iter()
+                    .map(|(variable, value)| format!("{}={}", variable, value))
+                    .collect();
+                let mut run = test.clone();
+                run.name = Some(format!("{}[{}]", name, parameters.join(",")));
+                run.env.extend(combination);
+                run
+            })
+            .collect()
+    }
+
+    /// The reports given with `--merge`, followed by the script arguments that are JUnit reports.
+    fn reports_to_merge(&self) -> impl Iterator<Item = &String> {
+        self.merge
+            .iter()
+            .chain(self.scripts.iter().filter(|name| is_report(name)))
+    }
+
+    /// Whether the list of scripts is read from std in, with `--stdin` or a `-` argument.
+    fn reads_stdin(&self) -> bool {
+        self.stdin || self.scripts.iter().any(|name| name == "-")
+    }
+
+    /// Reject combinations of options that can't work, before anything runs.
+    fn validate(&self) -> anyhow::Result<()> {
+        let runs = self
+            .matrix
+            .iter()
+            .try_fold(1usize, |runs, (_, values)| runs.checked_mul(values.len()))
+            .unwrap_or(usize::MAX);
+        if self.jobs == 0 {
+            bail!("Invalid --jobs: it must be at least 1");
+        }
+        if self.repeat == 0 {
+            bail!("Invalid --repeat: it must be at least 1");
+        }
+        if self.stdin_inherit && self.reads_stdin() {
+            bail!("Can't --stdin-inherit while reading the list of scripts from std in");
+        }
+        if self.env_clear && self.clean_env {
+            bail!("Can't --clean-env with --env-clear, which passes no variables at all");
+        }
+        if !self.pass_env.is_empty() && !self.clean_env {
+            bail!("--pass-env only applies with --clean-env");
+        }
+        if self.bail_after == Some(0) {
+            bail!("Invalid --bail-after: it must be at least 1");
+        }
+        if runs > MAX_MATRIX_RUNS {
+            bail!(
+                "The --matrix runs every script {} times, more than the limit of {}",
+                runs,
+                MAX_MATRIX_RUNS
+            );
+        }
+        if !(self.retry_backoff.is_finite() && self.retry_backoff >= 0.0) {
+            bail!("Invalid --retry-backoff: {}", self.retry_backoff);
+        }
+        let single_document = self.output_targets().into_iter().find(|target| {
+            target.path.is_some()
+                && matches!(
+                    target.format.unwrap_or(self.format),
+                    Format::Html | Format::Tap | Format::Json | Format::Sonar | Format::Ctrf
+                )
+        });
+        let compressed = self
+            .output_targets()
+            .into_iter()
+            .find(|target| self.is_compressed(target));
+        if let (true, Some(target)) = (self.append, compressed) {
+            bail!(
+                "Can't --append to {}: compressed reports can't be appended to",
+                target.path.unwrap_or_default()
+            );
+        }
+        if let (true, Some(target)) = (self.append, single_document) {
+            bail!(
+                "Can't --append to {}: {} can't be appended to",
+                target.path.unwrap_or_default(),
+                match target.format.unwrap_or(self.format) {
+                    Format::Tap => "TAP streams, with a single plan,",
+                    Format::Json | Format::Ctrf => "JSON documents",
+                    Format::Sonar => "SonarQube test executions",
+                    _ => "HTML pages",
+                }
+            );
+        }
+        Ok(())
+    }
+
+    /// Whether the report of `target` is gzipped: a file with `--compress` or a `.gz` suffix.
+    fn is_compressed(&self, target: &OutputTarget) -> bool {
+        target
+            .path
+            .as_deref()
+            .is_some_and(|path| self.compress || path.ends_with(".gz"))
+    }
+
+    /// Where the reports are written: one per `--output`, or the `--format` to std out without any,
+    /// and the `--html-report`. `--silent` leaves out the ones to std out.
+    fn output_targets(&self) -> Vec<OutputTarget> {
+        let mut targets = if self.output.is_empty() {
+            vec![OutputTarget {
+                format: Some(self.format),
+                path: None,
+            }]
+        } else {
+            self.output.clone()
         };
+        targets.extend(self.html_report.iter().map(|path| OutputTarget {
+            format: Some(Format::Html),
+            path: Some(path.clone()),
+        }));
+        if self.silent {
+            targets.retain(|target| target.path.is_some());
+        }
+        targets
+    }
+
+    /// The patterns of `--redact`, plus the defaults if `--redact-defaults` is set.
+    fn redact_patterns(&self) -> Vec<regex::Regex> {
+        let defaults = DEFAULT_REDACT_PATTERNS
+            .iter()
+            .filter(|_| self.redact_defaults)
+            .map(|pattern| parse_name_pattern(pattern).unwrap());
+        self.redact.iter().cloned().chain(defaults).collect()
+    }
+}
 
-        testcases.push(testcase);
+#[tokio::main]
+async fn main() {
+    let matches = Opt::clap().get_matches_safe().unwrap_or_else(|err| {
+        if !err.use_stderr() {
+            err.exit();
+        }
+        eprintln!("{}", err.message);
+        process::exit(EXIT_USAGE);
+    });
+    let mut opt = Opt::from_clap(&matches);
+    let config = Config::load(opt.config.as_deref(), std::env::vars());
+    let unknown_vars = config
+        .as_ref()
+        .map(|config| config.unknown_vars.clone())
+        .unwrap_or_default();
+    if let Err(err) = config.and_then(|config| config.apply(&mut opt, &matches)) {
+        eprintln!("{:#}", err);
+        process::exit(EXIT_USAGE);
+    }
+    opt.quiet |= opt.silent;
+    if let Err(err) = opt.validate() {
+        eprintln!("{:#}", err);
+        process::exit(EXIT_USAGE);
     }
 
-    let duration = start.elapsed();
+    let _ = stderrlog::new()
+        .module(module_path!())
+        .quiet(opt.quiet)
+        .verbosity(if opt.warn_only {
+            opt.verbose.max(1)
+        } else {
+            opt.verbose
+        })
+        .timestamp(opt.ts.unwrap_or(stderrlog::Timestamp::Off))
+        .init();
+    for name in unknown_vars {
+        warn!("Ignoring {}, which doesn't set any option", name);
+    }
 
-    let properties: Vec<Property> = env::vars()
-        .map(|(name, value)| Property { name, value })
-        .collect();
+    if let Some(report) = &opt.reemit {
+        let testsuite = TestSuite::from_file(report).and_then(|mut testsuite| {
+            testsuite.sanitize(opt.strip_ansi);
+            write_reports(&testsuite, &opt, false)
+        });
+        if let Err(err) = testsuite {
+            error!("{:#}", err);
+            process::exit(EXIT_INFRA_ERROR);
+        }
+        return;
+    }
 
-    let system_out: Vec<String> = stdout_messages.into_iter().map(|line| line.1).collect();
-    let system_err: Vec<String> = stderr_messages.into_iter().map(|line| line.1).collect();
+    if opt.scripts.is_empty() && opt.merge.is_empty() && opt.manifest.is_none() && !opt.stdin {
+        return;
+    }
 
-    let testsuite = TestSuite {
-        testcases,
-        errors: error_count,
-        failures: failure_count,
-        time: duration.as_secs_f32(),
-        tests: script_count,
-        system_out: system_out.join(LINE_ENDING),
-        system_err: system_err.join(LINE_ENDING),
-        name: env::var("PWD").unwrap_or_else(|_| "Unknown".to_string()),
-        properties: Properties { properties },
-        ..Default::default()
+    if let Err(err) = opt.load_env_files() {
+        error!("{:#}", err);
+        process::exit(EXIT_USAGE);
+    }
+    let tests = match opt.tests_to_run() {
+        Ok(tests) => tests,
+        Err(err) => {
+            error!("{:#}", err);
+            process::exit(EXIT_USAGE);
+        }
     };
 
-    let yaserde_cfg = yaserde::ser::Config {
-        perform_indent: true,
-        ..Default::default()
+    if opt.list {
+        let options = opt.options();
+        for name in tests.iter().map(|test| &test.script) {
+            match shunit::resolve_script(name) {
+                Ok(absolute_path) => println!("{}\t{}", name, options.classname(&absolute_path)),
+                Err(err) => println!("{}\t({})", name, err),
+            }
+        }
+        return;
+    }
+
+    let mut streams = match Streams::open(&opt) {
+        Ok(streams) => streams,
+        Err(err) => {
+            error!("{:#}", err);
+            process::exit(EXIT_INFRA_ERROR);
+        }
     };
 
-    // If an output file is not provided, send output to std out
-    let mut out_writer: Box<dyn io::Write> = opt.output.map_or(Box::new(io::stdout()), |p| {
-        Box::new(fs::File::create(&path::Path::new(&p)).unwrap())
-    });
+    let mut options = opt.options();
+    if let Some(target) = &opt.events {
+        match open_events(target) {
+            Ok(events) => options.events = Some(events),
+            Err(err) => {
+                error!("{:#}", err);
+                process::exit(EXIT_INFRA_ERROR);
+            }
+        }
+    }
 
-    let output = yaserde::ser::to_string_with_config(&testsuite, &yaserde_cfg).unwrap();
+    let interrupt = Interrupt::listen();
+    let shunit::SuiteRun {
+        mut testsuite,
+        aborted_by,
+        bailed_out,
+    } = shunit::run_observed(&tests, &options, &interrupt, &mut |testcase| {
+        streams.write_case(testcase)
+    })
+    .await;
 
-    out_writer
-        .write(output.as_bytes())
-        .map_err(|err| anyhow!("Failed to output test result: {:?}", err))
-        .unwrap();
+    for report in opt.reports_to_merge() {
+        let merged = TestSuite::from_file(report)
+            .and_then(|other| testsuite.merge(other, opt.merge_properties));
+        if let Err(err) = merged {
+            error!("{:#}", err);
+            process::exit(EXIT_INFRA_ERROR);
+        }
+    }
 
-    if error_count > 0 || failure_count > 0 {
-        process::exit(1);
+    if !opt.allow_duplicates {
+        testsuite.disambiguate();
+    }
+    testsuite.sort(opt.sort);
+
+    let redact = opt.redact_patterns();
+    redact_properties(&mut testsuite.properties.properties, &redact);
+    for testcase in &mut testsuite.testcases {
+        if let Some(properties) = &mut testcase.properties {
+            redact_properties(&mut properties.properties, &redact);
+        }
     }
-}
 
-/// Merge two log streams and sort the contents,
-fn join_and_sort(stdout: Vec<LogLine>, stderr: Vec<LogLine>) -> Vec<LogLine> {
-    let stdout = stdout[..].as_ref();
-    let stderr = stderr[..].as_ref();
-    let mut result = [stdout, stderr].concat();
-    result.sort_by(|a, b| a.0.cmp(&b.0));
-    result
+    testsuite.sanitize(opt.strip_ansi);
+    streams.finish(&testsuite);
+    if let Err(err) = write_reports(&testsuite, &opt, true) {
+        error!("{:#}", err);
+        process::exit(EXIT_INFRA_ERROR);
+    }
+
+    let within_max_failures = opt
+        .max_failures
+        .filter(|max| testsuite.failures + testsuite.errors <= *max);
+    if !opt.quiet {
+        let bailed_after = opt
+            .fail_fast
+            .then_some(1)
+            .or(opt.bail_after)
+            .filter(|_| bailed_out);
+        eprintln!("{}", summary(&testsuite, bailed_after, within_max_failures));
+        for rate in shunit::flaky::flaky(&testsuite) {
+            eprintln!(
+                "shunit: flaky: {} passed {} of {} runs ({:.0}%)",
+                rate.name,
+                rate.passed,
+                rate.runs,
+                rate.pass_rate * 100.0
+            );
+        }
+    }
+
+    if opt.warn_only {
+        for testcase in &testsuite.testcases {
+            if let Some(problem) = testcase.failure.as_ref().or(testcase.error.as_ref()) {
+                warn!(
+                    "{} {}: {}",
+                    testcase.classname, testcase.name, problem.message
+                );
+            }
+        }
+    }
+
+    if opt.github_annotations {
+        // On std err, which the runner reads workflow commands from too, so a report on std out
+        // stays valid.
+        let base = std::env::current_dir().unwrap_or_default();
+        let level = if opt.warn_only { "warning" } else { "error" };
+        for testcase in &testsuite.testcases {
+            if let Some(annotation) = shunit::github::annotation(testcase, &base, level) {
+                eprintln!("{}", annotation);
+            }
+        }
+    }
+
+    process::exit(exit_code(
+        &testsuite,
+        aborted_by.is_some(),
+        interrupt.is_interrupted(),
+        opt.warn_only || opt.no_fail || within_max_failures.is_some(),
+    ));
 }
 
 /**
-Join log messages so there is one message per line ending with a new line.
+The exit code of a run: an interruption wins over errors, and errors over failures. `tolerated`
+errors and failures of test cases, with `--warn-only`, `--no-fail` or within `--max-failures`, exit
+with 0, but not an interruption or an aborted run.
+*/
+fn exit_code(testsuite: &TestSuite, aborted: bool, interrupted: bool, tolerated: bool) -> i32 {
+    if interrupted {
+        EXIT_INTERRUPTED
+    } else if aborted {
+        EXIT_INFRA_ERROR
+    } else if tolerated {
+        0
+    } else if testsuite.errors > 0 {
+        EXIT_INFRA_ERROR
+    } else if testsuite.failures > 0 {
+        EXIT_FAILURES
+    } else {
+        0
+    }
+}
 
-Log messages are interleaved so if a line was sliced into two messages, they become a single message,
-with the timestamp from the first message.
+/// A one-line summary of the result, for CI logs: `shunit: ran N tests, F failures, E errors in Ts`,
+/// the limit of `--bail-after` if the run stopped there, and that of `--max-failures` if the
+/// failures and errors were within it.
+fn summary(
+    testsuite: &TestSuite,
+    bailed_after: Option<u32>,
+    within_max_failures: Option<u32>,
+) -> String {
+    let mut summary = format!(
+        "shunit: ran {} tests, {} failures, {} errors in {:.2}s",
+        testsuite.tests, testsuite.failures, testsuite.errors, testsuite.time
+    );
+    if let Some(limit) = bailed_after {
+        summary += &format!(", bailed out after {} failures or errors", limit);
+    }
+    if let Some(max) = within_max_failures {
+        summary += &format!(", within --max-failures of {}", max);
+    }
+    summary
+}
 
-- `messages` a vector of log messages and timestamps sorted in ascending order.
-*/
-fn join_log_lines(messages: &[(DateTime<Utc>, String)]) -> Vec<LogLine> {
-    let mut joined_messages: Vec<LogLine> = vec![];
-    let mut line: String = String::new();
-    let mut first_ts: Option<DateTime<Utc>> = None;
+/// A file a report is written to, and its format.
+#[derive(Clone, Debug, PartialEq)]
+struct OutputTarget {
+    /// The format of the report, or `--format` if it isn't given.
+    format: Option<Format>,
+    /// The file to write to, or std out.
+    path: Option<String>,
+}
+
+impl std::str::FromStr for OutputTarget {
+    type Err = anyhow::Error;
+
+    /// Parse `FORMAT:PATH`, `FORMAT=PATH`, or just `PATH`. A prefix that is no known format is part of
+    /// the path.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let target = s
+            .split_once([':', '='])
+            .and_then(|(format, path)| Some((format.parse().ok()?, path)))
+            .map(|(format, path)| OutputTarget {
+                format: Some(format),
+                path: Some(path.to_string()),
+            })
+            .unwrap_or_else(|| OutputTarget {
+                format: None,
+                path: Some(s.to_string()),
+            });
+        Ok(target)
+    }
+}
 
-    for (index, (ts, message)) in messages.iter().enumerate() {
-        line.push_str(message);
-        if first_ts.is_none() {
-            first_ts = Some(*ts);
+/// The streamed output targets, JSON Lines and TeamCity, which get every test case as soon as it
+/// completes.
+struct Streams {
+    writers: Vec<(String, Format, Box<dyn io::Write>)>,
+    /// The number of test cases written so far.
+    streamed: usize,
+    strip_ansi: bool,
+    suite_name: String,
+    /// The names written so far, unless `--allow-duplicates`, to rename the test cases like the
+    /// report does.
+    names: Option<UniqueNames>,
+    redact: Vec<regex::Regex>,
+}
+
+impl Streams {
+    /// Open the streamed output targets of the options, truncating them unless `--append` is set, and
+    /// start the TeamCity suites. Compressed ones are left to `write_reports`.
+    fn open(opt: &Opt) -> anyhow::Result<Self> {
+        let mut writers: Vec<(String, Format, Box<dyn io::Write>)> = vec![];
+        for target in opt.output_targets() {
+            let format = target.format.unwrap_or(opt.format);
+            if !format.is_streamed() || opt.is_compressed(&target) {
+                continue;
+            }
+            let writer: Box<dyn io::Write> = match &target.path {
+                Some(path) => Box::new(
+                    fs::OpenOptions::new()
+                        .write(true)
+                        .create(true)
+                        .append(opt.append)
+                        .truncate(!opt.append)
+                        .open(path)
+                        .map_err(|err| anyhow!("Failed to open {}: {}", path, err))?,
+                ),
+                None => Box::new(io::stdout()),
+            };
+            let name = target.path.unwrap_or_else(|| String::from("std out"));
+            writers.push((name, format, writer));
         }
-        if message.ends_with('\n') || index == (messages.len() - 1) {
-            joined_messages.push((first_ts.unwrap(), line));
-            first_ts = None;
-            line = String::new();
+        let mut streams = Streams {
+            writers,
+            streamed: 0,
+            strip_ansi: opt.strip_ansi,
+            suite_name: opt.options().suite_name(),
+            names: (!opt.allow_duplicates).then(UniqueNames::default),
+            redact: opt.redact_patterns(),
+        };
+        let started = shunit::teamcity::render_suite_started(&streams.suite_name);
+        streams.write_line(|format| (format == Format::Teamcity).then(|| started.clone()));
+        Ok(streams)
+    }
+
+    /// Write the line `line` gives for the format of every target, if any, flushing it so followers
+    /// see it right away.
+    fn write_line(&mut self, line: impl Fn(Format) -> Option<String>) {
+        for (name, format, writer) in &mut self.writers {
+            let Some(line) = line(*format) else { continue };
+            if let Err(err) = writeln!(writer, "{}", line).and_then(|_| writer.flush()) {
+                error!("Failed to write to {}: {}", name, err);
+            }
         }
     }
 
-    joined_messages
+    /// Write a test case to every target, in its format.
+    fn write_testcase(&mut self, testcase: &TestCase) {
+        self.write_line(|format| match format {
+            Format::Teamcity => Some(shunit::teamcity::render_case(testcase)),
+            _ => Some(shunit::jsonl::render_case(testcase)),
+        });
+    }
+
+    /// Write a test case of the run as soon as it is done, renamed and redacted like in the final
+    /// report. Only the `--sort` order of the report can't be known yet.
+    fn write_case(&mut self, testcase: &TestCase) {
+        if self.writers.is_empty() {
+            return;
+        }
+        let mut testcase = testcase.clone();
+        if let Some(names) = &mut self.names {
+            names.rename(&mut testcase);
+        }
+        if let Some(properties) = &mut testcase.properties {
+            redact_properties(&mut properties.properties, &self.redact);
+        }
+        testcase.sanitize(self.strip_ansi);
+        self.write_testcase(&testcase);
+        self.streamed += 1;
+    }
+
+    /// Write the test cases that weren't streamed, like the ones of merged reports, and the summary
+    /// or the end of the TeamCity suite.
+    fn finish(mut self, testsuite: &TestSuite) {
+        if self.writers.is_empty() {
+            return;
+        }
+        for testcase in testsuite.testcases.iter().skip(self.streamed) {
+            self.write_testcase(testcase);
+        }
+        let suite_name = self.suite_name.clone();
+        self.write_line(|format| match format {
+            Format::Teamcity => Some(shunit::teamcity::render_suite_finished(&suite_name)),
+            _ => Some(shunit::jsonl::render_summary(testsuite)),
+        });
+    }
+}
+
+/// Mask the values of the `properties` whose entire name matches one of the `patterns`.
+fn redact_properties(properties: &mut [Property], patterns: &[regex::Regex]) {
+    for property in properties {
+        if patterns
+            .iter()
+            .any(|pattern| pattern.is_match(&property.name))
+        {
+            property.value = String::from(REDACTED);
+        }
+    }
+}
+
+/**
+Write the test suite to every output target of the options, and its totals to `--summary-json`.
+
+- `streamed` the JSON Lines and TeamCity targets were already written by `Streams`, and are left
+  out, except for compressed ones, which can't be streamed.
+
+With `--validate`, the suite is checked as a JUnit report after the reports are written, so an
+invalid one can still be inspected.
+*/
+fn write_reports(testsuite: &TestSuite, opt: &Opt, streamed: bool) -> anyhow::Result<()> {
+    for target in opt.output_targets() {
+        let format = target.format.unwrap_or(opt.format);
+        let compressed = opt.is_compressed(&target);
+        if streamed && format.is_streamed() && !compressed {
+            continue;
+        }
+        match (&target.path, opt.append) {
+            (Some(path), _) if compressed => {
+                write_gzip(path, format.render(testsuite)?.as_bytes())?
+            }
+            (Some(path), true) => append_report(testsuite, format, path)?,
+            (path, _) => write_report(testsuite, format, path.as_deref())?,
+        }
+    }
+    if let Some(path) = &opt.summary_md {
+        let table = shunit::markdown::render_table(testsuite);
+        let written = if opt.append {
+            fs::OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(path)
+                .and_then(|mut file| file.write_all(format!("\n{}", table).as_bytes()))
+        } else {
+            fs::write(path, table)
+        };
+        written.map_err(|err| anyhow!("Failed to write the summary to {}: {}", path, err))?;
+    }
+    if let Some(dir) = &opt.allure_dir {
+        write_allure(testsuite, dir)?;
+    }
+    if let Some(path) = &opt.summary_json {
+        let finished = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+        fs::write(path, shunit::summary::render(testsuite, &finished))
+            .map_err(|err| anyhow!("Failed to write the summary to {}: {}", path, err))?;
+    }
+    if opt.validate {
+        shunit::validate::validate_junit(&render_junit(testsuite)?)
+            .map_err(|err| anyhow!("The report is not valid JUnit: {:#}", err))?;
+    }
+    Ok(())
 }
 
-// https://stackoverflow.com/questions/68173678/read-childstdout-without-blocking
-// https://stackoverflow.com/questions/34611742/how-do-i-read-the-output-of-a-child-process-without-blocking-in-rust
-async fn run_script(program: &str) -> ScriptResult {
-    let mut child = Command::new(program)
-        .stdout(process::Stdio::piped())
-        .stderr(process::Stdio::piped())
-        .spawn()?;
+/// Write the test suite to `output`, or to std out if no output file is provided.
+fn write_report(testsuite: &TestSuite, format: Format, output: Option<&str>) -> anyhow::Result<()> {
+    let mut out_writer: Box<dyn io::Write> = match output {
+        Some(p) => Box::new(fs::File::create(path::Path::new(p))?),
+        None => Box::new(io::stdout()),
+    };
 
-    let stdout = child
-        .stdout
-        .take()
-        .ok_or_else(|| anyhow!("No stdout handle?"))?;
-    let mut stdout = BufReader::new(stdout).lines();
+    let output = format.render(testsuite)?;
 
-    let stderr = child
-        .stderr
-        .take()
-        .ok_or_else(|| anyhow!("No stdout handle?"))?;
-    let mut stderr = BufReader::new(stderr).lines();
+    out_writer
+        .write_all(output.as_bytes())
+        .map_err(|err| anyhow!("Failed to output test result: {:?}", err))
+}
 
-    let mut stdout_vector: Vec<LogLine> = vec![];
-    let mut stderr_vector: Vec<LogLine> = vec![];
+/// Write the test suite as Allure results to the directory `dir`, creating it if it doesn't exist.
+fn write_allure(testsuite: &TestSuite, dir: &str) -> anyhow::Result<()> {
+    fs::create_dir_all(dir).map_err(|err| anyhow!("Failed to create {}: {}", dir, err))?;
+    // The time and the process tell the ids of this run apart from those of the earlier ones.
+    let nanos = time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .map_or(0, |since| since.as_nanos());
+    let seed = nanos ^ (u128::from(process::id()) << 64);
+    for (name, contents) in shunit::allure::render(testsuite, seed) {
+        let path = path::Path::new(dir).join(name);
+        fs::write(&path, contents)
+            .map_err(|err| anyhow!("Failed to write {}: {}", path.display(), err))?;
+    }
+    Ok(())
+}
 
-    let handle = tokio::spawn(async move { child.wait().await });
+/// Write `contents` to the file at `path`, gzipped.
+fn write_gzip(path: &str, contents: &[u8]) -> anyhow::Result<()> {
+    let file = fs::File::create(path).map_err(|err| anyhow!("Failed to open {}: {}", path, err))?;
+    let mut encoder = GzEncoder::new(file, Compression::default());
+    encoder
+        .write_all(contents)
+        .and_then(|_| encoder.finish())
+        .map_err(|err| anyhow!("Failed to compress {}: {}", path, err))?;
+    Ok(())
+}
 
-    loop {
-        let now: DateTime<Utc> = Utc::now();
-        let stdout_line = stdout.next_line().await?;
-        let stderr_line = stderr.next_line().await?;
-        if stdout_line == None && stderr_line == None {
-            break;
+/**
+Add the test suite to the report at `path`, creating it if it doesn't exist.
+
+A JUnit report is rewritten as a `<testsuites>` document with the existing suites followed by this
+one, so it stays valid. A Markdown report is appended to. An HTML page, a JSON document, a SonarQube
+document or a TAP stream, which has a single plan, can't be appended to.
+*/
+fn append_report(testsuite: &TestSuite, format: Format, path: &str) -> anyhow::Result<()> {
+    let exists = path::Path::new(path).is_file();
+    match format {
+        Format::Junit => {
+            let mut testsuites = if exists {
+                TestSuites::from_file(path)?
+            } else {
+                TestSuites::default()
+            };
+            testsuites.push(testsuite.clone());
+            fs::write(path, render_junit(&testsuites)?)
+                .map_err(|err| anyhow!("Failed to write {}: {}", path, err))
         }
-        if let Some(line) = stdout_line {
-            println!("{line}");
-            stdout_vector.push((now, line));
+        Format::Markdown | Format::Jsonl | Format::Teamcity => {
+            let mut file = fs::OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(path)
+                .map_err(|err| anyhow!("Failed to open {}: {}", path, err))?;
+            let separator = if exists && format == Format::Markdown {
+                "\n"
+            } else {
+                ""
+            };
+            let output = format!("{}{}", separator, format.render(testsuite)?);
+            io::Write::write_all(&mut file, output.as_bytes())
+                .map_err(|err| anyhow!("Failed to write {}: {}", path, err))
         }
-        if let Some(line) = stderr_line {
-            eprintln!("{line}");
-            stderr_vector.push((now, line));
+        Format::Html => bail!(
+            "Can't --append to {}: HTML pages can't be appended to",
+            path
+        ),
+        Format::Json | Format::Ctrf => bail!(
+            "Can't --append to {}: JSON documents can't be appended to",
+            path
+        ),
+        Format::Tap => bail!(
+            "Can't --append to {}: TAP streams, with a single plan, can't be appended to",
+            path
+        ),
+        Format::Sonar => bail!(
+            "Can't --append to {}: SonarQube test executions can't be appended to",
+            path
+        ),
+    }
+}
+
+/**
+Open the `--events` target: a file, which is truncated, or on unix an open file descriptor given by
+its number, like `3` for `shunit --events 3 3>events.jsonl`. The descriptor is closed for the
+scripts, so they can't write to it.
+*/
+fn open_events(target: &str) -> anyhow::Result<EventSink> {
+    #[cfg(unix)]
+    if let Ok(fd) = target.parse::<std::os::unix::io::RawFd>() {
+        use std::os::unix::io::FromRawFd;
+        // SAFETY: `fcntl` has no memory safety requirements.
+        if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+            bail!(
+                "Failed to open the events file descriptor {}: {}",
+                fd,
+                io::Error::last_os_error()
+            );
         }
+        // SAFETY: the descriptor is open, and is only written to through this file.
+        return Ok(EventSink::new(unsafe { fs::File::from_raw_fd(fd) }));
     }
+    let file =
+        fs::File::create(target).map_err(|err| anyhow!("Failed to open {}: {}", target, err))?;
+    Ok(EventSink::new(file))
+}
 
-    let exit_code = handle.await??;
-    Ok((exit_code, stdout_vector, stderr_vector))
+/// The list of scripts piped to std in, separated by newlines, or by NULs with `null`.
+fn read_script_list(null: bool) -> anyhow::Result<Vec<String>> {
+    let text = io::read_to_string(io::stdin())
+        .map_err(|err| anyhow!("Failed to read the list of scripts from std in: {}", err))?;
+    Ok(shunit::discover::parse_script_list(&text, null))
 }
 
-#[cfg(test)]
-mod test {
-    use crate::{join_log_lines, LogLine};
-    use chrono::DateTime;
-    use std::{str::FromStr, sync::Once};
-
-    static INIT: Once = Once::new();
-
-    pub fn setup() {
-        INIT.call_once(|| {
-            // stderrlog::new()
-            //     .module(module_path!())
-            //     .verbosity(5)
-            //     .init()
-            //     .unwrap();
-        });
-    }
+/// Whether a script argument is a JUnit report to merge rather than a script to run.
+fn is_report(name: &str) -> bool {
+    path::Path::new(name)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("xml"))
+}
 
-    #[test]
-    fn test_join_log_lines() {
-        setup();
-        let ts1 = DateTime::from_str("2022-04-03 10:13:48 UTC").unwrap();
-        let ts2 = DateTime::from_str("2022-04-03 10:13:49 UTC").unwrap();
-        let ts3 = DateTime::from_str("2022-04-03 10:13:50 UTC").unwrap();
-        let messages: Vec<LogLine> = vec![
-            (ts1, "A".to_string()),
-            (ts2, "B\n".to_string()),
-            (ts3, "C".to_string()),
-        ];
-        let joined = join_log_lines(&messages);
-        assert_eq!(joined.len(), 2);
-        assert_eq!(joined[0], (ts1, "AB\n".to_string()));
-        assert_eq!(joined[1], (ts3, "C".to_string()));
+/// Parse a regex that must match an entire property name.
+fn parse_name_pattern(s: &str) -> Result<regex::Regex, regex::Error> {
+    regex::Regex::new(&format!("^(?:{})$", s))
+}
+
+/// Parse a `VAR=A,B,C` matrix variable and its values given on the command line. The values are
+/// separated by `|` instead if there is one, like `VAR=A|B,C`, for values with commas.
+fn parse_matrix(s: &str) -> anyhow::Result<(String, Vec<String>)> {
+    let (variable, values) = parse_key_val(s)?;
+    let separator = if values.contains('|') { '|' } else { ',' };
+    let values: Vec<String> = values.split(separator).map(String::from).collect();
+    if variable.is_empty() || values.iter().any(String::is_empty) {
+        bail!("Invalid VAR=A,B,C: `{}`", s);
     }
+    Ok((variable, values))
+}
+
+/// Parse a `KEY=VALUE` pair given on the command line.
+fn parse_key_val(s: &str) -> anyhow::Result<(String, String)> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow!("Invalid KEY=VALUE: no `=` found in `{}`", s))?;
+    Ok((key.to_string(), value.to_string()))
 }
@@ -0,0 +1,135 @@
+//! The `--manifest` file, which lists the scripts to run, each with settings of its own. It is
+//! written in YAML if its name ends in `.yaml` or `.yml`, and in TOML otherwise.
+
+use serde::Deserialize;
+use shunit::{parse_seconds, TestSpec};
+use std::collections::BTreeMap;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The tests of a TOML manifest, as `[[test]]` tables. A YAML manifest is a list of the entries.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    #[serde(default)]
+    test: Vec<Entry>,
+}
+
+/// A test of the manifest. The settings it leaves out are the ones of the command line.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    script: String,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    timeout: Option<f64>,
+    cwd: Option<PathBuf>,
+    name: Option<String>,
+    expect_exit: Option<Vec<i32>>,
+}
+
+impl Entry {
+    fn into_spec(self) -> anyhow::Result<TestSpec> {
+        let timeout = match self.timeout {
+            Some(seconds) => Some(
+                parse_seconds(&seconds.to_string())
+                    .map_err(|err| anyhow!("Invalid timeout for {}: {}", self.script, err))?,
+            ),
+            None => None,
+        };
+        Ok(TestSpec {
+            script: self.script,
+            args: self.args,
+            env: self.env.into_iter().collect(),
+            timeout,
+            cwd: self.cwd,
+            name: self.name,
+            expect_exit: self.expect_exit,
+        })
+    }
+}
+
+/// Parse a TOML manifest into the tests it lists, in order.
+fn parse(toml: &str) -> anyhow::Result<Vec<TestSpec>> {
+    let manifest: Manifest = toml::from_str(toml)?;
+    manifest.test.into_iter().map(Entry::into_spec).collect()
+}
+
+/// Parse a YAML manifest into the tests it lists, in order.
+fn parse_yaml(yaml: &str) -> anyhow::Result<Vec<TestSpec>> {
+    let entries: Vec<Entry> = serde_yaml::from_str(yaml)?;
+    entries.into_iter().map(Entry::into_spec).collect()
+}
+
+/// Read the tests listed in the manifest at `path`, in YAML or TOML depending on its extension.
+pub fn load(path: &str) -> anyhow::Result<Vec<TestSpec>> {
+    let text =
+        fs::read_to_string(path).map_err(|err| anyhow!("Failed to read {}: {}", path, err))?;
+    let yaml = Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension == "yaml" || extension == "yml");
+    let tests = if yaml {
+        parse_yaml(&text)
+    } else {
+        parse(&text)
+    };
+    tests.map_err(|err| anyhow!("Failed to parse {}: {}", path, err))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, parse_yaml};
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_manifest() {
+        let toml = r#"
+            [[test]]
+            script = "test/db.sh"
+            args = ["--fast"]
+            timeout = 30
+            cwd = "test"
+            name = "database"
+            expect_exit = [3]
+            env = { DB = "postgres" }
+
+            [[test]]
+            script = "test/im_ok.sh"
+        "#;
+        let tests = parse(toml).unwrap();
+        assert_eq!(tests.len(), 2);
+        assert_eq!(tests[0].args, ["--fast"]);
+        assert_eq!(tests[0].timeout, Some(Duration::from_secs(30)));
+        assert_eq!(tests[0].env, [("DB".to_string(), "postgres".to_string())]);
+        assert_eq!(tests[0].expect_exit, Some(vec![3]));
+        assert_eq!(tests[1], shunit::TestSpec::from("test/im_ok.sh"));
+        assert!(parse("[[test]]\nscript = \"a.sh\"\nretries = 2\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_yaml_manifest() {
+        let yaml = r#"
+            - script: test/db.sh
+              args: [--fast]
+              timeout: 30
+              cwd: test
+              name: database
+              expect_exit: [3]
+              env:
+                DB: postgres
+            - script: test/im_ok.sh
+        "#;
+        let tests = parse_yaml(yaml).unwrap();
+        assert_eq!(tests.len(), 2);
+        assert_eq!(tests[0].args, ["--fast"]);
+        assert_eq!(tests[0].timeout, Some(Duration::from_secs(30)));
+        assert_eq!(tests[0].env, [("DB".to_string(), "postgres".to_string())]);
+        assert_eq!(tests[0].expect_exit, Some(vec![3]));
+        assert_eq!(tests[1], shunit::TestSpec::from("test/im_ok.sh"));
+        assert!(parse_yaml("- script: a.sh\n  retries: 2\n").is_err());
+    }
+}
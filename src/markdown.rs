@@ -0,0 +1,155 @@
+//! Rendering of a test suite as a Markdown summary, e.g. for pull request comments.
+
+use crate::flaky::flaky;
+use crate::model::{Status, TestCase, TestSuite};
+
+/// The glyph shown for a test case outcome.
+fn glyph(status: Status) -> &'static str {
+    match status {
+        Status::Passed => "✅",
+        Status::Failed => "❌",
+        Status::Errored => "⚠️",
+        Status::Skipped => "⏭️",
+    }
+}
+
+/// Escape text so it can be used in a table cell.
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|").replace(['\r', '\n'], " ")
+}
+
+/// A code fence longer than any run of backticks in `text`.
+fn fence(text: &str) -> String {
+    let longest = text
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    "`".repeat(longest.max(2) + 1)
+}
+
+/// The collapsible block with the captured output of a failed or errored test case.
+fn details(testcase: &TestCase) -> Option<String> {
+    let problem = testcase.error.as_ref().or(testcase.failure.as_ref())?;
+    let fence = fence(&problem.body);
+    Some(format!(
+        "<details>\n<summary>{} {}: {}</summary>\n\n{}text\n{}\n{}\n\n</details>\n",
+        glyph(testcase.status()),
+        cell(&testcase.name),
+        cell(&problem.message),
+        fence,
+        problem.body.trim_end(),
+        fence
+    ))
+}
+
+/// Render the test suite as a Markdown summary with a table of the test cases, and one of the flaky
+/// ones of a `--repeat` run, if any.
+pub fn render(testsuite: &TestSuite) -> String {
+    let mut markdown = format!(
+        "## {}\n\n**{} tests**, {} failures, {} errors, {} skipped in {:.2}s\n\n",
+        cell(&testsuite.name),
+        testsuite.tests,
+        testsuite.failures,
+        testsuite.errors,
+        testsuite.skipped,
+        testsuite.time
+    );
+
+    markdown.push_str("| Test | Status | Duration |\n| --- | :---: | ---: |\n");
+    for testcase in &testsuite.testcases {
+        markdown.push_str(&format!(
+            "| {} | {} | {:.2}s |\n",
+            cell(&testcase.name),
+            glyph(testcase.status()),
+            testcase.time
+        ));
+    }
+
+    let flaky = flaky(testsuite);
+    if !flaky.is_empty() {
+        markdown.push_str(
+            "\n### Flaky tests\n\n| Test | Passed | Pass rate |\n| --- | ---: | ---: |\n",
+        );
+        for rate in flaky {
+            markdown.push_str(&format!(
+                "| {} | {}/{} | {:.0}% |\n",
+                cell(rate.name),
+                rate.passed,
+                rate.runs,
+                rate.pass_rate * 100.0
+            ));
+        }
+    }
+
+    for details in testsuite.testcases.iter().filter_map(details) {
+        markdown.push('\n');
+        markdown.push_str(&details);
+    }
+
+    markdown
+}
+
+/// The first line of the captured output of a failed or errored test case, or its message if it
+/// has no output.
+fn first_error_line(testcase: &TestCase) -> Option<&str> {
+    let problem = testcase.error.as_ref().or(testcase.failure.as_ref())?;
+    let line = problem
+        .body
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty());
+    Some(line.unwrap_or(&problem.message))
+}
+
+/// Render the test suite as a compact summary: the counts and a table with the first error line of
+/// every test case, without the captured output. Fits GitHub's step summaries.
+pub fn render_table(testsuite: &TestSuite) -> String {
+    let mut markdown = format!(
+        "**{} tests**, {} failures, {} errors, {} skipped in {:.2}s\n\n",
+        testsuite.tests, testsuite.failures, testsuite.errors, testsuite.skipped, testsuite.time
+    );
+    markdown.push_str("| Test | Status | Duration | Error |\n| --- | :---: | ---: | --- |\n");
+    for testcase in &testsuite.testcases {
+        markdown.push_str(&format!(
+            "| {} | {} | {:.2}s | {} |\n",
+            cell(&testcase.name),
+            glyph(testcase.status()),
+            testcase.time,
+            cell(first_error_line(testcase).unwrap_or_default())
+        ));
+    }
+    markdown
+}
+
+#[cfg(test)]
+mod test {
+    use super::{render, render_table};
+    use crate::model::TestSuite;
+
+    #[test]
+    fn test_render() {
+        let testsuite = TestSuite::from_file("tests/fixtures/compact.xml").unwrap();
+        let markdown = render(&testsuite);
+        assert!(markdown
+            .starts_with("## compact\n\n**2 tests**, 1 failures, 0 errors, 0 skipped in 1.50s\n"));
+        assert!(markdown.contains("| ./test/im_ok.sh | ✅ | 0.50s |\n"));
+        assert!(markdown.contains("| ./test/bad_apple.sh | ❌ | 1.00s |\n"));
+        assert!(markdown.contains(
+            "<summary>❌ ./test/bad_apple.sh: Non-zero exit-code: 2</summary>\n\n```text\nls: cannot access\n```\n"
+        ));
+    }
+
+    #[test]
+    fn test_render_table() {
+        let testsuite = TestSuite::from_file("tests/fixtures/compact.xml").unwrap();
+        assert_eq!(
+            render_table(&testsuite),
+            "**2 tests**, 1 failures, 0 errors, 0 skipped in 1.50s\n\n\
+             | Test | Status | Duration | Error |\n\
+             | --- | :---: | ---: | --- |\n\
+             | ./test/im_ok.sh | ✅ | 0.50s |  |\n\
+             | ./test/bad_apple.sh | ❌ | 1.00s | ls: cannot access |\n"
+        );
+    }
+}
@@ -1,4 +1,12 @@
-#[derive(Clone, Debug, Default, PartialEq, YaDeserialize, YaSerialize)]
+// The yaserde 0.8 derive macros expand to impls inside anonymous consts.
+#![allow(non_local_definitions)]
+
+use crate::text::{strip_ansi, strip_invalid_xml_chars};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::{fs, path::Path};
+
+#[derive(Clone, Debug, Default, PartialEq, YaDeserialize, YaSerialize, Serialize)]
 #[yaserde(rename = "property")]
 pub struct Property {
     #[yaserde(attribute)]
@@ -7,24 +15,139 @@ pub struct Property {
     pub value: String,
 }
 
-#[derive(Clone, Debug, Default, PartialEq, YaDeserialize, YaSerialize)]
+#[derive(Clone, Debug, Default, PartialEq, YaDeserialize, YaSerialize, Serialize)]
 #[yaserde(rename = "properties")]
+#[serde(transparent)]
 pub struct Properties {
     #[yaserde(child, rename = "property")]
     pub properties: Vec<Property>,
 }
 
-#[derive(Clone, Debug, Default, PartialEq, YaDeserialize, YaSerialize)]
+/// How duplicate property names are resolved when suites are merged.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MergeProperties {
+    /// Keep the value seen first.
+    First,
+    /// Keep the value seen last.
+    Last,
+    /// Fail the merge if the values differ.
+    Error,
+    /// Keep every distinct value.
+    Union,
+}
+
+impl MergeProperties {
+    pub const VARIANTS: [&'static str; 4] = ["first", "last", "error", "union"];
+}
+
+impl std::str::FromStr for MergeProperties {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "first" => Ok(MergeProperties::First),
+            "last" => Ok(MergeProperties::Last),
+            "error" => Ok(MergeProperties::Error),
+            "union" => Ok(MergeProperties::Union),
+            _ => Err(anyhow!("Unknown property merge strategy: {}", s)),
+        }
+    }
+}
+
+/// The orders the test cases of a report can be sorted in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortOrder {
+    /// The order the scripts were given in.
+    Input,
+    /// By test case name, then classname.
+    Name,
+    /// By script path, then test case name.
+    Path,
+    /// The slowest first.
+    Duration,
+}
+
+impl SortOrder {
+    pub const VARIANTS: [&'static str; 4] = ["input", "name", "path", "duration"];
+}
+
+impl std::str::FromStr for SortOrder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "input" => Ok(SortOrder::Input),
+            "name" => Ok(SortOrder::Name),
+            "path" => Ok(SortOrder::Path),
+            "duration" => Ok(SortOrder::Duration),
+            _ => Err(anyhow!("Unknown sort order: {}", s)),
+        }
+    }
+}
+
+impl Properties {
+    /// Merge `other` into these properties, resolving duplicate names with `strategy`.
+    pub fn merge(&mut self, other: Properties, strategy: MergeProperties) -> anyhow::Result<()> {
+        for property in other.properties {
+            let existing = self.properties.iter().position(|p| p.name == property.name);
+            match (existing, strategy) {
+                (None, _) => self.properties.push(property),
+                (Some(_), MergeProperties::First) => {}
+                (Some(index), MergeProperties::Last) => self.properties[index] = property,
+                (Some(index), MergeProperties::Error) => {
+                    let existing = &self.properties[index];
+                    if existing.value != property.value {
+                        bail!(
+                            "Conflicting values for property {}: {:?} and {:?}",
+                            property.name,
+                            existing.value,
+                            property.value
+                        );
+                    }
+                }
+                (Some(_), MergeProperties::Union) => {
+                    if !self.properties.contains(&property) {
+                        self.properties.push(property);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, YaDeserialize, YaSerialize, Serialize)]
 pub struct TestError {
     #[yaserde(attribute)]
     pub message: String,
     #[yaserde(attribute, rename = "type")]
+    #[serde(rename = "type")]
     pub error_type: String,
     #[yaserde(text)]
     pub body: String,
 }
 
-#[derive(Clone, Debug, Default, PartialEq, YaDeserialize, YaSerialize)]
+#[derive(Clone, Debug, Default, PartialEq, YaDeserialize, YaSerialize, Serialize)]
+#[yaserde(rename = "skipped")]
+pub struct Skipped {
+    #[yaserde(attribute)]
+    pub message: String,
+}
+
+/// A failed attempt of a test case that passed on a retry, like Maven Surefire's `<flakyFailure>`.
+#[derive(Clone, Debug, Default, PartialEq, YaDeserialize, YaSerialize, Serialize)]
+pub struct FlakyFailure {
+    #[yaserde(attribute)]
+    pub message: String,
+    #[yaserde(attribute, rename = "type")]
+    #[serde(rename = "type")]
+    pub error_type: String,
+    /// The output of the attempt, std out and std err combined.
+    #[yaserde(rename = "system-out")]
+    pub system_out: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, YaDeserialize, YaSerialize, Serialize)]
 pub struct TestCase {
     #[yaserde(attribute)]
     pub classname: String,
@@ -32,11 +155,101 @@ pub struct TestCase {
     pub name: String,
     #[yaserde(attribute)]
     pub time: f32,
+    /// When the script started, in RFC 3339.
+    #[yaserde(attribute)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<String>,
+    #[yaserde(attribute)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assertions: Option<u32>,
+    #[yaserde(attribute)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[yaserde(attribute)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+    #[yaserde(child)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub properties: Option<Properties>,
     #[yaserde(child)]
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<TestError>,
+    #[yaserde(child)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure: Option<TestError>,
+    #[yaserde(child)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<Skipped>,
+    /// The attempts that failed before a retry passed.
+    #[yaserde(rename = "flakyFailure")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub flaky_failures: Vec<FlakyFailure>,
+    /// The attempts that had an error before a retry passed.
+    #[yaserde(rename = "flakyError")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub flaky_errors: Vec<FlakyFailure>,
 }
 
-#[derive(Clone, Debug, Default, PartialEq, YaDeserialize, YaSerialize)]
+/// The type of the failure of a script that ran but didn't pass, like one with a non-zero exit code.
+pub const ASSERTION_FAILED: &str = "Assertion failed";
+
+/// The outcome of a test case.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Passed,
+    Failed,
+    Errored,
+    Skipped,
+}
+
+impl TestCase {
+    /// Clean up every text in the test case, like `TestSuite::sanitize`.
+    pub fn sanitize(&mut self, ansi: bool) {
+        let clean = |text: &mut String| clean(text, ansi);
+        clean(&mut self.classname);
+        clean(&mut self.name);
+        if let Some(file) = &mut self.file {
+            clean(file);
+        }
+        for property in self
+            .properties
+            .iter_mut()
+            .flat_map(|properties| &mut properties.properties)
+        {
+            clean(&mut property.name);
+            clean(&mut property.value);
+        }
+        for error in [&mut self.error, &mut self.failure].into_iter().flatten() {
+            clean(&mut error.message);
+            clean(&mut error.error_type);
+            clean(&mut error.body);
+        }
+        if let Some(skipped) = &mut self.skipped {
+            clean(&mut skipped.message);
+        }
+        for flaky in self.flaky_failures.iter_mut().chain(&mut self.flaky_errors) {
+            clean(&mut flaky.message);
+            clean(&mut flaky.error_type);
+            clean(&mut flaky.system_out);
+        }
+    }
+
+    /// The outcome of the test case, where an error takes precedence over a failure.
+    pub fn status(&self) -> Status {
+        if self.error.is_some() {
+            Status::Errored
+        } else if self.failure.is_some() {
+            Status::Failed
+        } else if self.skipped.is_some() {
+            Status::Skipped
+        } else {
+            Status::Passed
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, YaDeserialize, YaSerialize, Serialize)]
 #[yaserde(rename = "testsuite")]
 pub struct TestSuite {
     #[yaserde(attribute)]
@@ -48,6 +261,8 @@ pub struct TestSuite {
     #[yaserde(attribute)]
     pub name: String,
     #[yaserde(attribute)]
+    pub skipped: u32,
+    #[yaserde(attribute)]
     pub tests: u32,
     #[yaserde(attribute)]
     pub time: f32,
@@ -55,10 +270,313 @@ pub struct TestSuite {
     pub timestamp: String,
     #[yaserde(child)]
     pub properties: Properties,
+    // The test cases come before the output, in the order of the JUnit schemas.
+    #[yaserde(rename = "testcase")]
+    pub testcases: Vec<TestCase>,
     #[yaserde(rename = "system-out")]
     pub system_out: String,
     #[yaserde(rename = "system-err")]
     pub system_err: String,
-    #[yaserde(rename = "testcase")]
-    pub testcases: Vec<TestCase>,
+}
+
+impl TestSuite {
+    /// Read a JUnit report from a file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let xml = fs::read_to_string(path)
+            .map_err(|err| anyhow!("Failed to read {}: {}", path.display(), err))?;
+        yaserde::de::from_str(&xml)
+            .map_err(|err| anyhow!("Failed to parse {}: {}", path.display(), err))
+    }
+
+    /**
+    Clean up every text in the suite so it can be serialized as valid XML.
+
+    - `ansi` also remove ANSI escape sequences, instead of only their control characters.
+    */
+    pub fn sanitize(&mut self, ansi: bool) {
+        let clean = |text: &mut String| clean(text, ansi);
+        clean(&mut self.name);
+        clean(&mut self.system_out);
+        clean(&mut self.system_err);
+        for property in &mut self.properties.properties {
+            clean(&mut property.name);
+            clean(&mut property.value);
+        }
+        for testcase in &mut self.testcases {
+            testcase.sanitize(ansi);
+        }
+    }
+
+    /**
+    Fold the test cases, counters, output and properties of `other` into this suite.
+
+    Suite-level attributes like `name`, `hostname` and `timestamp` are kept from this suite.
+
+    - `other` the suite to merge in.
+    - `strategy` how properties present in both suites are resolved.
+    */
+    pub fn merge(&mut self, other: TestSuite, strategy: MergeProperties) -> anyhow::Result<()> {
+        self.properties.merge(other.properties, strategy)?;
+        self.errors += other.errors;
+        self.failures += other.failures;
+        self.skipped += other.skipped;
+        self.tests += other.tests;
+        self.time += other.time;
+        for (own, theirs) in [
+            (&mut self.system_out, other.system_out),
+            (&mut self.system_err, other.system_err),
+        ] {
+            if !own.is_empty() && !theirs.is_empty() {
+                own.push('\n');
+            }
+            own.push_str(&theirs);
+        }
+        self.testcases.extend(other.testcases);
+        Ok(())
+    }
+
+    /// Rename test cases that repeat the classname and name of an earlier one, like `name#2`, as
+    /// some consumers merge or drop duplicates.
+    pub fn disambiguate(&mut self) {
+        let mut names = UniqueNames::default();
+        for testcase in &mut self.testcases {
+            names.rename(testcase);
+        }
+    }
+
+    /// Sort the test cases in `order`. Test cases that compare equal keep their order.
+    pub fn sort(&mut self, order: SortOrder) {
+        let path =
+            |testcase: &TestCase| testcase.file.clone().unwrap_or(testcase.classname.clone());
+        match order {
+            SortOrder::Input => {}
+            SortOrder::Name => self
+                .testcases
+                .sort_by(|a, b| (&a.name, &a.classname).cmp(&(&b.name, &b.classname))),
+            SortOrder::Path => self
+                .testcases
+                .sort_by(|a, b| (path(a), &a.name).cmp(&(path(b), &b.name))),
+            SortOrder::Duration => self.testcases.sort_by(|a, b| b.time.total_cmp(&a.time)),
+        }
+    }
+}
+
+/// The classnames and names of the test cases seen so far, to rename the ones that repeat them.
+#[derive(Debug, Default)]
+pub struct UniqueNames(HashSet<(String, String)>);
+
+impl UniqueNames {
+    /// Rename `testcase` like `name#2` if an earlier one had the same classname and name.
+    pub fn rename(&mut self, testcase: &mut TestCase) {
+        let mut name = testcase.name.clone();
+        let mut occurrence = 1;
+        while !self.0.insert((testcase.classname.clone(), name.clone())) {
+            occurrence += 1;
+            name = format!("{}#{}", testcase.name, occurrence);
+        }
+        testcase.name = name;
+    }
+}
+
+/// Remove the characters XML can't represent from `text`, and with `ansi` also ANSI escape sequences.
+fn clean(text: &mut String, ansi: bool) {
+    let stripped = if ansi { strip_ansi(text) } else { text.clone() };
+    *text = strip_invalid_xml_chars(&stripped);
+}
+
+/// Milliseconds since the epoch of an RFC 3339 timestamp, like those of the reports, or 0 if it
+/// isn't one.
+pub(crate) fn epoch_millis(timestamp: &str) -> i64 {
+    chrono::DateTime::parse_from_rfc3339(timestamp).map_or(0, |time| time.timestamp_millis())
+}
+
+/// A JUnit document with several test suites under a `<testsuites>` root.
+#[derive(Clone, Debug, Default, PartialEq, YaDeserialize, YaSerialize)]
+#[yaserde(rename = "testsuites")]
+pub struct TestSuites {
+    #[yaserde(attribute)]
+    pub errors: u32,
+    #[yaserde(attribute)]
+    pub failures: u32,
+    #[yaserde(attribute)]
+    pub skipped: u32,
+    #[yaserde(attribute)]
+    pub tests: u32,
+    #[yaserde(attribute)]
+    pub time: f32,
+    #[yaserde(rename = "testsuite")]
+    pub testsuites: Vec<TestSuite>,
+}
+
+impl TestSuites {
+    /// Read a JUnit report from a file, which may have a single `<testsuite>` as its root.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let xml = fs::read_to_string(path)
+            .map_err(|err| anyhow!("Failed to read {}: {}", path.display(), err))?;
+        if root_element(&xml) == Some("testsuite") {
+            let mut testsuites = TestSuites::default();
+            testsuites.push(TestSuite::from_file(path)?);
+            return Ok(testsuites);
+        }
+        yaserde::de::from_str(&xml)
+            .map_err(|err| anyhow!("Failed to parse {}: {}", path.display(), err))
+    }
+
+    /// Add a test suite, updating the totals.
+    pub fn push(&mut self, testsuite: TestSuite) {
+        self.errors += testsuite.errors;
+        self.failures += testsuite.failures;
+        self.skipped += testsuite.skipped;
+        self.tests += testsuite.tests;
+        self.time += testsuite.time;
+        self.testsuites.push(testsuite);
+    }
+}
+
+/// The name of the root element of an XML document, after the declaration and any comments.
+fn root_element(xml: &str) -> Option<&str> {
+    let mut rest = xml;
+    loop {
+        rest = &rest[rest.find('<')? + 1..];
+        if !rest.starts_with('?') && !rest.starts_with('!') {
+            let end = rest.find(|c: char| c.is_whitespace() || c == '>' || c == '/')?;
+            return Some(&rest[..end]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        MergeProperties, Properties, Property, SortOrder, TestCase, TestSuite, TestSuites,
+    };
+
+    fn merged(strategy: MergeProperties) -> anyhow::Result<TestSuite> {
+        let mut suite = TestSuite::from_file("tests/fixtures/merge_a.xml")?;
+        suite.merge(
+            TestSuite::from_file("tests/fixtures/merge_b.xml")?,
+            strategy,
+        )?;
+        Ok(suite)
+    }
+
+    fn values(suite: &TestSuite, name: &str) -> Vec<String> {
+        suite
+            .properties
+            .properties
+            .iter()
+            .filter(|property| property.name == name)
+            .map(|property| property.value.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_counters() {
+        let suite = merged(MergeProperties::First).unwrap();
+        assert_eq!(suite.name, "a");
+        assert_eq!(suite.tests, 2);
+        assert_eq!(suite.failures, 1);
+        assert_eq!(suite.testcases.len(), 2);
+        assert!(suite.testcases[1].failure.is_some());
+        assert_eq!(values(&suite, "ONLY_B"), ["b"]);
+    }
+
+    #[test]
+    fn test_merge_properties() {
+        let first = merged(MergeProperties::First).unwrap();
+        assert_eq!(values(&first, "BRANCH"), ["main"]);
+        let last = merged(MergeProperties::Last).unwrap();
+        assert_eq!(values(&last, "BRANCH"), ["feature"]);
+        let union = merged(MergeProperties::Union).unwrap();
+        assert_eq!(values(&union, "BRANCH"), ["main", "feature"]);
+        assert!(merged(MergeProperties::Error).is_err());
+    }
+
+    #[test]
+    fn test_testcase_properties() {
+        let suite = TestSuite {
+            testcases: vec![TestCase {
+                name: String::from("smoke"),
+                properties: Some(Properties {
+                    properties: vec![Property {
+                        name: String::from("category"),
+                        value: String::from("smoke"),
+                    }],
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let xml = yaserde::ser::to_string(&suite).unwrap();
+        assert!(xml.contains(
+            r#"<testcase classname="" name="smoke" time="0"><properties><property name="category" value="smoke" /></properties></testcase>"#
+        ));
+        let reparsed: TestSuite = yaserde::de::from_str(&xml).unwrap();
+        assert_eq!(reparsed, suite);
+    }
+
+    #[test]
+    fn test_testsuites_from_testsuite_file() {
+        let mut suites = TestSuites::from_file("tests/fixtures/merge_a.xml").unwrap();
+        suites.push(TestSuite::from_file("tests/fixtures/merge_b.xml").unwrap());
+        assert_eq!(suites.testsuites.len(), 2);
+        assert_eq!(suites.tests, 2);
+        assert_eq!(suites.failures, 1);
+        let xml = yaserde::ser::to_string(&suites).unwrap();
+        let reparsed: TestSuites = yaserde::de::from_str(&xml).unwrap();
+        assert_eq!(reparsed, suites);
+    }
+
+    #[test]
+    fn test_sort_testcases() {
+        let testcase = |name: &str, file: &str, time: f32| TestCase {
+            name: name.to_string(),
+            classname: String::from("test"),
+            file: Some(file.to_string()),
+            time,
+            ..Default::default()
+        };
+        let suite = TestSuite {
+            testcases: vec![
+                testcase("b", "test/z/b.sh", 0.5),
+                testcase("c", "test/a/c.sh", 2.0),
+                testcase("a", "test/m/a.sh", 0.5),
+            ],
+            ..Default::default()
+        };
+        let sorted = |order: SortOrder| {
+            let mut suite = suite.clone();
+            suite.sort(order);
+            let names: Vec<String> = suite.testcases.into_iter().map(|t| t.name).collect();
+            names.join(",")
+        };
+        assert_eq!(sorted(SortOrder::Input), "b,c,a");
+        assert_eq!(sorted(SortOrder::Name), "a,b,c");
+        assert_eq!(sorted(SortOrder::Path), "c,a,b");
+        assert_eq!(sorted(SortOrder::Duration), "c,b,a");
+    }
+
+    #[test]
+    fn test_disambiguate_testcases() {
+        let testcase = |classname: &str, name: &str| TestCase {
+            classname: classname.to_string(),
+            name: name.to_string(),
+            ..Default::default()
+        };
+        let mut suite = TestSuite {
+            testcases: vec![
+                testcase("test", "a"),
+                testcase("test", "a"),
+                testcase("other", "a"),
+                testcase("test", "a#2"),
+                testcase("test", "a"),
+            ],
+            ..Default::default()
+        };
+        suite.disambiguate();
+        let names: Vec<&str> = suite.testcases.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["a", "a#2", "a", "a#2#2", "a#3"]);
+    }
 }
@@ -0,0 +1,191 @@
+//! Derivation of JUnit classnames and test case names from script paths.
+
+use std::{env, fs, path};
+
+/// Marker file whose contents name the package of the scripts below it.
+pub const PACKAGE_FILE: &str = ".shunit-package";
+
+/// The ways a JUnit classname can be derived from a script path.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClassnameStrategy {
+    /// The canonical absolute path of the script.
+    Absolute,
+    /// The path relative to the current working directory.
+    Relative,
+    /// Just the file name of the script.
+    Basename,
+    /// The parent directory relative to the current working directory, as a pseudo-package.
+    Dirname,
+}
+
+impl ClassnameStrategy {
+    pub const VARIANTS: [&'static str; 4] = ["absolute", "relative", "basename", "dirname"];
+
+    /// Derive the classname of the script at `absolute_path` (as returned by `fs::canonicalize`).
+    pub fn classname(&self, absolute_path: &path::Path) -> String {
+        let relative = || {
+            env::current_dir()
+                .ok()
+                .and_then(|cwd| {
+                    absolute_path
+                        .strip_prefix(cwd)
+                        .ok()
+                        .map(path::Path::to_path_buf)
+                })
+                .unwrap_or_else(|| absolute_path.to_path_buf())
+        };
+        let classname = match self {
+            ClassnameStrategy::Absolute => absolute_path.to_path_buf(),
+            ClassnameStrategy::Relative => relative(),
+            ClassnameStrategy::Basename => absolute_path
+                .file_name()
+                .map(path::PathBuf::from)
+                .unwrap_or_default(),
+            ClassnameStrategy::Dirname => relative()
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .map(path::Path::to_path_buf)
+                .unwrap_or_else(|| path::PathBuf::from(".")),
+        };
+        classname.to_string_lossy().into_owned()
+    }
+
+    /// The path of the script at `absolute_path` in the style of this strategy. `Dirname` gives
+    /// the relative path, as a directory is no file.
+    pub fn file(&self, absolute_path: &path::Path) -> String {
+        match self {
+            ClassnameStrategy::Dirname => ClassnameStrategy::Relative.classname(absolute_path),
+            _ => self.classname(absolute_path),
+        }
+    }
+}
+
+impl std::str::FromStr for ClassnameStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "absolute" => Ok(ClassnameStrategy::Absolute),
+            "relative" => Ok(ClassnameStrategy::Relative),
+            "basename" => Ok(ClassnameStrategy::Basename),
+            "dirname" => Ok(ClassnameStrategy::Dirname),
+            _ => Err(anyhow!("Unknown classname strategy: {}", s)),
+        }
+    }
+}
+
+/// The ways a JUnit test case name can be derived from a script path.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NameStrategy {
+    /// The path exactly as given on the command line.
+    Path,
+    /// The file name of the script.
+    Filename,
+    /// The file name of the script without its extension.
+    Stem,
+}
+
+impl NameStrategy {
+    pub const VARIANTS: [&'static str; 3] = ["path", "filename", "stem"];
+
+    /// Derive the test case name of the script given as `script` on the command line.
+    pub fn name(&self, script: &str) -> String {
+        let script_path = path::Path::new(script);
+        let name = match self {
+            NameStrategy::Path => None,
+            NameStrategy::Filename => script_path.file_name(),
+            NameStrategy::Stem => script_path.file_stem(),
+        };
+        name.map_or_else(
+            || script.to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        )
+    }
+}
+
+impl std::str::FromStr for NameStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "path" => Ok(NameStrategy::Path),
+            "filename" => Ok(NameStrategy::Filename),
+            "stem" => Ok(NameStrategy::Stem),
+            _ => Err(anyhow!("Unknown name strategy: {}", s)),
+        }
+    }
+}
+
+/**
+Derive a classname from the nearest package marker file above the script.
+
+The classname is the contents of the marker followed by the path of the script relative to the
+directory containing the marker, joined with dots. Returns `None` if no marker file is found.
+
+- `absolute_path` the canonical path of the script.
+*/
+pub fn package_classname(absolute_path: &path::Path) -> Option<String> {
+    absolute_path.ancestors().skip(1).find_map(|dir| {
+        let package = fs::read_to_string(dir.join(PACKAGE_FILE)).ok()?;
+        let relative = absolute_path.strip_prefix(dir).ok()?;
+        let segments = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned());
+        let classname: Vec<String> = std::iter::once(package.trim().to_string())
+            .filter(|package| !package.is_empty())
+            .chain(segments)
+            .collect();
+        Some(classname.join("."))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::naming::{package_classname, ClassnameStrategy, NameStrategy};
+    use std::env;
+
+    #[test]
+    fn test_classname_strategy() {
+        let path = env::current_dir().unwrap().join("test").join("im_ok.sh");
+        assert_eq!(
+            ClassnameStrategy::Absolute.classname(&path),
+            path.to_string_lossy()
+        );
+        assert_eq!(
+            ClassnameStrategy::Relative.classname(&path),
+            "test/im_ok.sh"
+        );
+        assert_eq!(ClassnameStrategy::Basename.classname(&path), "im_ok.sh");
+        assert_eq!(ClassnameStrategy::Dirname.classname(&path), "test");
+        assert_eq!(ClassnameStrategy::Dirname.file(&path), "test/im_ok.sh");
+        assert_eq!(ClassnameStrategy::Basename.file(&path), "im_ok.sh");
+    }
+
+    #[test]
+    fn test_package_classname() {
+        let cwd = env::current_dir().unwrap();
+        let packaged = cwd.join("tests/fixtures/package/checkout/pay.sh");
+        assert_eq!(
+            package_classname(&packaged),
+            Some("billing.checkout.pay.sh".to_string())
+        );
+        assert_eq!(package_classname(&cwd.join("test/im_ok.sh")), None);
+    }
+
+    #[test]
+    fn test_name_strategy() {
+        assert_eq!(
+            NameStrategy::Path.name("./test/im_ok.sh"),
+            "./test/im_ok.sh"
+        );
+        assert_eq!(NameStrategy::Filename.name("./test/im_ok.sh"), "im_ok.sh");
+        assert_eq!(NameStrategy::Stem.name("./test/im_ok.sh"), "im_ok");
+        assert_eq!(NameStrategy::Path.name("/usr/bin/true"), "/usr/bin/true");
+        assert_eq!(NameStrategy::Filename.name("/usr/bin/true"), "true");
+        assert_eq!(NameStrategy::Stem.name("/usr/bin/true"), "true");
+        assert_eq!(
+            NameStrategy::Stem.name("test/archive.tar.gz"),
+            "archive.tar"
+        );
+    }
+}
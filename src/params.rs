@@ -0,0 +1,89 @@
+//! The `--param-file`, which lists the sets of arguments to run every script with, one per line.
+
+use std::fs;
+
+/**
+Split a line into words like a shell does, without expanding anything: words are separated by
+whitespace, and `'...'` and `"..."` quote whitespace and the other quote. A `\` escapes the next
+character, except between single quotes.
+*/
+fn split_words(line: &str) -> anyhow::Result<Vec<String>> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' | '"' => {
+                let quote = c;
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some(c) if c == quote => break,
+                        Some('\\') if quote == '"' => word.extend(chars.next()),
+                        Some(c) => word.push(c),
+                        None => bail!("Unterminated {} in `{}`", quote, line),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => bail!("Trailing \\ in `{}`", line),
+            },
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Parse a parameter file into its sets of arguments, in order. Blank lines and `#` comments are
+/// left out.
+fn parse(text: &str) -> anyhow::Result<Vec<Vec<String>>> {
+    let sets = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(split_words)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if sets.is_empty() {
+        bail!("No parameter sets");
+    }
+    Ok(sets)
+}
+
+/// Read the sets of arguments of the parameter file at `path`.
+pub fn load(path: &str) -> anyhow::Result<Vec<Vec<String>>> {
+    let text =
+        fs::read_to_string(path).map_err(|err| anyhow!("Failed to read {}: {}", path, err))?;
+    parse(&text).map_err(|err| anyhow!("Failed to parse {}: {}", path, err))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, split_words};
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(
+            split_words(r#"--prefix /opt  "two words" 'it"s' it\'s a""b"#).unwrap(),
+            ["--prefix", "/opt", "two words", "it\"s", "it's", "ab"]
+        );
+        assert_eq!(split_words(r#""" '\n'"#).unwrap(), ["", "\\n"]);
+        assert!(split_words("'open").is_err());
+        assert!(split_words("trailing\\").is_err());
+    }
+
+    #[test]
+    fn test_parse_params() {
+        let text = "# installers\n--prefix /opt\n\n  --prefix /usr --dry-run\n";
+        assert_eq!(
+            parse(text).unwrap(),
+            [
+                vec!["--prefix", "/opt"],
+                vec!["--prefix", "/usr", "--dry-run"]
+            ]
+        );
+        assert!(parse("# nothing\n").is_err());
+    }
+}
@@ -0,0 +1,78 @@
+//! Measuring the peak memory and CPU time of a script, where the platform supports it.
+
+use std::{io, process::ExitStatus, time::Duration};
+use tokio::process::Child;
+
+/// The resources a script and its waited-for children used.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ResourceUsage {
+    /// The peak resident set size.
+    pub peak_rss_bytes: u64,
+    /// The user plus system CPU time.
+    pub cpu_time: Duration,
+}
+
+impl ResourceUsage {
+    /// The usage as test case properties.
+    pub fn properties(&self) -> Vec<crate::Property> {
+        vec![
+            crate::Property {
+                name: String::from("peak_rss_bytes"),
+                value: self.peak_rss_bytes.to_string(),
+            },
+            crate::Property {
+                name: String::from("cpu_seconds"),
+                value: format!("{:.3}", self.cpu_time.as_secs_f64()),
+            },
+        ]
+    }
+}
+
+/**
+Wait for `child` to exit and measure the resources it used with `wait4`.
+
+The child is reaped here rather than by tokio, which is only told about it when the handle is
+dropped afterwards.
+*/
+#[cfg(unix)]
+pub async fn wait_with_usage(child: Child) -> io::Result<(ExitStatus, Option<ResourceUsage>)> {
+    use std::os::unix::process::ExitStatusExt;
+
+    let pid = match child.id() {
+        Some(pid) => pid as libc::pid_t,
+        None => return without_usage(child).await,
+    };
+    let (status, usage) = tokio::task::spawn_blocking(move || {
+        let mut status = 0;
+        // SAFETY: an all-zero `rusage` is valid, and both pointers outlive the call.
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        let result = unsafe { libc::wait4(pid, &mut status, 0, &mut usage) };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok((status, usage))
+    })
+    .await??;
+    drop(child);
+
+    let time = |time: libc::timeval| {
+        Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+    };
+    // Linux reports the peak resident set size in KiB, macOS in bytes.
+    let rss_unit = if cfg!(target_os = "macos") { 1 } else { 1024 };
+    let usage = ResourceUsage {
+        peak_rss_bytes: usage.ru_maxrss as u64 * rss_unit,
+        cpu_time: time(usage.ru_utime) + time(usage.ru_stime),
+    };
+    Ok((ExitStatus::from_raw(status), Some(usage)))
+}
+
+#[cfg(not(unix))]
+pub async fn wait_with_usage(child: Child) -> io::Result<(ExitStatus, Option<ResourceUsage>)> {
+    without_usage(child).await
+}
+
+/// Wait for `child` to exit, without measuring its resources.
+async fn without_usage(mut child: Child) -> io::Result<(ExitStatus, Option<ResourceUsage>)> {
+    Ok((child.wait().await?, None))
+}
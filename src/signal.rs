@@ -0,0 +1,154 @@
+//! Parsing and naming of the POSIX signals that can terminate a script.
+
+use std::{io, process::ExitStatus};
+use tokio::process::{Child, Command};
+
+/// The signal Ctrl-C sends.
+#[cfg(unix)]
+pub const INTERRUPT: i32 = libc::SIGINT;
+
+/// The number `SIGINT` has elsewhere.
+#[cfg(not(unix))]
+pub const INTERRUPT: i32 = 2;
+
+/// Signal names and numbers understood on the command line.
+#[cfg(unix)]
+const SIGNALS: &[(&str, i32)] = &[
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("ILL", libc::SIGILL),
+    ("TRAP", libc::SIGTRAP),
+    ("ABRT", libc::SIGABRT),
+    ("BUS", libc::SIGBUS),
+    ("FPE", libc::SIGFPE),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("SEGV", libc::SIGSEGV),
+    ("USR2", libc::SIGUSR2),
+    ("PIPE", libc::SIGPIPE),
+    ("ALRM", libc::SIGALRM),
+    ("TERM", libc::SIGTERM),
+    ("XCPU", libc::SIGXCPU),
+    ("XFSZ", libc::SIGXFSZ),
+];
+
+#[cfg(not(unix))]
+const SIGNALS: &[(&str, i32)] = &[];
+
+/// Parse a signal given by name (`KILL`, `SIGKILL`) or by number (`9`).
+pub fn parse_signal(s: &str) -> anyhow::Result<i32> {
+    if let Ok(number) = s.parse::<i32>() {
+        return Ok(number);
+    }
+    let name = s.trim_start_matches("SIG").to_uppercase();
+    SIGNALS
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|(_, number)| *number)
+        .ok_or_else(|| anyhow!("Unknown signal: {}", s))
+}
+
+/// The conventional name of a signal, like `SIGKILL`, falling back to its number.
+pub fn signal_name(signal: i32) -> String {
+    SIGNALS
+        .iter()
+        .find(|(_, number)| *number == signal)
+        .map_or_else(|| signal.to_string(), |(name, _)| format!("SIG{}", name))
+}
+
+/// The signal that terminated a process, if it was terminated by one.
+#[cfg(unix)]
+pub fn terminating_signal(status: &ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+#[cfg(not(unix))]
+pub fn terminating_signal(_status: &ExitStatus) -> Option<i32> {
+    None
+}
+
+/// Whether sending `signal` kills a process outright, as `SIGKILL` does.
+#[cfg(unix)]
+pub fn is_kill(signal: i32) -> bool {
+    signal == libc::SIGKILL
+}
+
+/// Without signals, every signal is a kill.
+#[cfg(not(unix))]
+pub fn is_kill(_signal: i32) -> bool {
+    true
+}
+
+/// Start the command in a process group of its own, which `send_signal` can signal as a whole.
+#[cfg(unix)]
+pub fn own_process_group(command: &mut Command) {
+    // SAFETY: `setpgid` is async-signal-safe, and nothing is allocated in the closure.
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setpgid(0, 0) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Without process groups, the command is left as it is.
+#[cfg(not(unix))]
+pub fn own_process_group(_command: &mut Command) {}
+
+/**
+Send `signal` to `child`, without waiting for it to exit. With `group`, the signal goes to the
+process group `child` leads, so the processes it started get it too. Otherwise `SIGKILL` goes
+through tokio.
+*/
+#[cfg(unix)]
+pub fn send_signal(child: &mut Child, signal: i32, group: bool) -> io::Result<()> {
+    let pid = match child.id() {
+        Some(pid) if group => -(pid as libc::pid_t),
+        Some(pid) if !is_kill(signal) => pid as libc::pid_t,
+        _ => return child.start_kill(),
+    };
+    // SAFETY: `kill` has no memory safety requirements, and the child isn't reaped yet, so its
+    // process group still exists.
+    if unsafe { libc::kill(pid, signal) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Without signals, the child is always killed.
+#[cfg(not(unix))]
+pub fn send_signal(child: &mut Child, _signal: i32, _group: bool) -> io::Result<()> {
+    child.start_kill()
+}
+
+/// Kill `child`, and with `group` every process in its process group, see `send_signal`.
+#[cfg(unix)]
+pub fn kill(child: &mut Child, group: bool) -> io::Result<()> {
+    send_signal(child, libc::SIGKILL, group)
+}
+
+/// Without process groups, only the child is killed.
+#[cfg(not(unix))]
+pub fn kill(child: &mut Child, _group: bool) -> io::Result<()> {
+    child.start_kill()
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::{parse_signal, signal_name};
+
+    #[test]
+    fn test_parse_signal() {
+        assert_eq!(parse_signal("KILL").unwrap(), libc::SIGKILL);
+        assert_eq!(parse_signal("SIGBUS").unwrap(), libc::SIGBUS);
+        assert_eq!(parse_signal("term").unwrap(), libc::SIGTERM);
+        assert_eq!(parse_signal("9").unwrap(), 9);
+        assert!(parse_signal("NOPE").is_err());
+        assert_eq!(signal_name(libc::SIGKILL), "SIGKILL");
+        assert_eq!(signal_name(1234), "1234");
+    }
+}
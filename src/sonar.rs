@@ -0,0 +1,102 @@
+//! Rendering of a test suite in SonarQube's generic test execution format, which SonarQube imports
+//! with `sonar.testExecutionReportPaths`.
+
+use crate::model::{Status, TestCase, TestSuite};
+use crate::text::escape_html;
+
+/// The `<testCase>` element of a test case, with its duration in milliseconds.
+fn test_case(testcase: &TestCase) -> String {
+    let start = format!(
+        "    <testCase name=\"{}\" duration=\"{:.0}\"",
+        escape_html(&testcase.name),
+        testcase.time * 1000.0
+    );
+    let (element, problem) = match testcase.status() {
+        Status::Passed => return start + "/>\n",
+        Status::Failed => ("failure", testcase.failure.as_ref()),
+        Status::Errored => ("error", testcase.error.as_ref()),
+        Status::Skipped => {
+            let reason = testcase
+                .skipped
+                .as_ref()
+                .map_or("", |skipped| &skipped.message);
+            return format!(
+                "{}>\n      <skipped message=\"{}\"/>\n    </testCase>\n",
+                start,
+                escape_html(reason)
+            );
+        }
+    };
+    let problem = problem.expect("a failed test case has a failure or an error");
+    format!(
+        "{}>\n      <{element} message=\"{}\">{}</{element}>\n    </testCase>\n",
+        start,
+        escape_html(&problem.message),
+        escape_html(&problem.body),
+        element = element
+    )
+}
+
+/**
+Render the test suite as a `<testExecutions>` document, with a `<file>` per script, in the order
+they were first reported, and a `<testCase>` per test case of that script.
+
+The path of a script is the `file` of its test cases. SonarQube takes absolute paths, and relative
+ones from the root of the project.
+*/
+pub fn render(testsuite: &TestSuite) -> String {
+    let mut files: Vec<(&str, Vec<&TestCase>)> = vec![];
+    for testcase in &testsuite.testcases {
+        let path = testcase.file.as_deref().unwrap_or(&testcase.classname);
+        match files.iter_mut().find(|(file, _)| *file == path) {
+            Some((_, testcases)) => testcases.push(testcase),
+            None => files.push((path, vec![testcase])),
+        }
+    }
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<testExecutions version=\"1\">\n",
+    );
+    for (path, testcases) in files {
+        xml.push_str(&format!("  <file path=\"{}\">\n", escape_html(path)));
+        xml.extend(testcases.into_iter().map(test_case));
+        xml.push_str("  </file>\n");
+    }
+    xml.push_str("</testExecutions>\n");
+    xml
+}
+
+#[cfg(test)]
+mod test {
+    use super::render;
+    use crate::model::{Skipped, TestCase, TestSuite};
+
+    #[test]
+    fn test_render() {
+        let mut testsuite = TestSuite::from_file("tests/fixtures/compact.xml").unwrap();
+        testsuite.testcases.push(TestCase {
+            classname: String::from("test/im_ok.sh"),
+            name: String::from("later <maybe>"),
+            skipped: Some(Skipped {
+                message: String::from("Skipped by --skip pattern: later"),
+            }),
+            ..Default::default()
+        });
+        assert_eq!(
+            render(&testsuite),
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+             <testExecutions version=\"1\">\n  \
+             <file path=\"test/im_ok.sh\">\n    \
+             <testCase name=\"./test/im_ok.sh\" duration=\"500\"/>\n    \
+             <testCase name=\"later &lt;maybe&gt;\" duration=\"0\">\n      \
+             <skipped message=\"Skipped by --skip pattern: later\"/>\n    \
+             </testCase>\n  \
+             </file>\n  \
+             <file path=\"test/bad_apple.sh\">\n    \
+             <testCase name=\"./test/bad_apple.sh\" duration=\"1000\">\n      \
+             <failure message=\"Non-zero exit-code: 2\">ls: cannot access</failure>\n    \
+             </testCase>\n  \
+             </file>\n\
+             </testExecutions>\n"
+        );
+    }
+}
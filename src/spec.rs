@@ -0,0 +1,85 @@
+//! A script to run, with the settings that override the [`Options`] of the suite for it.
+
+use crate::Options;
+use std::{path::PathBuf, time::Duration};
+
+/// A script to run and its own settings, like an entry of a `--manifest`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TestSpec {
+    /// The path of the script.
+    pub script: String,
+    /// The arguments the script is called with.
+    pub args: Vec<String>,
+    /// Environment variables set for the script, after and on top of `Options::env`.
+    pub env: Vec<(String, String)>,
+    /// Replaces `Options::timeout`. A `shunit-timeout` in the script still takes precedence.
+    pub timeout: Option<Duration>,
+    /// The working directory of the script, instead of the current one.
+    pub cwd: Option<PathBuf>,
+    /// The test case name, instead of the one derived by `Options::name_strategy`.
+    pub name: Option<String>,
+    /// Replaces `Options::expect_exit`.
+    pub expect_exit: Option<Vec<i32>>,
+}
+
+impl TestSpec {
+    /// The options the script runs with: `options` with the settings of this spec applied.
+    pub fn options(&self, options: &Options) -> Options {
+        let mut options = options.clone();
+        options.env.extend(self.env.iter().cloned());
+        if let Some(timeout) = self.timeout {
+            options.timeout = Some(timeout);
+        }
+        if let Some(expect_exit) = &self.expect_exit {
+            options.expect_exit.clone_from(expect_exit);
+        }
+        options
+    }
+}
+
+impl From<&str> for TestSpec {
+    fn from(script: &str) -> Self {
+        TestSpec {
+            script: script.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<String> for TestSpec {
+    fn from(script: String) -> Self {
+        TestSpec {
+            script,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::spec::TestSpec;
+    use crate::Options;
+    use std::time::Duration;
+
+    #[test]
+    fn test_spec_overrides_options() {
+        let options = Options {
+            env: vec![("A".to_string(), "1".to_string())],
+            expect_exit: vec![3],
+            timeout: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+        let spec = TestSpec {
+            env: vec![("A".to_string(), "2".to_string())],
+            timeout: Some(Duration::from_secs(5)),
+            ..TestSpec::from("test/im_ok.sh")
+        };
+        let options = spec.options(&options);
+        assert_eq!(
+            options.env.last(),
+            Some(&("A".to_string(), "2".to_string()))
+        );
+        assert_eq!(options.timeout, Some(Duration::from_secs(5)));
+        assert_eq!(options.expect_exit, vec![3]);
+    }
+}
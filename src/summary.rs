@@ -0,0 +1,88 @@
+//! Rendering of the totals of a test suite as a small JSON object, e.g. for status badges.
+
+use crate::flaky::{pass_rates, PassRate};
+use crate::model::{Status, TestSuite};
+use serde::Serialize;
+
+/// The totals of a suite and the names of its unsuccessful test cases.
+#[derive(Serialize)]
+struct Summary<'a> {
+    tests: u32,
+    failures: u32,
+    errors: u32,
+    skipped: u32,
+    time: f32,
+    started: &'a str,
+    finished: &'a str,
+    failing: Vec<&'a str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pass_rates: Vec<PassRate<'a>>,
+}
+
+/**
+Render the totals of the suite as a pretty-printed JSON object.
+
+`failing` lists the names of the test cases that failed or had errors. `started` is the timestamp
+of the suite, and `finished` is given by the caller, as the suite doesn't record it. A `--repeat`
+run also has the `pass_rates` of the scripts, each flagged as `flaky` if its runs had mixed results.
+*/
+pub fn render(testsuite: &TestSuite, finished: &str) -> String {
+    let summary = Summary {
+        tests: testsuite.tests,
+        failures: testsuite.failures,
+        errors: testsuite.errors,
+        skipped: testsuite.skipped,
+        time: testsuite.time,
+        started: &testsuite.timestamp,
+        finished,
+        failing: testsuite
+            .testcases
+            .iter()
+            .filter(|testcase| matches!(testcase.status(), Status::Failed | Status::Errored))
+            .map(|testcase| testcase.name.as_str())
+            .collect(),
+        pass_rates: pass_rates(testsuite),
+    };
+    serde_json::to_string_pretty(&summary).expect("a summary is always valid JSON") + "\n"
+}
+
+#[cfg(test)]
+mod test {
+    use crate::model::{TestCase, TestError, TestSuite};
+    use crate::summary::render;
+
+    #[test]
+    fn test_render_summary() {
+        let testsuite = TestSuite {
+            tests: 2,
+            failures: 1,
+            time: 1.5,
+            timestamp: String::from("2024-05-01T12:00:00Z"),
+            testcases: vec![
+                TestCase {
+                    name: String::from("ok"),
+                    ..Default::default()
+                },
+                TestCase {
+                    name: String::from("broken"),
+                    failure: Some(TestError {
+                        message: String::from("Non-zero exit-code: 1"),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let summary: serde_json::Value =
+            serde_json::from_str(&render(&testsuite, "2024-05-01T12:00:02Z")).unwrap();
+        assert_eq!(summary["tests"], 2);
+        assert_eq!(summary["failures"], 1);
+        assert_eq!(summary["errors"], 0);
+        assert_eq!(summary["time"], 1.5);
+        assert_eq!(summary["started"], "2024-05-01T12:00:00Z");
+        assert_eq!(summary["finished"], "2024-05-01T12:00:02Z");
+        assert_eq!(summary["failing"], serde_json::json!(["broken"]));
+        assert_eq!(summary.get("pass_rates"), None);
+    }
+}
@@ -0,0 +1,269 @@
+//! Rendering of a test suite as a TAP version 13 stream, with a test point per test case, and
+//! parsing of the TAP streams scripts print.
+
+use crate::model::{Status, TestCase, TestSuite};
+
+/// Escape a test point description, which ends at a `#` and at the end of the line.
+fn description(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('#', "\\#")
+        .replace(['\r', '\n'], " ")
+}
+
+/// A YAML scalar for `text`. A JSON string is a valid double-quoted YAML scalar.
+fn scalar(text: &str) -> String {
+    serde_json::to_string(text).expect("a string is always valid JSON")
+}
+
+/// The YAML diagnostics of a failed or errored test case, with its captured output.
+fn diagnostics(testcase: &TestCase) -> Option<String> {
+    let problem = testcase.error.as_ref().or(testcase.failure.as_ref())?;
+    let severity = match testcase.status() {
+        Status::Errored => "error",
+        _ => "fail",
+    };
+    let mut yaml = format!(
+        "  ---\n  message: {}\n  severity: {}\n  type: {}\n  duration_ms: {:.0}\n",
+        scalar(&problem.message),
+        severity,
+        scalar(&problem.error_type),
+        testcase.time * 1000.0
+    );
+    if !problem.body.trim().is_empty() {
+        yaml.push_str("  output: |\n");
+        for line in problem.body.trim_end().lines() {
+            yaml.push_str(&format!("    {}\n", line));
+        }
+    }
+    yaml.push_str("  ...\n");
+    Some(yaml)
+}
+
+/// Render the test suite as a TAP stream: the plan, then a test point per test case, in order.
+pub fn render(testsuite: &TestSuite) -> String {
+    let mut tap = format!("TAP version 13\n1..{}\n", testsuite.testcases.len());
+    for (number, testcase) in testsuite.testcases.iter().enumerate() {
+        let point = format!("{} - {}", number + 1, description(&testcase.name));
+        match testcase.status() {
+            Status::Passed => tap.push_str(&format!("ok {}\n", point)),
+            Status::Skipped => {
+                let reason = testcase
+                    .skipped
+                    .as_ref()
+                    .map_or("", |skipped| &skipped.message);
+                tap.push_str(&format!("ok {} # SKIP {}\n", point, description(reason)));
+            }
+            Status::Failed | Status::Errored => {
+                tap.push_str(&format!("not ok {}\n", point));
+                tap.extend(diagnostics(testcase));
+            }
+        }
+    }
+    tap
+}
+
+/// The `# SKIP` or `# TODO` directive of a test point, with its reason.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Directive {
+    Skip(String),
+    Todo(String),
+}
+
+/// A test point parsed from a TAP stream.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestPoint {
+    /// Whether the line starts with `ok` rather than `not ok`.
+    pub ok: bool,
+    /// The number of the test point, or its position if it has none.
+    pub number: u32,
+    /// The description, unescaped, without the `-` in front of it.
+    pub description: String,
+    pub directive: Option<Directive>,
+    /// The index of the line of the test point in the stream.
+    pub line: usize,
+    /// The lines after the test point, up to the next one, like its YAML block and comments.
+    pub diagnostics: Vec<String>,
+}
+
+/// The plan, the test points and the bail out of a TAP stream.
+#[derive(Debug, Default, PartialEq)]
+pub struct Stream {
+    /// The number of test points the plan announced, if there was a plan.
+    pub plan: Option<u32>,
+    pub points: Vec<TestPoint>,
+    /// The reason of a `Bail out!`, if the stream bailed out.
+    pub bail_out: Option<String>,
+}
+
+/// Split a test point line after its `ok` into the number, the description and the directive.
+fn test_point(rest: &str, position: u32) -> (u32, String, Option<Directive>) {
+    let rest = rest.trim_start();
+    let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let number = rest[..digits].parse().unwrap_or(position);
+    let rest = rest[digits..].trim_start();
+    let rest = rest.strip_prefix("- ").unwrap_or(rest);
+
+    // The description ends at the first `#` that isn't escaped.
+    let mut description = String::new();
+    let mut directive = None;
+    let mut chars = rest.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '\\' => description.extend(chars.next().map(|(_, c)| c)),
+            '#' => {
+                let comment = rest[index + 1..].trim();
+                let keyword = comment.get(..4).unwrap_or_default().to_ascii_lowercase();
+                let reason = || {
+                    comment
+                        .trim_start_matches(char::is_alphabetic)
+                        .trim()
+                        .to_string()
+                };
+                directive = match keyword.as_str() {
+                    "skip" => Some(Directive::Skip(reason())),
+                    "todo" => Some(Directive::Todo(reason())),
+                    _ => None,
+                };
+                break;
+            }
+            c => description.push(c),
+        }
+    }
+    (number, description.trim().to_string(), directive)
+}
+
+/**
+Parse the TAP stream a script printed, line by line.
+
+Only the lines of the top-level stream count: indented lines, like those of subtests, and any
+other lines after a test point are its diagnostics. Lines before the first test point, other than
+the plan and a bail out, are ignored.
+*/
+pub fn parse<'a>(lines: impl IntoIterator<Item = &'a str>) -> Stream {
+    let mut stream = Stream::default();
+    for (index, line) in lines.into_iter().enumerate() {
+        let point = match line.strip_prefix("not ok") {
+            Some(rest) => Some((false, rest)),
+            None => line.strip_prefix("ok").map(|rest| (true, rest)),
+        };
+        match point {
+            Some((ok, rest)) if rest.is_empty() || rest.starts_with([' ', '\t']) => {
+                let position = stream.points.len() as u32 + 1;
+                let (number, description, directive) = test_point(rest, position);
+                stream.points.push(TestPoint {
+                    ok,
+                    number,
+                    description,
+                    directive,
+                    line: index,
+                    diagnostics: vec![],
+                });
+                continue;
+            }
+            _ => {}
+        }
+        if let Some(reason) = line.strip_prefix("Bail out!") {
+            stream.bail_out = Some(reason.trim().to_string());
+        } else if let Some(count) = line.strip_prefix("1..") {
+            let digits = count.len() - count.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            stream.plan = count[..digits].parse().ok().or(stream.plan);
+        } else if let Some(point) = stream.points.last_mut() {
+            point.diagnostics.push(line.to_string());
+        }
+    }
+    stream
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, render, Directive, Stream, TestPoint};
+    use crate::model::{Skipped, TestCase, TestSuite};
+
+    #[test]
+    fn test_render() {
+        let mut testsuite = TestSuite::from_file("tests/fixtures/compact.xml").unwrap();
+        testsuite.testcases.push(TestCase {
+            name: String::from("issue #12"),
+            skipped: Some(Skipped {
+                message: String::from("Skipped by --skip pattern: 12"),
+            }),
+            ..Default::default()
+        });
+        assert_eq!(
+            render(&testsuite),
+            "TAP version 13\n\
+             1..3\n\
+             ok 1 - ./test/im_ok.sh\n\
+             not ok 2 - ./test/bad_apple.sh\n  \
+             ---\n  \
+             message: \"Non-zero exit-code: 2\"\n  \
+             severity: fail\n  \
+             type: \"Assertion failed\"\n  \
+             duration_ms: 1000\n  \
+             output: |\n    \
+             ls: cannot access\n  \
+             ...\n\
+             ok 3 - issue \\#12 # SKIP Skipped by --skip pattern: 12\n"
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let stream = parse([
+            "TAP version 13",
+            "1..4",
+            "ok 1 - connects",
+            "not ok 2 - issue \\#12 is fixed",
+            "  ---",
+            "  message: off by one",
+            "  ...",
+            "ok 3 # SKIP no network",
+            "ok - counted # todo later",
+            "Bail out! out of disk",
+        ]);
+        let point =
+            |ok, number, description: &str, directive, line, diagnostics: &[&str]| TestPoint {
+                ok,
+                number,
+                description: description.to_string(),
+                directive,
+                line,
+                diagnostics: diagnostics.iter().map(|line| line.to_string()).collect(),
+            };
+        assert_eq!(
+            stream,
+            Stream {
+                plan: Some(4),
+                points: vec![
+                    point(true, 1, "connects", None, 2, &[]),
+                    point(
+                        false,
+                        2,
+                        "issue #12 is fixed",
+                        None,
+                        3,
+                        &["  ---", "  message: off by one", "  ..."]
+                    ),
+                    point(
+                        true,
+                        3,
+                        "",
+                        Some(Directive::Skip("no network".into())),
+                        7,
+                        &[]
+                    ),
+                    point(
+                        true,
+                        4,
+                        "counted",
+                        Some(Directive::Todo("later".into())),
+                        8,
+                        &[]
+                    ),
+                ],
+                bail_out: Some(String::from("out of disk")),
+            }
+        );
+        assert!(parse(["okay", "not okay"]).points.is_empty());
+    }
+}
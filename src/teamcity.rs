@@ -0,0 +1,111 @@
+//! Rendering of a test suite as TeamCity service messages, which TeamCity reads from the build log
+//! to show the tests of a running build.
+
+use crate::model::{Status, TestCase, TestSuite};
+
+/// Escape an attribute value of a service message.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '|' => escaped.push_str("||"),
+            '\'' => escaped.push_str("|'"),
+            '\n' => escaped.push_str("|n"),
+            '\r' => escaped.push_str("|r"),
+            '[' => escaped.push_str("|["),
+            ']' => escaped.push_str("|]"),
+            '\u{0085}' => escaped.push_str("|x"),
+            '\u{2028}' => escaped.push_str("|l"),
+            '\u{2029}' => escaped.push_str("|p"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A service message, like `##teamcity[testStarted name='im_ok']`.
+fn message(kind: &str, attributes: &[(&str, &str)]) -> String {
+    let attributes: Vec<String> = attributes
+        .iter()
+        .map(|(name, value)| format!(" {}='{}'", name, escape(value)))
+        .collect();
+    format!("##teamcity[{}{}]", kind, attributes.concat())
+}
+
+/// The message that opens the suite named `name`.
+pub fn render_suite_started(name: &str) -> String {
+    message("testSuiteStarted", &[("name", name)])
+}
+
+/// The message that closes the suite named `name`.
+pub fn render_suite_finished(name: &str) -> String {
+    message("testSuiteFinished", &[("name", name)])
+}
+
+/// The messages of a complete test case, a line each, without the last line ending: it started, how
+/// it failed or why it was skipped, and it finished, with its duration in milliseconds.
+pub fn render_case(testcase: &TestCase) -> String {
+    let name = testcase.name.as_str();
+    let mut messages = vec![message("testStarted", &[("name", name)])];
+    match testcase.status() {
+        Status::Failed | Status::Errored => {
+            let problem = testcase.error.as_ref().or(testcase.failure.as_ref());
+            let problem = problem.expect("a failed test case has a failure or an error");
+            messages.push(message(
+                "testFailed",
+                &[
+                    ("name", name),
+                    ("message", &problem.message),
+                    ("details", &problem.body),
+                ],
+            ));
+        }
+        Status::Skipped => {
+            let reason = testcase
+                .skipped
+                .as_ref()
+                .map_or("", |skipped| &skipped.message);
+            messages.push(message(
+                "testIgnored",
+                &[("name", name), ("message", reason)],
+            ));
+        }
+        Status::Passed => {}
+    }
+    let duration = format!("{:.0}", testcase.time * 1000.0);
+    messages.push(message(
+        "testFinished",
+        &[("name", name), ("duration", &duration)],
+    ));
+    messages.join("\n")
+}
+
+/// Render the whole test suite as service messages, for when they can't be written as it runs.
+pub fn render(testsuite: &TestSuite) -> String {
+    let mut lines = vec![render_suite_started(&testsuite.name)];
+    lines.extend(testsuite.testcases.iter().map(render_case));
+    lines.push(render_suite_finished(&testsuite.name));
+    lines.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod test {
+    use super::render;
+    use crate::model::TestSuite;
+
+    #[test]
+    fn test_render() {
+        let mut testsuite = TestSuite::from_file("tests/fixtures/compact.xml").unwrap();
+        testsuite.name = String::from("it's [compact]");
+        assert_eq!(
+            render(&testsuite),
+            "##teamcity[testSuiteStarted name='it|'s |[compact|]']\n\
+             ##teamcity[testStarted name='./test/im_ok.sh']\n\
+             ##teamcity[testFinished name='./test/im_ok.sh' duration='500']\n\
+             ##teamcity[testStarted name='./test/bad_apple.sh']\n\
+             ##teamcity[testFailed name='./test/bad_apple.sh' message='Non-zero exit-code: 2' details='ls: cannot access']\n\
+             ##teamcity[testFinished name='./test/bad_apple.sh' duration='1000']\n\
+             ##teamcity[testSuiteFinished name='it|'s |[compact|]']\n"
+        );
+    }
+}
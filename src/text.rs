@@ -0,0 +1,101 @@
+//! Helpers for cleaning up captured script output before it is serialized.
+
+/// Whether `c` may appear in an XML 1.0 document.
+fn is_xml_char(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\r' | '\u{20}'..='\u{D7FF}' | '\u{E000}'..='\u{FFFD}' | '\u{10000}'..)
+}
+
+/// Remove the characters that XML 1.0 cannot represent, even as character references.
+pub fn strip_invalid_xml_chars(text: &str) -> String {
+    text.chars().filter(|c| is_xml_char(*c)).collect()
+}
+
+/// Escape text for use in HTML element content or quoted attribute values.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars().filter(|c| is_xml_char(*c)) {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/**
+Remove ANSI escape sequences, like the ones used to color terminal output.
+
+Handles CSI sequences (`ESC [ ... final`), OSC sequences (`ESC ] ... BEL` or `ESC ] ... ESC \`) and
+two-character escapes. Other control characters are left alone.
+*/
+pub fn strip_ansi(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\u{1b}' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('[') => {
+                // Parameter and intermediate bytes, up to and including the final byte.
+                for c in chars.by_ref() {
+                    if ('\u{40}'..='\u{7e}').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\u{07}' {
+                        break;
+                    }
+                    if c == '\u{1b}' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::{escape_html, strip_ansi, strip_invalid_xml_chars};
+
+    #[test]
+    fn test_strip_invalid_xml_chars() {
+        assert_eq!(
+            strip_invalid_xml_chars("bell\u{07}\ttab\r\n"),
+            "bell\ttab\r\n"
+        );
+        assert_eq!(strip_invalid_xml_chars("nul\u{0}\u{FFFE}"), "nul");
+        assert_eq!(strip_invalid_xml_chars("æøå ✅"), "æøå ✅");
+    }
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(
+            escape_html("<a href=\"x\">Tom & Jerry's</a>\u{07}"),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn test_strip_ansi() {
+        assert_eq!(strip_ansi("\u{1b}[31mred\u{1b}[0m"), "red");
+        assert_eq!(strip_ansi("\u{1b}[1;32;40mbold\u{1b}[K"), "bold");
+        assert_eq!(strip_ansi("\u{1b}]0;title\u{07}text"), "text");
+        assert_eq!(strip_ansi("\u{1b}]8;;url\u{1b}\\link"), "link");
+        assert_eq!(strip_ansi("bell\u{07}"), "bell\u{07}");
+    }
+}
@@ -0,0 +1,261 @@
+/*!
+A structural check of JUnit XML reports.
+
+There is no single JUnit schema, so this checks against what the common ones (Ant, Jenkins, Maven
+Surefire) agree on: a `<testsuite>` or `<testsuites>` root, the required attributes, number and
+timestamp formats, and the elements in their schema order. The attributes shunit adds to test cases,
+`timestamp`, `file`, `line` and `assertions`, are accepted, as most consumers do, and so are the
+`<flakyFailure>` and `<flakyError>` of Surefire.
+*/
+
+use roxmltree::{Document, Node};
+
+/// The attributes and children an element may have.
+struct Rule {
+    element: &'static str,
+    required: &'static [&'static str],
+    optional: &'static [&'static str],
+    /// The children in the order they must appear in, where the elements of a group may be mixed.
+    children: &'static [&'static [&'static str]],
+}
+
+const RULES: &[Rule] = &[
+    Rule {
+        element: "testsuites",
+        required: &[],
+        optional: &["name", "tests", "failures", "errors", "skipped", "time"],
+        children: &[&["testsuite"]],
+    },
+    Rule {
+        element: "testsuite",
+        required: &["name", "tests"],
+        optional: &[
+            "failures",
+            "errors",
+            "skipped",
+            "time",
+            "timestamp",
+            "hostname",
+            "id",
+            "package",
+        ],
+        children: &[
+            &["properties"],
+            &["testcase"],
+            &["system-out"],
+            &["system-err"],
+        ],
+    },
+    Rule {
+        element: "properties",
+        required: &[],
+        optional: &[],
+        children: &[&["property"]],
+    },
+    Rule {
+        element: "property",
+        required: &["name", "value"],
+        optional: &[],
+        children: &[],
+    },
+    Rule {
+        element: "testcase",
+        required: &["name", "classname"],
+        optional: &["time", "timestamp", "assertions", "file", "line"],
+        children: &[
+            &["properties"],
+            &["skipped", "error", "failure", "flakyFailure", "flakyError"],
+            &["system-out"],
+            &["system-err"],
+        ],
+    },
+    Rule {
+        element: "failure",
+        required: &[],
+        optional: &["message", "type"],
+        children: &[],
+    },
+    Rule {
+        element: "error",
+        required: &[],
+        optional: &["message", "type"],
+        children: &[],
+    },
+    Rule {
+        element: "flakyFailure",
+        required: &[],
+        optional: &["message", "type"],
+        children: &[&["stackTrace"], &["system-out"], &["system-err"]],
+    },
+    Rule {
+        element: "flakyError",
+        required: &[],
+        optional: &["message", "type"],
+        children: &[&["stackTrace"], &["system-out"], &["system-err"]],
+    },
+    Rule {
+        element: "stackTrace",
+        required: &[],
+        optional: &[],
+        children: &[],
+    },
+    Rule {
+        element: "skipped",
+        required: &[],
+        optional: &["message"],
+        children: &[],
+    },
+    Rule {
+        element: "system-out",
+        required: &[],
+        optional: &[],
+        children: &[],
+    },
+    Rule {
+        element: "system-err",
+        required: &[],
+        optional: &[],
+        children: &[],
+    },
+];
+
+/// The elements that may appear more than once in their parent.
+const REPEATABLE: &[&str] = &[
+    "testsuite",
+    "testcase",
+    "property",
+    "failure",
+    "error",
+    "flakyFailure",
+    "flakyError",
+];
+
+/// Check that `xml` is a JUnit report that JUnit consumers accept, naming the first problem if not.
+pub fn validate_junit(xml: &str) -> anyhow::Result<()> {
+    let document = Document::parse(xml).map_err(|err| anyhow!("Invalid XML: {}", err))?;
+    let root = document.root_element();
+    let name = root.tag_name().name();
+    if name != "testsuite" && name != "testsuites" {
+        bail!(
+            "Unexpected root <{}>, expected <testsuite> or <testsuites>",
+            name
+        );
+    }
+    check(root, name)
+}
+
+/// Check `node` and its children against their rules. `path` locates it in error messages.
+fn check(node: Node, path: &str) -> anyhow::Result<()> {
+    let name = node.tag_name().name();
+    let rule = RULES
+        .iter()
+        .find(|rule| rule.element == name)
+        .ok_or_else(|| anyhow!("{}: unknown element <{}>", path, name))?;
+
+    for attribute in rule.required {
+        if node.attribute(*attribute).is_none() {
+            bail!("{}: missing attribute {}", path, attribute);
+        }
+    }
+    for attribute in node.attributes() {
+        let attribute_name = attribute.name();
+        if !rule.required.contains(&attribute_name) && !rule.optional.contains(&attribute_name) {
+            bail!("{}: unexpected attribute {}", path, attribute_name);
+        }
+        check_value(attribute_name, attribute.value())
+            .map_err(|err| anyhow!("{}: invalid {}: {}", path, attribute_name, err))?;
+    }
+
+    let mut last: Option<(usize, &str)> = None;
+    for (index, child) in node.children().filter(Node::is_element).enumerate() {
+        let child_name = child.tag_name().name();
+        let child_path = format!("{}/{}[{}]", path, child_name, index + 1);
+        let group = rule
+            .children
+            .iter()
+            .position(|group| group.contains(&child_name))
+            .ok_or_else(|| anyhow!("{}: unexpected element <{}>", path, child_name))?;
+        match last {
+            Some((last_group, last_name)) if group < last_group => {
+                bail!("{}: must come before <{}>", child_path, last_name);
+            }
+            Some((_, last_name))
+                if last_name == child_name && !REPEATABLE.contains(&child_name) =>
+            {
+                bail!("{}: <{}> may only appear once", child_path, child_name);
+            }
+            _ => {}
+        }
+        last = Some((group, child_name));
+        check(child, &child_path)?;
+    }
+    Ok(())
+}
+
+/// Check the format of an attribute value.
+fn check_value(attribute: &str, value: &str) -> anyhow::Result<()> {
+    match attribute {
+        "tests" | "failures" | "errors" | "skipped" | "assertions" | "line" => {
+            value
+                .parse::<u64>()
+                .map_err(|_| anyhow!("{:?} is no count", value))?;
+        }
+        "time" => {
+            let seconds: f64 = value
+                .parse()
+                .map_err(|_| anyhow!("{:?} is no number of seconds", value))?;
+            if !(seconds.is_finite() && seconds >= 0.0) {
+                bail!("{:?} is no number of seconds", value);
+            }
+        }
+        "timestamp" => {
+            let pattern =
+                regex::Regex::new(r"^\d{4}-\d\d-\d\dT\d\d:\d\d:\d\d(\.\d+)?(Z|[+-]\d\d:\d\d)?$")
+                    .unwrap();
+            if !pattern.is_match(value) {
+                bail!("{:?} is no ISO 8601 date and time", value);
+            }
+        }
+        "name" | "hostname" if value.is_empty() => bail!("it is empty"),
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::validate_junit;
+    use crate::{render_junit, TestSuite};
+
+    #[test]
+    fn test_validate_junit() {
+        let report = TestSuite::from_file("tests/fixtures/compact.xml").unwrap();
+        validate_junit(&render_junit(&report).unwrap()).unwrap();
+
+        let invalid = |xml: &str| validate_junit(xml).unwrap_err().to_string();
+        assert_eq!(
+            invalid(r#"<testsuite name="a" tests="1" timestamp=""/>"#),
+            r#"testsuite: invalid timestamp: "" is no ISO 8601 date and time"#
+        );
+        assert_eq!(
+            invalid(r#"<testsuite name="a"/>"#),
+            "testsuite: missing attribute tests"
+        );
+        assert_eq!(
+            invalid(r#"<report/>"#),
+            "Unexpected root <report>, expected <testsuite> or <testsuites>"
+        );
+        assert_eq!(
+            invalid(
+                r#"<testsuite name="a" tests="1"><system-out/><testcase name="b" classname="c"/></testsuite>"#
+            ),
+            "testsuite/testcase[2]: must come before <system-out>"
+        );
+        assert_eq!(
+            invalid(
+                r#"<testsuite name="a" tests="1"><testcase name="b" classname="c" time="-1"/></testsuite>"#
+            ),
+            r#"testsuite/testcase[1]: invalid time: "-1" is no number of seconds"#
+        );
+    }
+}
@@ -0,0 +1,5 @@
+#!/usr/bin/env bash
+
+echo "Checked the login"
+echo "Checked the logout"
+echo "##shunit-assertions:2"
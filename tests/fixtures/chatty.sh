@@ -0,0 +1,5 @@
+#!/usr/bin/env bash
+
+for ((i = 0; i < 1000; i++)); do
+    echo "Line $i of a lot of output"
+done
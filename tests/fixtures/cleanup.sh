@@ -0,0 +1,7 @@
+#!/usr/bin/env bash
+#
+# Cleans up and exits on SIGTERM, or only cleans up with IGNORE_TERM set.
+
+trap 'echo "Cleaning up"; [ -n "$IGNORE_TERM" ] || exit 1' TERM
+echo "Working"
+for _ in $(seq 100); do sleep 0.1; done
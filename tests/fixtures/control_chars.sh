@@ -0,0 +1,5 @@
+#!/usr/bin/env bash
+
+printf "\033[31mred\033[0m\n"
+printf "bell\a\n" >&2
+exit 1
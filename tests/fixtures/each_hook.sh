@@ -0,0 +1,4 @@
+#!/usr/bin/env bash
+
+echo "hook: ${SHUNIT_TEST_NAME} ${SHUNIT_TEST_STATUS:-starting}"
+exit "${HOOK_EXIT:-0}"
@@ -0,0 +1,5 @@
+#!/usr/bin/env bash
+
+echo "##shunit-name:"
+echo "##shunit-classname  billing"
+echo "Paid the invoice"
@@ -0,0 +1,6 @@
+#!/usr/bin/env bash
+#
+# Exits right when `--timeout 0.5` stops it, racing the kill.
+
+sleep 0.5
+echo "Done"
@@ -0,0 +1,5 @@
+#!/usr/bin/env bash
+
+echo "Checking the total"
+echo "FAIL at line 4: expected 42" >&2
+exit 1
@@ -0,0 +1,8 @@
+#!/usr/bin/env bash
+#
+# Fails until it has run $FLAKY_PASSES_AT times, counting the runs in the file $FLAKY_COUNTER.
+
+count=$(( $(cat "$FLAKY_COUNTER" 2>/dev/null || echo 0) + 1 ))
+echo "$count" > "$FLAKY_COUNTER"
+echo "Attempt $count"
+[ "$count" -ge "$FLAKY_PASSES_AT" ]
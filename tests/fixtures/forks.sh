@@ -0,0 +1,5 @@
+#!/usr/bin/env bash
+# Starts a child that keeps stdout open, so the run only ends once the child is killed too.
+echo "Starting the server"
+sleep 30 &
+wait
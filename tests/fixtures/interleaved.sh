@@ -0,0 +1,7 @@
+#!/usr/bin/env bash
+
+echo "first on out"
+sleep 0.1
+echo "second on err" >&2
+sleep 0.1
+echo "third on out"
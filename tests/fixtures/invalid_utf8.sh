@@ -0,0 +1,3 @@
+#!/usr/bin/env bash
+
+printf 'before \xff\xfe after\n'
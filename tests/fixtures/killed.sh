@@ -0,0 +1,4 @@
+#!/usr/bin/env bash
+
+echo "Going down"
+kill -KILL $$
@@ -0,0 +1,6 @@
+#!/usr/bin/env bash
+#
+# shunit-xfail: the parser drops trailing spaces, see #12
+
+echo "expected 'a ', got 'a'"
+exit 1
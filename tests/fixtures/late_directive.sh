@@ -0,0 +1,4 @@
+#!/usr/bin/env bash
+
+echo "Only the leading comments count"
+# shunit-timeout: 0.5
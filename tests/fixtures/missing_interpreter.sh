@@ -0,0 +1,3 @@
+#!/nonexistent/interpreter
+
+echo "Never runs"
@@ -0,0 +1,4 @@
+#!/usr/bin/env bash
+# Plans more test points than it prints, like a script that died halfway.
+echo "1..3"
+echo "ok 1 - connects"
@@ -0,0 +1,6 @@
+#!/usr/bin/env bash
+
+echo "args: $*"
+echo "mode: ${MODE:-none}"
+echo "cwd: $(basename "$PWD")"
+exit "${EXIT_CODE:-0}"
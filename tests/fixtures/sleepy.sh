@@ -0,0 +1,4 @@
+#!/usr/bin/env bash
+
+sleep 2
+echo "Rested"
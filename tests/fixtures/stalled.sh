@@ -0,0 +1,4 @@
+#!/usr/bin/env bash
+
+echo "Connecting"
+exec sleep 10
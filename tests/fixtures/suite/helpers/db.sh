@@ -0,0 +1,2 @@
+#!/usr/bin/env bash
+wait_for_db() { sleep 1; }
@@ -0,0 +1,12 @@
+#!/usr/bin/env bash
+# Prints TAP like a test framework would, with one failing test point.
+echo "TAP version 13"
+echo "1..4"
+echo "ok 1 - connects"
+echo "not ok 2 - reads the config"
+echo "  ---"
+echo "  message: missing key"
+echo "  ..."
+echo "ok 3 - uploads # SKIP no network"
+echo "ok 4 - cleans up"
+exit 1
@@ -0,0 +1,6 @@
+#!/usr/bin/env bash
+#
+# shunit-timeout: 0.5
+
+echo "Waiting for a reply"
+exec sleep 10
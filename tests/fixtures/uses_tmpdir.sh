@@ -0,0 +1,6 @@
+#!/usr/bin/env bash
+
+[ "$TMPDIR" = "$SHUNIT_TMPDIR" ] || exit 3
+echo "tmpdir: $TMPDIR [$(ls -A "$TMPDIR")]"
+touch "$TMPDIR/scratch"
+exit "${EXIT_CODE:-0}"
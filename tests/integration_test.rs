@@ -1,17 +1,19 @@
 use anyhow::Result;
 use assert_cmd::Command;
+use predicates::prelude::*;
+use std::io::Read;
 
 #[test]
 fn run_ok_script() -> Result<()> {
     let mut cmd = Command::cargo_bin("shunit")?;
-    cmd.args(&["./test/im_ok.sh"]).assert().success();
+    cmd.args(["./test/im_ok.sh"]).assert().success();
     Ok(())
 }
 
 #[test]
 fn run_fail_script() -> Result<()> {
     let mut cmd = Command::cargo_bin("shunit")?;
-    cmd.args(&["./test/bad_apple.sh"]).assert().failure();
+    cmd.args(["./test/bad_apple.sh"]).assert().failure();
     Ok(())
 }
 
@@ -25,3 +27,2207 @@ fn run_a_suite() -> Result<()> {
     cmd.assert().failure();
     Ok(())
 }
+
+#[test]
+fn run_with_env() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--env", "TEST_MODE=ci", "./tests/fixtures/needs_env.sh"])
+        .assert()
+        .success();
+    Ok(())
+}
+
+#[test]
+fn run_with_env_clear() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.env("TEST_MODE", "ci")
+        .args(["--env-clear", "./tests/fixtures/needs_env.sh"])
+        .assert()
+        .failure();
+    Ok(())
+}
+
+#[test]
+fn run_with_env_file() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--env-file", "./tests/fixtures/test.env"])
+        .arg("./tests/fixtures/needs_env.sh")
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--env-file", "./tests/fixtures/test.env"])
+        .args(["--env", "TEST_MODE=local", "./tests/fixtures/needs_env.sh"])
+        .assert()
+        .failure();
+
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args([
+        "--env-file",
+        "./tests/fixtures/missing.env",
+        "./test/im_ok.sh",
+    ])
+    .assert()
+    .code(4)
+    .stderr(predicates::str::contains(
+        "Failed to read ./tests/fixtures/missing.env",
+    ));
+    Ok(())
+}
+
+#[test]
+fn run_with_clean_env() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.env("TEST_MODE", "ci")
+        .env("LEAKY_VARIABLE", "leaked")
+        .args(["--clean-env", "--pass-env", "PATH,TEST_MODE"])
+        .arg("./tests/fixtures/needs_env.sh")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            r#"<property name="TEST_MODE" value="ci" />"#,
+        ))
+        .stdout(predicates::str::contains("LEAKY_VARIABLE").not())
+        .stdout(predicates::str::contains(r#"<property name="HOME""#).not());
+
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.env("TEST_MODE", "ci")
+        .args(["--clean-env", "./tests/fixtures/needs_env.sh"])
+        .assert()
+        .failure();
+
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--pass-env", "PATH", "./test/im_ok.sh"])
+        .assert()
+        .code(4)
+        .stderr(predicates::str::contains(
+            "--pass-env only applies with --clean-env",
+        ));
+    Ok(())
+}
+
+#[test]
+fn custom_suite_properties() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.env("BUILD_ID", "7")
+        .args(["--clean-env", "--pass-env", "BUILD_ID"])
+        .args(["-D", "git.sha=abc123", "--property", "BUILD_ID=8"])
+        .arg("./test/im_ok.sh")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            r#"<property name="git.sha" value="abc123" />
+    <property name="BUILD_ID" value="8" />
+  </properties>"#,
+        ))
+        .stdout(predicates::str::contains(r#"value="7""#).not());
+    Ok(())
+}
+
+#[test]
+fn reject_invalid_env() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--env", "TEST_MODE", "./test/im_ok.sh"])
+        .assert()
+        .failure();
+    Ok(())
+}
+
+#[test]
+fn list_scripts_without_running() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args([
+        "--list",
+        "--classname-strategy",
+        "relative",
+        "./test/bad_apple.sh",
+    ])
+    .assert()
+    .success()
+    .stdout("./test/bad_apple.sh\ttest/bad_apple.sh\n");
+    Ok(())
+}
+
+#[test]
+fn discover_scripts_in_directories_and_globs() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    let output = cmd
+        .args([
+            "--list",
+            "./test/bad_apple.sh",
+            "test",
+            "tests/fixtures/**/p*.sh",
+        ])
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    let names: Vec<&str> = stdout
+        .lines()
+        .map(|line| line.split('\t').next().unwrap())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "./test/bad_apple.sh",
+            "test/im_ok.sh",
+            "test/slow.sh",
+            "tests/fixtures/package/checkout/pay.sh",
+            "tests/fixtures/suite/passes.sh",
+        ]
+    );
+
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--list", "--pattern", "im_*", "test"])
+        .assert()
+        .success()
+        .stdout(predicates::str::starts_with("test/im_ok.sh\t"))
+        .stdout(predicates::str::contains("slow").not());
+    Ok(())
+}
+
+#[test]
+fn discovery_honors_shunitignore() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    let output = cmd.args(["--list", "tests/fixtures/suite"]).output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    let names: Vec<&str> = stdout
+        .lines()
+        .map(|line| line.split('\t').next().unwrap())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "tests/fixtures/suite/keep_wip.sh",
+            "tests/fixtures/suite/nested/deep.sh",
+            "tests/fixtures/suite/passes.sh",
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn abort_on_signal() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args([
+        "--abort-on-signal",
+        "./tests/fixtures/killed.sh",
+        "./test/im_ok.sh",
+    ])
+    .assert()
+    .code(2)
+    .stdout(predicates::str::contains("Killed by signal SIGKILL"))
+    .stdout(predicates::str::contains("im_ok").not());
+    Ok(())
+}
+
+#[test]
+fn exit_code_tells_errors_from_failures() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["./test/im_ok.sh", "./test/bad_apple.sh"])
+        .assert()
+        .code(1);
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["./test/typo.sh", "./test/bad_apple.sh"])
+        .assert()
+        .code(2);
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--no-such-option", "./test/im_ok.sh"])
+        .assert()
+        .code(4)
+        .stdout("");
+    Ok(())
+}
+
+#[test]
+fn warn_only_exits_with_zero() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--warn-only", "./test/typo.sh", "./test/bad_apple.sh"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(r#"failures="1""#))
+        .stderr(predicates::str::contains(
+            "test/bad_apple.sh bad_apple: Non-zero exit-code: 2",
+        ))
+        .stderr(predicates::str::contains(
+            "./test/typo.sh typo: Script not found: ./test/typo.sh",
+        ));
+    Ok(())
+}
+
+#[test]
+fn no_fail_exits_with_zero_silently() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--no-fail", "./test/bad_apple.sh"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(r#"failures="1""#))
+        .stderr(predicates::str::contains("WARN").not())
+        .stderr(predicates::str::contains("bad_apple: Non-zero exit-code").not());
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.env("SHUNIT_NO_FAIL", "1")
+        .arg("./test/bad_apple.sh")
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--no-fail", "--warn-only", "./test/bad_apple.sh"])
+        .assert()
+        .success()
+        .stderr(predicates::str::contains(
+            "test/bad_apple.sh bad_apple: Non-zero exit-code: 2",
+        ));
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--no-fail", "--quiet", "./test/bad_apple.sh"])
+        .assert()
+        .success();
+    Ok(())
+}
+
+#[test]
+fn bail_after_too_many_failures() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args([
+        "--bail-after",
+        "2",
+        "./test/bad_apple.sh",
+        "./test/im_ok.sh",
+        "./test/typo.sh",
+        "./test/im_ok.sh",
+    ])
+    .assert()
+    .code(2)
+    .stdout(predicates::str::contains(r#"tests="3""#))
+    .stderr(predicates::str::contains(
+        "ran 3 tests, 1 failures, 1 errors",
+    ))
+    .stderr(predicates::str::contains(
+        ", bailed out after 2 failures or errors",
+    ));
+    Ok(())
+}
+
+#[test]
+fn signal_is_a_failure_without_abort() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["./tests/fixtures/killed.sh", "./test/im_ok.sh"])
+        .assert()
+        .code(1)
+        .stdout(predicates::str::contains("im_ok"));
+    Ok(())
+}
+
+/// Parse `output` as XML, failing the test if it is not well-formed.
+/// Assert that `output` is a JUnit report that common JUnit consumers accept.
+fn assert_valid_xml(output: &[u8]) {
+    let output = std::str::from_utf8(output).expect("Report is not UTF-8");
+    if let Err(err) = shunit::validate::validate_junit(output) {
+        panic!("Invalid JUnit report: {}", err);
+    }
+}
+
+#[test]
+fn validate_report() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--validate", "./test/im_ok.sh", "./test/bad_apple.sh"])
+        .assert()
+        .code(1)
+        .stdout(predicates::str::contains("<testsuite "));
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args([
+        "--validate",
+        "--reemit",
+        "tests/fixtures/empty_timestamp.xml",
+    ])
+    .assert()
+    .code(2)
+    .stderr(predicates::str::contains(
+        r#"The report is not valid JUnit: testsuite: invalid hostname: it is empty"#,
+    ));
+    Ok(())
+}
+
+#[test]
+fn gzip_the_report() -> Result<()> {
+    let compressed = std::env::temp_dir().join("shunit_report.xml.gz");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.arg("-o")
+        .arg(&compressed)
+        .args(["./test/im_ok.sh", "./test/bad_apple.sh"])
+        .assert()
+        .code(1)
+        .stdout(predicates::str::contains("<testsuite").not());
+    let mut decompressed = vec![];
+    flate2::read::GzDecoder::new(std::fs::File::open(&compressed)?)
+        .read_to_end(&mut decompressed)?;
+    let report = std::env::temp_dir().join("shunit_report_decompressed.xml");
+    std::fs::write(&report, decompressed)?;
+    let testsuite = shunit::TestSuite::from_file(report.to_str().unwrap())?;
+    assert_eq!(testsuite.tests, 2);
+    assert_eq!(testsuite.failures, 1);
+
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.arg("--append")
+        .arg("-o")
+        .arg(&compressed)
+        .arg("./test/im_ok.sh")
+        .assert()
+        .code(4)
+        .stderr(predicates::str::contains(
+            "compressed reports can't be appended to",
+        ));
+    Ok(())
+}
+
+#[test]
+fn control_characters_make_valid_xml() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_control_chars.xml");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.arg("-o")
+        .arg(&report)
+        .arg("./tests/fixtures/control_chars.sh")
+        .assert()
+        .failure();
+    assert_valid_xml(&std::fs::read(&report)?);
+    Ok(())
+}
+
+#[test]
+fn strip_ansi_escapes() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_strip_ansi.xml");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--strip-ansi", "-o"])
+        .arg(&report)
+        .arg("./tests/fixtures/control_chars.sh")
+        .assert()
+        .failure();
+    let report = std::fs::read_to_string(&report)?;
+    assert_valid_xml(report.as_bytes());
+    assert!(report.contains("<system-out>red</system-out>"));
+    assert!(report.contains("<system-err>bell</system-err>"));
+    Ok(())
+}
+
+#[test]
+fn survive_invalid_utf8() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_invalid_utf8.xml");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.arg("-o")
+        .arg(&report)
+        .arg("./tests/fixtures/invalid_utf8.sh")
+        .arg("./test/im_ok.sh")
+        .assert()
+        .success();
+    let report = std::fs::read_to_string(&report)?;
+    assert_valid_xml(report.as_bytes());
+    assert!(report.contains("before \u{FFFD}\u{FFFD} after"));
+    assert!(report.contains(r#"name="im_ok""#));
+    Ok(())
+}
+
+#[test]
+fn expect_exit_code() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args([
+        "--expect-exit",
+        "2",
+        "./test/bad_apple.sh",
+        "./test/im_ok.sh",
+    ])
+    .assert()
+    .success();
+    Ok(())
+}
+
+#[test]
+fn expect_failure() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--expect-failure", "./test/bad_apple.sh"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--expect-failure", "./test/im_ok.sh"])
+        .assert()
+        .failure()
+        .stdout(predicates::str::contains(
+            "Expected failure, but got exit-code: 0",
+        ));
+    Ok(())
+}
+
+#[test]
+fn merge_xml_arguments() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["./test/im_ok.sh", "./tests/fixtures/merge_b.xml"])
+        .assert()
+        .failure()
+        .stdout(predicates::str::contains(r#"tests="2""#))
+        .stdout(predicates::str::contains(r#"name="fails""#))
+        .stdout(predicates::str::contains(
+            r#"<failure message="Non-zero exit-code: 1" type="Assertion failed">"#,
+        ));
+    Ok(())
+}
+
+#[test]
+fn sort_test_cases_by_name() -> Result<()> {
+    let output = Command::cargo_bin("shunit")?
+        .args([
+            "--sort",
+            "name",
+            "./test/im_ok.sh",
+            "./tests/fixtures/assertions.sh",
+        ])
+        .output()?;
+    let report = String::from_utf8(output.stdout)?;
+    let position = |name: &str| report.find(&format!(r#"name="{}""#, name)).unwrap();
+    assert!(position("assertions") < position("im_ok"));
+    Ok(())
+}
+
+#[test]
+fn rename_duplicate_test_cases() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["./test/im_ok.sh", "./test/im_ok.sh"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(r#"name="im_ok" "#))
+        .stdout(predicates::str::contains(r#"name="im_ok#2" "#));
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--allow-duplicates", "./test/im_ok.sh", "./test/im_ok.sh"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("im_ok#2").not());
+    Ok(())
+}
+
+#[test]
+fn run_a_matrix_of_values() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--matrix", "MODE=a,b", "--matrix", "LEVEL=1,2"])
+        .arg("./tests/fixtures/show_args.sh")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(r#"tests="4""#))
+        .stdout(predicates::str::contains(
+            r#"name="show_args[MODE=a,LEVEL=1]""#,
+        ))
+        .stdout(predicates::str::contains(
+            r#"name="show_args[MODE=b,LEVEL=2]""#,
+        ))
+        .stdout(predicates::str::contains("mode: b"));
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--matrix", "MODE=C|en_US.UTF-8,C.UTF-8"])
+        .arg("./tests/fixtures/show_args.sh")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(r#"tests="2""#))
+        .stdout(predicates::str::contains("mode: en_US.UTF-8,C.UTF-8"));
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--matrix", "A=1,2,3,4,5,6,7,8,9,10"])
+        .args(["--matrix", "B=1,2,3,4,5,6,7,8,9,10"])
+        .args(["--matrix", "C=1,2,3,4,5,6,7,8,9,10,11"])
+        .arg("./test/im_ok.sh")
+        .assert()
+        .code(4)
+        .stderr(predicates::str::contains(
+            "The --matrix runs every script 1100 times, more than the limit of 1000",
+        ));
+    Ok(())
+}
+
+#[test]
+fn run_scripts_in_parallel() -> Result<()> {
+    let started = std::time::Instant::now();
+    let output = Command::cargo_bin("shunit")?
+        .args(["--jobs", "2", "--prefix"])
+        .args([
+            "./tests/fixtures/sleepy.sh",
+            "./tests/fixtures/sleepy.sh",
+            "./test/im_ok.sh",
+        ])
+        .output()?;
+    assert!(started.elapsed() < std::time::Duration::from_secs(4));
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("[out] ./tests/fixtures/sleepy.sh: Rested"));
+    let position = |name: &str| stdout.find(&format!(r#"name="{}""#, name)).unwrap();
+    assert!(position("sleepy") < position("sleepy#2"));
+    assert!(position("sleepy#2") < position("im_ok"));
+    assert!(stdout.contains(r#"tests="3""#));
+    Ok(())
+}
+
+#[test]
+fn exit_code_77_skips_the_script() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["./tests/fixtures/skips_itself.sh", "./test/im_ok.sh"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(r#"failures="0""#))
+        .stdout(predicates::str::contains(r#"skipped="1""#))
+        .stdout(predicates::str::contains(
+            r#"<skipped message="Skipped by the script, exit-code: 77" />"#,
+        ));
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--expect-exit", "77", "./tests/fixtures/skips_itself.sh"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(r#"skipped="0""#));
+    Ok(())
+}
+
+#[test]
+fn xfail_turns_the_result_around() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--xfail", "bad_apple"])
+        .args(["./tests/fixtures/known_bug.sh", "./test/bad_apple.sh"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(r#"failures="0""#))
+        .stdout(predicates::str::contains(
+            r#"<property name="xfail" value="Non-zero exit-code: 1" />"#,
+        ))
+        .stdout(predicates::str::contains(
+            r#"<property name="xfail" value="Non-zero exit-code: 2" />"#,
+        ));
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--xfail", "im_ok", "./test/im_ok.sh"])
+        .assert()
+        .code(1)
+        .stdout(predicates::str::contains(
+            r#"<failure message="Unexpected pass (XPASS), expected to fail: matches --xfail im_ok" type="xpass">OK!</failure>"#,
+        ));
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--xfail", r"/b.d_[a-z]+\.sh$"])
+        .args(["./test/bad_apple.sh", "./test/im_ok.sh"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(r#"failures="0""#))
+        .stdout(predicates::str::contains(r#"name="xfail""#).count(1));
+    Ok(())
+}
+
+#[test]
+fn xfail_ignores_hooks() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args([
+        "--xfail",
+        "im_ok|fixed_bug|bad_apple",
+        "--setup",
+        "./test/im_ok.sh",
+    ])
+    .args(["--before-each", "./tests/fixtures/fixed_bug.sh"])
+    .arg("./test/bad_apple.sh")
+    .assert()
+    .success()
+    .stdout(predicates::str::contains(r#"errors="0""#))
+    .stdout(predicates::str::contains(r#"failures="0""#));
+    Ok(())
+}
+
+#[test]
+fn repeat_the_whole_run() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--repeat", "3", "./test/im_ok.sh", "./test/bad_apple.sh"])
+        .assert()
+        .code(1)
+        .stdout(predicates::str::contains(r#"tests="6""#))
+        .stdout(predicates::str::contains(r#"failures="3""#))
+        .stdout(predicates::str::contains(r#"name="im_ok (run 1/3)""#))
+        .stdout(predicates::str::contains(r#"name="bad_apple (run 3/3)""#))
+        .stdout(predicates::str::contains("#2").not());
+    Ok(())
+}
+
+#[test]
+fn repeat_reports_the_flaky_scripts() -> Result<()> {
+    let counter = std::env::temp_dir().join("shunit_repeat_flaky.count");
+    let summary = std::env::temp_dir().join("shunit_repeat_flaky.json");
+    let _ = std::fs::remove_file(&counter);
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.env("FLAKY_COUNTER", &counter)
+        .env("FLAKY_PASSES_AT", "2")
+        .args(["--repeat", "3", "--format", "markdown", "--summary-json"])
+        .arg(&summary)
+        .args(["./tests/fixtures/flaky.sh", "./test/im_ok.sh"])
+        .assert()
+        .code(1)
+        .stdout(predicates::str::contains(
+            "### Flaky tests\n\n| Test | Passed | Pass rate |\n| --- | ---: | ---: |\n| flaky | 2/3 | 67% |\n",
+        ))
+        .stderr(predicates::str::contains(
+            "shunit: flaky: flaky passed 2 of 3 runs (67%)\n",
+        ))
+        .stderr(predicates::str::contains("flaky: im_ok").not());
+    let summary: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&summary)?)?;
+    assert_eq!(summary["pass_rates"][0]["name"], "flaky");
+    assert_eq!(summary["pass_rates"][0]["flaky"], true);
+    assert_eq!(summary["pass_rates"][1]["name"], "im_ok");
+    assert_eq!(summary["pass_rates"][1]["pass_rate"], 1.0);
+    assert_eq!(summary["pass_rates"][1]["flaky"], false);
+    Ok(())
+}
+
+#[test]
+fn record_queue_time() -> Result<()> {
+    let output = Command::cargo_bin("shunit")?
+        .args([
+            "--queue-time",
+            "./tests/fixtures/interleaved.sh",
+            "./test/im_ok.sh",
+        ])
+        .output()?;
+    let report = String::from_utf8(output.stdout)?;
+    let queued: Vec<f64> = regex::Regex::new(r#"name="queue_seconds" value="([\d.]+)""#)?
+        .captures_iter(&report)
+        .map(|captures| captures[1].parse().unwrap())
+        .collect();
+    assert_eq!(queued.len(), 2);
+    assert!(queued[0] < 0.2);
+    assert!(queued[1] >= 0.2);
+
+    let output = Command::cargo_bin("shunit")?
+        .args(["--queue-time", "--jobs", "2"])
+        .args(["--setup", "./tests/fixtures/interleaved.sh"])
+        .args(["./test/im_ok.sh", "./tests/fixtures/interleaved.sh"])
+        .output()?;
+    let report = String::from_utf8(output.stdout)?;
+    let queued: Vec<f64> = regex::Regex::new(r#"name="queue_seconds" value="([\d.]+)""#)?
+        .captures_iter(&report)
+        .map(|captures| captures[1].parse().unwrap())
+        .collect();
+    assert_eq!(queued.len(), 2);
+    assert!(queued.iter().all(|queued| *queued < 0.2));
+    Ok(())
+}
+
+#[test]
+fn suite_name() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--suite-name", "billing", "./test/im_ok.sh"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(r#" name="billing" "#));
+    let cwd = std::env::current_dir()?;
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.env_remove("PWD")
+        .arg("./test/im_ok.sh")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(format!(
+            r#" name="{}" "#,
+            cwd.display()
+        )));
+    Ok(())
+}
+
+#[test]
+fn testcase_start_timestamps() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.arg("./test/im_ok.sh")
+        .assert()
+        .success()
+        .stdout(predicates::str::is_match(
+            r#"<testcase [^>]* timestamp="\d{4}-\d\d-\d\dT\d\d:\d\d:\d\d\.\d{3}Z""#,
+        )?);
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--no-testcase-timestamps", "./test/im_ok.sh"])
+        .assert()
+        .success()
+        .stdout(predicates::str::is_match(r#"<testcase [^>]* timestamp="#)?.not());
+    Ok(())
+}
+
+#[test]
+fn truncate_captured_output() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_max_output.xml");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--max-output", "1024", "-o"])
+        .arg(&report)
+        .arg("./tests/fixtures/chatty.sh")
+        .assert()
+        .success();
+    let report = std::fs::read_to_string(&report)?;
+    assert!(report.contains("... output truncated at 1 KiB ..."));
+    assert!(report.matches("of a lot of output").count() < 100);
+    Ok(())
+}
+
+#[test]
+fn redact_properties() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.env("MY_TOKEN", "hunter2")
+        .env("MY_NAME", "shunit")
+        .env("API_KEYS", "abc")
+        .args(["--redact-defaults", "--redact", "API_.*", "./test/im_ok.sh"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            r#"<property name="MY_TOKEN" value="***" />"#,
+        ))
+        .stdout(predicates::str::contains(
+            r#"<property name="API_KEYS" value="***" />"#,
+        ))
+        .stdout(predicates::str::contains(
+            r#"<property name="MY_NAME" value="shunit" />"#,
+        ))
+        .stdout(predicates::str::contains("hunter2").not());
+    Ok(())
+}
+
+#[test]
+fn streamed_cases_are_renamed_and_redacted() -> Result<()> {
+    let stream = std::env::temp_dir().join("shunit_streamed_redacted.jsonl");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--category", "nightly", "--redact", "category", "-o"])
+        .arg(format!("jsonl:{}", stream.display()))
+        .args(["./test/im_ok.sh", "./test/im_ok.sh"])
+        .assert()
+        .success();
+    let stream = std::fs::read_to_string(&stream)?;
+    assert!(stream.contains(r#""name":"im_ok#2""#));
+    assert!(stream.contains(r#""value":"***""#));
+    assert!(!stream.contains("nightly"));
+    Ok(())
+}
+
+#[test]
+fn markdown_report() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_report.md");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--format", "markdown", "-o"])
+        .arg(&report)
+        .args(["./test/im_ok.sh", "./test/bad_apple.sh"])
+        .assert()
+        .failure();
+    let report = std::fs::read_to_string(&report)?;
+    assert!(report.contains("**2 tests**, 1 failures, 0 errors, 0 skipped"));
+    assert!(report.contains("| bad_apple | ❌ |"));
+    assert!(report.contains("<summary>❌ bad_apple: Non-zero exit-code: 2</summary>"));
+    Ok(())
+}
+
+#[test]
+fn non_executable_script() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.arg("./tests/fixtures/not_executable.sh")
+        .assert()
+        .failure()
+        .stdout(predicates::str::contains(
+            "not_executable.sh is not executable, try `chmod +x ./tests/fixtures/not_executable.sh`",
+        ));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn fallback_to_the_shebang() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.arg("--fallback-shebang")
+        .arg("./tests/fixtures/plain_shebang.sh")
+        .arg("./tests/fixtures/not_executable.sh")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Run by sh\n"))
+        .stdout(predicates::str::contains("I forgot chmod +x\n"))
+        .stdout(predicates::str::contains(r#"errors="0" failures="0""#));
+    Ok(())
+}
+
+#[test]
+fn skip_non_executable_script() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args([
+        "--skip-non-executable",
+        "./tests/fixtures/not_executable.sh",
+        "./test/im_ok.sh",
+    ])
+    .assert()
+    .success()
+    .stdout(predicates::str::contains(r#"skipped="1""#))
+    .stdout(predicates::str::contains("<skipped message="));
+    Ok(())
+}
+
+#[test]
+fn write_partial_report_on_ctrl_c() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_interrupted.xml");
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("shunit"))
+        .arg("-o")
+        .arg(&report)
+        .args(["./tests/fixtures/sleepy.sh", "./test/im_ok.sh"])
+        .stdout(std::process::Stdio::null())
+        .spawn()?;
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    unsafe { libc::kill(child.id() as i32, libc::SIGINT) };
+    let status = child.wait()?;
+    assert_eq!(status.code(), Some(3));
+    let report = std::fs::read_to_string(&report)?;
+    assert_valid_xml(report.as_bytes());
+    assert!(report.contains(r#"tests="1""#));
+    assert!(report.contains(r#"name="sleepy""#));
+    assert!(!report.contains(r#"name="im_ok""#));
+    Ok(())
+}
+
+#[test]
+fn filter_and_exclude_scripts() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args([
+        "--list",
+        "--filter",
+        "im_ok",
+        "--filter",
+        "bad_",
+        "--exclude",
+        "apple",
+        "./test/im_ok.sh",
+        "./test/bad_apple.sh",
+        "./test/slow.sh",
+    ])
+    .assert()
+    .success()
+    .stdout(predicates::str::starts_with("./test/im_ok.sh\t"))
+    .stdout(predicates::str::contains("bad_apple").not())
+    .stdout(predicates::str::contains("slow").not());
+
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args([
+        "--exclude",
+        "bad_apple",
+        "./test/im_ok.sh",
+        "./test/bad_apple.sh",
+    ])
+    .assert()
+    .success()
+    .stdout(predicates::str::contains(r#"tests="1""#));
+
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args([
+        "--list",
+        "--filter",
+        r"^\./test/(im|s).+\.sh$",
+        "--exclude",
+        r"_o.\.sh",
+        "./test/im_ok.sh",
+        "./test/bad_apple.sh",
+        "./test/slow.sh",
+    ])
+    .assert()
+    .success()
+    .stdout(predicates::str::starts_with("./test/slow.sh\t"))
+    .stdout(predicates::str::contains("im_ok").not())
+    .stdout(predicates::str::contains("bad_apple").not());
+    Ok(())
+}
+
+#[test]
+fn tag_test_cases_with_categories() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--category", "smoke", "--category", "fast", "./test/im_ok.sh"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "<properties>\n      <property name=\"category\" value=\"smoke\" />\n      <property name=\"category\" value=\"fast\" />\n    </properties>\n  </testcase>",
+        ));
+    Ok(())
+}
+
+#[test]
+fn failed_setup_aborts_the_suite() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_failed_setup.xml");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--setup", "./test/bad_apple.sh", "-o"])
+        .arg(&report)
+        .arg("./test/im_ok.sh")
+        .assert()
+        .failure();
+    let report = std::fs::read_to_string(&report)?;
+    assert!(report.contains(r#"errors="2""#));
+    assert!(report.contains(r#"tests="1""#));
+    assert!(report.contains("The setup ./test/bad_apple.sh failed: Non-zero exit-code: 2"));
+    assert!(report.contains(
+        r#"<error message="Not run, the setup ./test/bad_apple.sh failed" type="setup""#
+    ));
+    Ok(())
+}
+
+#[test]
+fn failed_teardown_is_a_suite_error() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_failed_teardown.xml");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--teardown", "./test/bad_apple.sh", "-o"])
+        .arg(&report)
+        .arg("./test/im_ok.sh")
+        .assert()
+        .code(2);
+    let report = std::fs::read_to_string(&report)?;
+    assert!(report.contains(r#"errors="1""#));
+    assert!(report.contains(r#"failures="0""#));
+    assert!(report.contains(r#"tests="1""#));
+    assert!(!report.contains("<error"));
+    assert!(!report.contains(r#"name="teardown""#));
+    assert!(report.contains("The teardown ./test/bad_apple.sh failed: Non-zero exit-code: 2"));
+    Ok(())
+}
+
+#[test]
+fn before_and_after_each_script() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_each_hooks.xml");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--before-each", "./tests/fixtures/each_hook.sh"])
+        .args(["--after-each", "./tests/fixtures/each_hook.sh", "-o"])
+        .arg(&report)
+        .args(["./test/im_ok.sh", "./test/bad_apple.sh"])
+        .assert()
+        .code(1);
+    let report = std::fs::read_to_string(&report)?;
+    assert!(report.contains(
+        "<system-out>hook: im_ok starting\nOK!\nhook: im_ok passed\n\
+         hook: bad_apple starting\nhook: bad_apple failed</system-out>"
+    ));
+
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--before-each", "./tests/fixtures/each_hook.sh"])
+        .args(["--env", "HOOK_EXIT=1", "./test/im_ok.sh"])
+        .assert()
+        .code(2)
+        .stdout(predicates::str::contains(
+            "The before-each ./tests/fixtures/each_hook.sh failed: Non-zero exit-code: 1",
+        ))
+        .stdout(predicates::str::contains("OK!").not());
+    Ok(())
+}
+
+#[test]
+fn fresh_temporary_directory_per_script() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_tmpdirs.xml");
+    let tmpdirs = |output: &[u8]| -> Vec<(String, String)> {
+        String::from_utf8_lossy(output)
+            .lines()
+            .filter_map(|line| line.strip_prefix("tmpdir: ")?.split_once(' '))
+            .map(|(path, contents)| (path.to_string(), contents.to_string()))
+            .collect()
+    };
+    let mut cmd = Command::cargo_bin("shunit")?;
+    let output = cmd
+        .arg("-o")
+        .arg(&report)
+        .args(["./tests/fixtures/uses_tmpdir.sh"; 2])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let dirs = tmpdirs(&output);
+    assert_eq!(dirs.len(), 2);
+    assert_ne!(dirs[0].0, dirs[1].0);
+    for (path, contents) in &dirs {
+        assert_eq!(contents, "[]");
+        assert!(!std::path::Path::new(path).exists());
+    }
+
+    let mut cmd = Command::cargo_bin("shunit")?;
+    let output = cmd
+        .args(["--keep-tmp", "--env", "EXIT_CODE=1", "-o"])
+        .arg(&report)
+        .arg("./tests/fixtures/uses_tmpdir.sh")
+        .assert()
+        .code(1)
+        .get_output()
+        .stdout
+        .clone();
+    let (path, _) = tmpdirs(&output).remove(0);
+    let report = std::fs::read_to_string(&report)?;
+    assert!(report.contains(&format!(r#"<property name="tmpdir" value="{}" />"#, path)));
+    assert!(std::path::Path::new(&path).join("scratch").exists());
+    std::fs::remove_dir_all(&path)?;
+    Ok(())
+}
+
+#[test]
+fn hooks_as_test_cases() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_hooks_as_cases.xml");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args([
+        "--setup",
+        "./test/im_ok.sh",
+        "--teardown",
+        "./test/im_ok.sh",
+    ])
+    .args(["--hooks-as-cases", "-o"])
+    .arg(&report)
+    .arg("./test/bad_apple.sh")
+    .assert()
+    .failure();
+    let report = std::fs::read_to_string(&report)?;
+    assert!(report.contains(r#"tests="3""#));
+    assert!(report.contains(r#"failures="1""#));
+    let setup = report.find(r#"name="setup""#).unwrap();
+    let teardown = report.find(r#"name="teardown""#).unwrap();
+    assert!(setup < report.find(r#"name="bad_apple""#).unwrap());
+    assert!(teardown > report.find(r#"name="bad_apple""#).unwrap());
+    Ok(())
+}
+
+#[test]
+fn timeout_directive_overrides_timeout() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_timeout.xml");
+    let started = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--timeout", "60", "-o"])
+        .arg(&report)
+        .arg("./tests/fixtures/timeout_directive.sh")
+        .assert()
+        .failure();
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    let report = std::fs::read_to_string(&report)?;
+    // The output until the script was killed shows where it got stuck.
+    assert!(report.contains(
+        r#"<error message="Script exceeded timeout of 0.5s" type="timeout">Waiting for a reply</error>"#
+    ));
+    Ok(())
+}
+
+#[test]
+fn idle_timeout_kills_a_silent_script() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_idle_timeout.xml");
+    let started = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--timeout", "60", "--idle-timeout", "0.5", "-o"])
+        .arg(&report)
+        .arg("./tests/fixtures/stalled.sh")
+        .arg("./test/im_ok.sh")
+        .assert()
+        .failure();
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    let report = std::fs::read_to_string(&report)?;
+    assert!(report.contains(r#"<error message="No output for 0.5s" type="idle-timeout">"#));
+    assert!(report.contains("Connecting"));
+    assert!(report.contains(r#"errors="1""#));
+    Ok(())
+}
+
+#[test]
+fn script_exiting_at_the_timeout_is_still_reported() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_exits_at_timeout.xml");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--timeout", "0.5", "--jobs", "8", "-o"])
+        .arg(&report)
+        .args(["./tests/fixtures/exits_at_timeout.sh"; 8])
+        .assert()
+        .stderr(predicates::str::contains("No such process").not());
+    let report = std::fs::read_to_string(&report)?;
+    assert!(report.contains(r#"tests="8""#));
+    Ok(())
+}
+
+#[test]
+fn timeout_kills_the_children_too() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_timeout_children.xml");
+    let started = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--timeout", "0.5", "-o"])
+        .arg(&report)
+        .arg("./tests/fixtures/forks.sh")
+        .assert()
+        .failure();
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    let report = std::fs::read_to_string(&report)?;
+    assert!(report.contains(
+        r#"<error message="Script exceeded timeout of 0.5s" type="timeout">Starting the server</error>"#
+    ));
+    Ok(())
+}
+
+#[test]
+fn max_total_time_reports_the_scripts_left() -> Result<()> {
+    let started = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--max-total-time", "0.5"])
+        .args(["./tests/fixtures/sleepy.sh", "./test/im_ok.sh"])
+        .assert()
+        .code(2)
+        .stdout(predicates::str::contains(
+            r#"<error message="Stopped, the run exceeded --max-total-time of 0.5s" type="max-total-time">"#,
+        ))
+        .stdout(predicates::str::contains(
+            r#"<error message="Not run, the run exceeded --max-total-time of 0.5s" type="max-total-time">"#,
+        ))
+        .stdout(predicates::str::contains(r#"tests="2""#))
+        .stdout(predicates::str::contains(r#"errors="2""#));
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    Ok(())
+}
+
+#[test]
+fn min_time_flags_fast_scripts() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--min-time", "1", "./test/im_ok.sh", "./test/bad_apple.sh"])
+        .assert()
+        .code(1)
+        .stdout(predicates::str::is_match(
+            r#"<failure message="Finished in 0\.\d{3}s, faster than the minimum of 1s" type="too-fast">"#,
+        )?)
+        .stdout(predicates::str::contains(r#"failures="2""#))
+        .stdout(predicates::str::contains("Non-zero exit-code: 2"));
+    Ok(())
+}
+
+#[test]
+fn min_time_ignores_hooks() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--min-time", "1", "--setup", "./test/im_ok.sh"])
+        .args([
+            "--before-each",
+            "./test/im_ok.sh",
+            "./tests/fixtures/sleepy.sh",
+        ])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(r#"errors="0""#))
+        .stdout(predicates::str::contains(r#"failures="0""#));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn timeout_signal_lets_scripts_clean_up() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_timeout_signal.xml");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--timeout", "0.5", "--timeout-signal", "TERM", "-o"])
+        .arg(&report)
+        .arg("./tests/fixtures/cleanup.sh")
+        .assert()
+        .failure();
+    let report = std::fs::read_to_string(&report)?;
+    assert!(report.contains(r#"message="Script exceeded timeout of 0.5s, stopped by SIGTERM""#));
+    assert!(report.contains("Working\nCleaning up"));
+
+    let report = std::env::temp_dir().join("shunit_kill_grace.xml");
+    let started = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args([
+        "--timeout",
+        "0.5",
+        "--timeout-signal",
+        "TERM",
+        "--kill-grace",
+        "0.5",
+    ])
+    .args(["--env", "IGNORE_TERM=1", "-o"])
+    .arg(&report)
+    .arg("./tests/fixtures/cleanup.sh")
+    .assert()
+    .failure();
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    let report = std::fs::read_to_string(&report)?;
+    assert!(report.contains(
+        r#"message="Script exceeded timeout of 0.5s, killed after ignoring SIGTERM for 0.5s""#
+    ));
+    Ok(())
+}
+
+#[test]
+fn write_several_output_formats() -> Result<()> {
+    let junit = std::env::temp_dir().join("shunit_outputs.xml");
+    let markdown = std::env::temp_dir().join("shunit_outputs.md");
+    let json = std::env::temp_dir().join("shunit_outputs.json");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.arg("-o")
+        .arg(&junit)
+        .arg("--output")
+        .arg(format!("markdown:{}", markdown.display()))
+        .arg("--output")
+        .arg(format!("json={}", json.display()))
+        .arg("./test/im_ok.sh")
+        .assert()
+        .success();
+    assert_valid_xml(&std::fs::read(&junit)?);
+    assert!(std::fs::read_to_string(&markdown)?.contains("| im_ok | ✅ |"));
+    let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&json)?)?;
+    assert_eq!(json["testcases"][0]["name"], "im_ok");
+    Ok(())
+}
+
+#[test]
+fn report_assertions_marker() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_assertions.xml");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.arg("-o")
+        .arg(&report)
+        .args(["./tests/fixtures/assertions.sh", "./test/im_ok.sh"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Checked the logout"))
+        .stdout(predicates::str::contains("##shunit").not());
+    let report = std::fs::read_to_string(&report)?;
+    assert!(report.contains(r#"name="assertions" time=""#));
+    assert!(report.contains(r#"assertions="2""#));
+    assert_eq!(report.matches("assertions=").count(), 1);
+    assert!(!report.contains("##shunit"));
+    Ok(())
+}
+
+#[test]
+fn group_output_per_script() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args([
+        "--group-output",
+        "./tests/fixtures/assertions.sh",
+        "./tests/fixtures/fail_at_line.sh",
+    ])
+    .assert()
+    .failure()
+    .stdout(predicates::str::contains(
+        "== ./tests/fixtures/assertions.sh ==\nChecked the login\nChecked the logout\n\
+         == ./tests/fixtures/fail_at_line.sh ==\nChecking the total\n",
+    ))
+    .stderr(predicates::str::contains("FAIL at line 4: expected 42"));
+    Ok(())
+}
+
+#[test]
+fn combine_output_in_system_out() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.arg("./tests/fixtures/interleaved.sh")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "<system-out>first on out\nthird on out</system-out>",
+        ))
+        .stdout(predicates::str::contains(
+            "<system-err>second on err</system-err>",
+        ));
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--combine-output", "./tests/fixtures/interleaved.sh"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "<system-out>first on out\nsecond on err\nthird on out</system-out>",
+        ))
+        .stdout(predicates::str::contains("<system-err></system-err>"));
+    Ok(())
+}
+
+#[test]
+fn write_summary_json() -> Result<()> {
+    let summary = std::env::temp_dir().join("shunit_summary.json");
+    let report = std::env::temp_dir().join("shunit_summary.xml");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.arg("--summary-json")
+        .arg(&summary)
+        .arg("-o")
+        .arg(&report)
+        .args(["./test/im_ok.sh", "./test/bad_apple.sh"])
+        .assert()
+        .code(1);
+    let summary: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&summary)?)?;
+    assert_eq!(summary["tests"], 2);
+    assert_eq!(summary["failures"], 1);
+    assert_eq!(summary["failing"], serde_json::json!(["bad_apple"]));
+    assert!(summary["finished"].as_str().unwrap() >= summary["started"].as_str().unwrap());
+    assert_valid_xml(&std::fs::read(&report)?);
+    Ok(())
+}
+
+#[test]
+fn name_and_classname_markers() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_identity.xml");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.arg("-o")
+        .arg(&report)
+        .args([
+            "./tests/fixtures/identity.sh",
+            "./tests/fixtures/empty_identity.sh",
+            "./test/im_ok.sh",
+        ])
+        .assert()
+        .success();
+    let report = std::fs::read_to_string(&report)?;
+    assert!(report.contains(r#"<testcase classname="billing" name="checkout flow""#));
+    assert!(report.contains(r#"fixtures/empty_identity.sh" name="empty_identity""#));
+    assert!(report.contains(r#"test/im_ok.sh" name="im_ok""#));
+    assert!(report.contains("##shunit-classname  billing"));
+    assert!(!report.contains("##shunit-name"));
+    assert!(!report.contains("##shunit-classname:"));
+    Ok(())
+}
+
+#[test]
+fn file_and_line_attributes() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_file_line.xml");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--classname-strategy", "relative", "-o"])
+        .arg(&report)
+        .args(["./tests/fixtures/fail_at_line.sh", "./test/im_ok.sh"])
+        .assert()
+        .failure();
+    let report = std::fs::read_to_string(&report)?;
+    assert!(report.contains(r#"file="tests/fixtures/fail_at_line.sh" line="4""#));
+    assert!(report.contains(r#"file="test/im_ok.sh""#));
+    assert_eq!(report.matches("line=").count(), 1);
+    Ok(())
+}
+
+#[test]
+fn fail_fast_skips_the_scripts_left() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--fail-fast", "./test/bad_apple.sh", "./test/im_ok.sh"])
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains(r#"tests="2""#))
+        .stdout(predicate::str::contains(r#"skipped="1""#))
+        .stdout(predicate::str::contains(
+            r#"<skipped message="Not run, --fail-fast stopped the run after a failure" />"#,
+        ))
+        .stdout(predicate::str::contains("OK!").not())
+        .stderr(predicate::str::contains(
+            ", bailed out after 1 failures or errors",
+        ));
+    Ok(())
+}
+
+#[test]
+fn max_failures_tolerates_that_many() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args([
+        "--max-failures",
+        "1",
+        "./test/im_ok.sh",
+        "./test/bad_apple.sh",
+    ])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains(r#"failures="1""#))
+    .stderr(predicate::str::contains("1 failures, 0 errors in"))
+    .stderr(predicate::str::contains(", within --max-failures of 1\n"));
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args([
+        "--max-failures",
+        "1",
+        "--quiet",
+        "./test/bad_apple.sh",
+        "./test/typo.sh",
+    ])
+    .assert()
+    .code(2);
+    Ok(())
+}
+
+#[test]
+fn summary_line_on_stderr() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["./test/bad_apple.sh", "./test/im_ok.sh"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::is_match(
+            r"shunit: ran 2 tests, 1 failures, 0 errors in \d+\.\d\ds\n$",
+        )?);
+
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--quiet", "./test/im_ok.sh"])
+        .assert()
+        .success()
+        .stderr(predicates::str::contains("shunit: ran").not());
+    Ok(())
+}
+
+#[test]
+fn prefix_forwarded_output() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_prefix.xml");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--prefix", "-o"])
+        .arg(&report)
+        .arg("./tests/fixtures/fail_at_line.sh")
+        .assert()
+        .failure()
+        .stdout(predicates::str::is_match(
+            r"^\d{4}-\d\d-\d\dT\d\d:\d\d:\d\d\.\d{3}Z \[out\] Checking the total\n$",
+        )?)
+        .stderr(predicates::str::contains("Z [err] FAIL at line 4"));
+    let report = std::fs::read_to_string(&report)?;
+    assert!(report.contains("<system-out>Checking the total</system-out>"));
+    Ok(())
+}
+
+#[test]
+fn color_forwarded_stderr() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_color.xml");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--color", "always", "-o"])
+        .arg(&report)
+        .arg("./tests/fixtures/fail_at_line.sh")
+        .assert()
+        .failure()
+        .stdout("Checking the total\n")
+        .stderr(predicates::str::contains(
+            "\u{1b}[31mFAIL at line 4: expected 42\u{1b}[0m",
+        ));
+    let report = std::fs::read_to_string(&report)?;
+    assert!(!report.contains('\u{1b}'));
+    Ok(())
+}
+
+#[test]
+fn run_tests_from_manifest() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_manifest.xml");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--manifest", "tests/fixtures/manifest.toml", "-o"])
+        .arg(&report)
+        .arg("./test/im_ok.sh")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "args: one two\nmode: ci\ncwd: test\n",
+        ));
+    let report = std::fs::read_to_string(&report)?;
+    assert!(report.contains(r#"tests="3""#));
+    assert!(report.contains(r#"name="im_ok""#));
+    assert!(report.contains(r#"name="with_args""#));
+    assert!(report.contains(r#"name="expected_exit""#));
+    Ok(())
+}
+
+#[test]
+fn run_tests_from_yaml_manifest() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_yaml_manifest.xml");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--manifest", "tests/fixtures/manifest.yaml", "-o"])
+        .arg(&report)
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "args: one two\nmode: ci\ncwd: test\n",
+        ));
+    let report = std::fs::read_to_string(&report)?;
+    assert!(report.contains(r#"tests="2""#));
+    assert!(report.contains(r#"name="with_args""#));
+    assert!(report.contains(r#"name="expected_exit""#));
+    Ok(())
+}
+
+#[test]
+fn expand_variables_in_manifest() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--env", "FIXTURES=tests/fixtures"])
+        .args(["--manifest", "tests/fixtures/variables.toml"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "args: --stdin tests/fixtures/input.txt $HOME ci\n",
+        ));
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--manifest", "tests/fixtures/variables.toml"])
+        .assert()
+        .code(4)
+        .stdout("")
+        .stderr(predicates::str::contains(
+            "Undefined variable FIXTURES in `${FIXTURES}/show_args.sh`",
+        ));
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args([
+        "--allow-unset",
+        "--manifest",
+        "tests/fixtures/variables.toml",
+    ])
+    .assert()
+    .code(2)
+    .stdout(predicates::str::contains("Script not found: /show_args.sh"));
+    Ok(())
+}
+
+#[test]
+fn malformed_manifest_runs_nothing() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args([
+        "--manifest",
+        "tests/fixtures/bad_manifest.toml",
+        "./test/im_ok.sh",
+    ])
+    .assert()
+    .code(4)
+    .stdout("")
+    .stderr(predicates::str::contains("unknown field `retries`"));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn run_commands_from_the_path() -> Result<()> {
+    let path = std::env::join_paths(
+        std::iter::once(std::fs::canonicalize("test")?)
+            .chain(std::env::split_paths(&std::env::var_os("PATH").unwrap())),
+    )?;
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.env("PATH", &path)
+        .args(["im_ok.sh", "no-such-command"])
+        .assert()
+        .code(2)
+        .stdout(predicates::str::is_match(
+            r#"classname="/[^"]*/test/im_ok.sh" name="im_ok""#,
+        )?)
+        .stdout(predicates::str::contains(
+            r#"message="Command not found: no-such-command, neither a file nor on the PATH" type="NotFound""#,
+        ))
+        .stdout(predicates::str::contains(r#"errors="1""#));
+    Ok(())
+}
+
+#[test]
+fn missing_script_is_an_error() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_missing.xml");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.arg("-o")
+        .arg(&report)
+        .args(["./test/typo.sh", "./test/im_ok.sh"])
+        .assert()
+        .failure();
+    let report = std::fs::read_to_string(&report)?;
+    assert!(report.contains(r#"tests="2""#));
+    assert!(report.contains(r#"errors="1""#));
+    assert!(report.contains(r#"classname="./test/typo.sh""#));
+    assert!(
+        report.contains(r#"<error message="Script not found: ./test/typo.sh" type="NotFound">"#)
+    );
+    assert!(report.contains(r#"name="im_ok""#));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn missing_interpreter_is_classified() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.arg("./tests/fixtures/missing_interpreter.sh")
+        .assert()
+        .failure()
+        .stdout(predicates::str::contains(r#"type="NotFound""#))
+        .stdout(predicates::str::contains("No such file or directory"));
+    Ok(())
+}
+
+#[test]
+fn scripts_get_an_empty_stdin() -> Result<()> {
+    // Keep std in open, like the pipe of a CI job, which `cat` would wait on forever.
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("shunit"))
+        .arg("./tests/fixtures/reads_stdin.sh")
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .spawn()?;
+    let started = std::time::Instant::now();
+    while child.try_wait()?.is_none() {
+        if started.elapsed() > std::time::Duration::from_secs(5) {
+            child.kill()?;
+            panic!("The script blocked on std in");
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    assert!(child.wait_with_output()?.status.success());
+
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--stdin-inherit", "./tests/fixtures/reads_stdin.sh"])
+        .write_stdin("from the pipeline")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Read: from the pipeline"));
+    Ok(())
+}
+
+#[test]
+fn read_the_scripts_from_stdin() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--list", "-0", "./test/bad_apple.sh", "-"])
+        .write_stdin("./test/im_ok.sh\0./test/slow.sh\0")
+        .assert()
+        .success()
+        .stdout(predicates::str::is_match(
+            "^./test/bad_apple.sh\t.*\n./test/im_ok.sh\t.*\n./test/slow.sh\t.*\n$",
+        )?);
+
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--stdin", "./test/bad_apple.sh"])
+        .write_stdin("./test/im_ok.sh\n")
+        .assert()
+        .code(1)
+        .stdout(predicates::str::contains("tests=\"2\""));
+
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--stdin-inherit", "-"])
+        .assert()
+        .code(4)
+        .stderr(predicates::str::contains("Can't --stdin-inherit"));
+    Ok(())
+}
+
+#[test]
+fn config_file_at_the_repository_root() -> Result<()> {
+    let repo = std::env::temp_dir().join("shunit_config_repo");
+    let subdir = repo.join("tests").join("unit");
+    std::fs::create_dir_all(repo.join(".git"))?;
+    std::fs::create_dir_all(&subdir)?;
+    let script = std::env::current_dir()?.join("test/im_ok.sh");
+    std::fs::write(
+        repo.join("shunit.toml"),
+        format!("format = \"markdown\"\nscripts = [{:?}]\n", script),
+    )?;
+
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.current_dir(&subdir)
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("**1 tests**"));
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.current_dir(&subdir)
+        .args(["--format", "junit"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("<testsuite "));
+    std::fs::remove_dir_all(&repo)?;
+    Ok(())
+}
+
+#[test]
+fn options_from_environment_variables() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.env("SHUNIT_FORMAT", "markdown")
+        .env("SHUNIT_EXPECT_EXIT", "2")
+        .arg("./test/bad_apple.sh")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("**1 tests**"));
+
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.env("SHUNIT_FORMAT", "markdown")
+        .args(["--format", "tap", "./test/im_ok.sh"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("TAP version 13"));
+
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.env("SHUNIT_JOBS", "many")
+        .arg("./test/im_ok.sh")
+        .assert()
+        .code(4)
+        .stderr(predicates::str::contains("Invalid SHUNIT_JOBS"));
+
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.env("SHUNIT_TESTS_DIR", "test")
+        .args(["-v", "./test/im_ok.sh"])
+        .assert()
+        .success()
+        .stderr(predicates::str::contains(
+            "Ignoring SHUNIT_TESTS_DIR, which doesn't set any option",
+        ));
+    Ok(())
+}
+
+#[test]
+fn pass_arguments_after_double_dash() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args([
+        "--manifest",
+        "tests/fixtures/manifest.toml",
+        "./tests/fixtures/show_args.sh",
+        "--",
+        "--flag",
+        "two words",
+    ])
+    .assert()
+    .stdout(predicates::str::contains("args: --flag two words\n"))
+    .stdout(predicates::str::contains(
+        "args: one two --flag two words\n",
+    ));
+    Ok(())
+}
+
+#[test]
+fn run_once_per_parameter_set() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args([
+        "--param-file",
+        "tests/fixtures/params.txt",
+        "./tests/fixtures/show_args.sh",
+    ])
+    .assert()
+    .success()
+    .stdout(predicates::str::contains("tests=\"2\""))
+    .stdout(predicates::str::contains(
+        "name=\"show_args[--prefix /opt]\"",
+    ))
+    .stdout(predicates::str::contains(
+        "name=\"show_args[--prefix /usr/local --no-docs]\"",
+    ))
+    .stdout(predicates::str::contains(
+        "args: --prefix /usr/local --no-docs\n",
+    ));
+    Ok(())
+}
+
+#[test]
+fn append_to_junit_report() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_append.xml");
+    let _ = std::fs::remove_file(&report);
+    for script in ["./test/im_ok.sh", "./test/bad_apple.sh"] {
+        let mut cmd = Command::cargo_bin("shunit")?;
+        cmd.args(["--append", "-o"])
+            .arg(&report)
+            .arg(script)
+            .assert();
+    }
+    let xml = std::fs::read_to_string(&report)?;
+    let document = roxmltree::Document::parse(&xml)?;
+    let root = document.root_element();
+    assert_eq!(root.tag_name().name(), "testsuites");
+    assert_eq!(root.attribute("tests"), Some("2"));
+    assert_eq!(root.attribute("failures"), Some("1"));
+    assert_eq!(root.children().filter(|node| node.is_element()).count(), 2);
+
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--append", "--output", "html:/tmp/shunit_append.html"])
+        .arg("./test/im_ok.sh")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("HTML pages can't be appended to"));
+    Ok(())
+}
+
+#[test]
+fn github_annotations() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args([
+        "--github-annotations",
+        "./test/im_ok.sh",
+        "./test/bad_apple.sh",
+    ])
+    .assert()
+    .code(1)
+    .stderr(predicates::str::contains(
+        "::error file=test/bad_apple.sh,title=bad_apple::Non-zero exit-code: 2\n",
+    ))
+    .stderr(predicates::str::contains("title=im_ok").not());
+    Ok(())
+}
+
+#[test]
+fn write_summary_md() -> Result<()> {
+    let summary = std::env::temp_dir().join("shunit_summary.md");
+    for append in [false, true] {
+        let mut cmd = Command::cargo_bin("shunit")?;
+        if append {
+            cmd.arg("--append");
+        }
+        cmd.arg("--summary-md")
+            .arg(&summary)
+            .args(["./test/im_ok.sh", "./test/bad_apple.sh"])
+            .assert()
+            .code(1);
+    }
+    let summary = std::fs::read_to_string(&summary)?;
+    assert!(summary.starts_with("**2 tests**, 1 failures, 0 errors, 0 skipped in "));
+    assert!(summary.contains("| im_ok | ✅ | "));
+    assert!(summary
+        .contains(" | ls: cannot access 'i_dont_exist_oh_oh': No such file or directory |\n"));
+    assert_eq!(
+        summary
+            .matches("| Test | Status | Duration | Error |")
+            .count(),
+        2
+    );
+    Ok(())
+}
+
+#[test]
+fn html_report_besides_the_junit_one() -> Result<()> {
+    let page = std::env::temp_dir().join("shunit_html_report.html");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.arg("--html-report")
+        .arg(&page)
+        .arg("./test/im_ok.sh")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("<testsuite "));
+    let page = std::fs::read_to_string(&page)?;
+    assert!(page.starts_with("<!DOCTYPE html>"));
+    assert!(page.contains("im_ok"));
+    Ok(())
+}
+
+#[test]
+fn write_events_while_running() -> Result<()> {
+    let events = std::env::temp_dir().join("shunit_events.jsonl");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.arg("--events")
+        .arg(&events)
+        .args(["./test/im_ok.sh", "./test/bad_apple.sh"])
+        .assert()
+        .code(1);
+    let events: Vec<serde_json::Value> = std::fs::read_to_string(&events)?
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    let kinds: Vec<&str> = events
+        .iter()
+        .map(|event| event["event"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            "suite-start",
+            "test-start",
+            "test-output-line",
+            "test-end",
+            "test-start",
+            "test-output-line",
+            "test-end",
+            "suite-end"
+        ]
+    );
+    assert_eq!(events[0]["tests"], 2);
+    assert_eq!(events[2]["script"], "./test/im_ok.sh");
+    assert_eq!(events[2]["stream"], "stdout");
+    assert_eq!(events[2]["line"], "OK!");
+    assert_eq!(events[5]["stream"], "stderr");
+    assert_eq!(events[6]["status"], "failed");
+    assert_eq!(
+        events[6]["testcase"]["failure"]["message"],
+        "Non-zero exit-code: 2"
+    );
+    assert_eq!(events[7]["failures"], 1);
+    Ok(())
+}
+
+#[test]
+fn json_format() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_report.json");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--format", "json", "-o"])
+        .arg(&report)
+        .args(["./test/im_ok.sh", "./test/bad_apple.sh"])
+        .assert()
+        .code(1);
+    let report: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&report)?)?;
+    assert_eq!(report["tests"], 2);
+    assert_eq!(report["failures"], 1);
+    assert_eq!(report["testcases"][0]["name"], "im_ok");
+    assert_eq!(
+        report["testcases"][1]["failure"]["message"],
+        "Non-zero exit-code: 2"
+    );
+    assert_eq!(report["system_out"], "OK!");
+    Ok(())
+}
+
+#[test]
+fn allure_dir() -> Result<()> {
+    let dir = std::env::temp_dir().join("shunit_allure_results");
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.arg("--allure-dir")
+        .arg(&dir)
+        .args(["./test/im_ok.sh", "./test/bad_apple.sh"])
+        .assert()
+        .code(1);
+    let mut results = vec![];
+    let mut containers = vec![];
+    for entry in std::fs::read_dir(&dir)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        if name.ends_with("-result.json") {
+            results.push(serde_json::from_str::<serde_json::Value>(
+                &std::fs::read_to_string(&path)?,
+            )?);
+        } else if name.ends_with("-container.json") {
+            containers.push(name);
+        }
+    }
+    results.sort_by_key(|result| result["name"].to_string());
+    assert_eq!(results.len(), 2);
+    assert_eq!(containers.len(), 1);
+    assert_eq!(results[0]["name"], "bad_apple");
+    assert_eq!(results[0]["status"], "failed");
+    let attachment = results[0]["attachments"][0]["source"].as_str().unwrap();
+    assert!(std::fs::read_to_string(dir.join(attachment))?.contains("No such file"));
+    assert_eq!(results[1]["status"], "passed");
+    Ok(())
+}
+
+#[test]
+fn ctrf_format() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    let output = cmd
+        .args(["--format", "ctrf", "--quiet"])
+        .args(["./test/im_ok.sh", "./test/bad_apple.sh"])
+        .assert()
+        .code(1)
+        .get_output()
+        .stdout
+        .clone();
+    let report: serde_json::Value = serde_json::from_slice(&output)?;
+    let results = &report["results"];
+    assert_eq!(results["tool"]["name"], "shunit");
+    assert_eq!(results["summary"]["tests"], 2);
+    assert_eq!(results["summary"]["failed"], 1);
+    assert_eq!(results["tests"][0]["name"], "im_ok");
+    assert_eq!(results["tests"][0]["status"], "passed");
+    assert_eq!(results["tests"][1]["message"], "Non-zero exit-code: 2");
+    Ok(())
+}
+
+#[test]
+fn sonar_format() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--format", "sonar", "--quiet"])
+        .args(["./test/im_ok.sh", "./test/bad_apple.sh"])
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains(
+            "test/im_ok.sh\">\n    <testCase name=\"im_ok\" duration=",
+        ))
+        .stdout(predicate::str::contains("test/bad_apple.sh\">"))
+        .stdout(predicate::str::contains(
+            "<failure message=\"Non-zero exit-code: 2\">",
+        ));
+    Ok(())
+}
+
+#[test]
+fn teamcity_format() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--format", "teamcity", "--quiet", "--suite-name", "it's"])
+        .args(["./test/im_ok.sh", "./test/bad_apple.sh"])
+        .assert()
+        .code(1)
+        .stdout(predicate::str::starts_with(
+            "##teamcity[testSuiteStarted name='it|'s']\n\
+             ##teamcity[testStarted name='im_ok']\n\
+             ##teamcity[testFinished name='im_ok' duration=",
+        ))
+        .stdout(predicate::str::contains(
+            "##teamcity[testFailed name='bad_apple' message='Non-zero exit-code: 2' details=",
+        ))
+        .stdout(predicate::str::ends_with(
+            "##teamcity[testSuiteFinished name='it|'s']\n",
+        ));
+    Ok(())
+}
+
+#[test]
+fn parse_tap_into_test_cases() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--parse-tap", "--quiet"])
+        .args(["./tests/fixtures/tap.sh", "./tests/fixtures/short_tap.sh"])
+        .assert()
+        .code(2)
+        .stdout(predicates::str::contains(r#"name="connects""#).count(2))
+        .stdout(predicates::str::contains(
+            "<failure message=\"not ok 2\" type=\"tap\">  ---\n  message: missing key\n  ...</failure>",
+        ))
+        .stdout(predicates::str::contains(r#"<skipped message="no network" />"#))
+        .stdout(predicates::str::contains(r#"name="cleans up""#))
+        // The failing test point explains the exit code of tap.sh, but short_tap.sh died halfway.
+        .stdout(predicates::str::contains(r#"name="tap""#).not())
+        .stdout(predicates::str::contains(
+            r#"<error message="Planned 3 tests, but ran 1" type="tap">"#,
+        ))
+        .stdout(predicates::str::contains(r#"tests="6""#));
+    Ok(())
+}
+
+#[test]
+fn tap_format() -> Result<()> {
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--format", "tap", "--quiet"])
+        .args(["./test/im_ok.sh", "./test/bad_apple.sh"])
+        .assert()
+        .code(1)
+        .stdout(predicates::str::starts_with(
+            "TAP version 13\n1..2\nok 1 - im_ok\nnot ok 2 - bad_apple\n  ---\n",
+        ))
+        .stdout(predicates::str::contains(
+            "  output: |\n    ls: cannot access 'i_dont_exist_oh_oh': No such file or directory\n  ...\n",
+        ));
+    Ok(())
+}
+
+/// What a terminal shows of a run of shunit with `args`, through the `script` program, so that std
+/// err is a terminal.
+#[cfg(target_os = "linux")]
+fn on_terminal(args: &str) -> Result<String> {
+    let shunit = assert_cmd::cargo::cargo_bin("shunit");
+    let command = format!("{} {}", shunit.display(), args);
+    let output = std::process::Command::new("script")
+        .args(["-qec", &command, "/dev/null"])
+        .output()?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn progress_bar_on_a_terminal() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_progress.xml");
+    let scripts = "./tests/fixtures/sleepy.sh ./tests/fixtures/sleepy.sh";
+    let shown = on_terminal(&format!("-j 2 -o {} {}", report.display(), scripts))?;
+    assert!(shown.contains("0/2 2 running: ./tests/fixtures/sleepy.sh, ./tests/fixtures/sleepy.sh"));
+    let shown = on_terminal(&format!("-j 2 --format tap {}", scripts))?;
+    assert!(shown.contains("TAP version 13"));
+    assert!(!shown.contains("running:") && !shown.contains("0/2"));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn record_resource_stats() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_resource_stats.xml");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--resource-stats", "-o"])
+        .arg(&report)
+        .arg("./test/bad_apple.sh")
+        .assert()
+        .failure();
+    let report = std::fs::read_to_string(&report)?;
+    assert!(
+        predicates::str::is_match(r#"<property name="peak_rss_bytes" value="[1-9]\d*" />"#)?
+            .eval(&report)
+    );
+    assert!(
+        predicates::str::is_match(r#"<property name="cpu_seconds" value="\d+\.\d{3}" />"#)?
+            .eval(&report)
+    );
+    assert!(report.contains(r#"<failure message="Non-zero exit-code: 2""#));
+    Ok(())
+}
+
+#[test]
+fn skip_matching_scripts() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_skip.xml");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--skip", "bad_", "-o"])
+        .arg(&report)
+        .args(["./test/bad_apple.sh", "./test/im_ok.sh"])
+        .assert()
+        .success();
+    let report = std::fs::read_to_string(&report)?;
+    assert!(report.contains(r#"tests="2""#));
+    assert!(report.contains(r#"skipped="1""#));
+    assert!(report.contains(r#"failures="0""#));
+    assert!(report.contains(r#"<skipped message="Skipped by --skip pattern: bad_" />"#));
+    assert!(!report.contains("i_dont_exist_oh_oh"));
+
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--skip", "windows_only", "./tests/fixtures/windows_only.sh"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(r#"skipped="1""#))
+        .stdout(predicates::str::contains(r#"errors="0""#));
+
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--skip", r"^\./test/[a-z]+_o.\.sh$"])
+        .args(["./test/bad_apple.sh", "./test/im_ok.sh"])
+        .assert()
+        .code(1)
+        .stdout(predicates::str::contains(r#"skipped="1""#))
+        .stdout(predicates::str::contains(r#"failures="1""#));
+    Ok(())
+}
+
+#[test]
+fn stream_json_lines() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_stream.jsonl");
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("shunit"))
+        .arg("--output")
+        .arg(format!("jsonl:{}", report.display()))
+        .args(["./test/im_ok.sh", "./tests/fixtures/sleepy.sh"])
+        .stdout(std::process::Stdio::null())
+        .spawn()?;
+    std::thread::sleep(std::time::Duration::from_millis(1000));
+    let streamed = std::fs::read_to_string(&report)?;
+    assert!(child.try_wait()?.is_none());
+    assert_eq!(streamed.lines().count(), 1);
+    assert!(child.wait()?.success());
+
+    let lines: Vec<serde_json::Value> = std::fs::read_to_string(&report)?
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<std::result::Result<_, _>>()?;
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0]["name"], "im_ok");
+    assert_eq!(lines[0]["status"], "passed");
+    assert_eq!(lines[1]["name"], "sleepy");
+    assert_eq!(lines[2]["type"], "summary");
+    assert_eq!(lines[2]["tests"], 2);
+    Ok(())
+}
+
+#[test]
+fn retry_with_backoff() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_retry.xml");
+    let counter = std::env::temp_dir().join("shunit_retry.count");
+    let _ = std::fs::remove_file(&counter);
+    let started = std::time::Instant::now();
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.env("FLAKY_COUNTER", &counter)
+        .env("FLAKY_PASSES_AT", "3")
+        .args([
+            "--retries",
+            "3",
+            "--validate",
+            "--retry-delay",
+            "200",
+            "--retry-backoff",
+            "2",
+        ])
+        .arg("-o")
+        .arg(&report)
+        .arg("./tests/fixtures/flaky.sh")
+        .assert()
+        .success();
+    assert!(started.elapsed() >= std::time::Duration::from_millis(600));
+    let report = std::fs::read_to_string(&report)?;
+    assert!(report.contains(r#"<property name="attempts" value="3" />"#));
+    assert!(report.contains("Attempt 1\nAttempt 2\nAttempt 3"));
+    assert_eq!(
+        report
+            .matches(r#"<flakyFailure message="Non-zero exit-code: 1" type="Assertion failed">"#)
+            .count(),
+        2
+    );
+    assert!(
+        report.contains("<system-out>Attempt 2</system-out>\n    </flakyFailure>\n  </testcase>")
+    );
+    let time = report.split(r#"name="flaky" time=""#).nth(1).unwrap();
+    let time: f32 = time[..time.find('"').unwrap()].parse()?;
+    assert!(time < 0.6, "the waits are not part of the time: {}", time);
+    Ok(())
+}
+
+#[test]
+fn quiet_still_writes_the_report() -> Result<()> {
+    let report = std::env::temp_dir().join("shunit_quiet.xml");
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.args(["--quiet", "-o"])
+        .arg(&report)
+        .arg("./tests/fixtures/fail_at_line.sh")
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr("");
+    assert_valid_xml(&std::fs::read(&report)?);
+
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.arg("--quiet")
+        .arg("./test/im_ok.sh")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("<testsuite "))
+        .stderr("");
+
+    let mut cmd = Command::cargo_bin("shunit")?;
+    cmd.arg("--silent")
+        .arg("./test/im_ok.sh")
+        .assert()
+        .success()
+        .stdout("")
+        .stderr("");
+    Ok(())
+}